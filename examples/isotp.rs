@@ -1,6 +1,7 @@
 //! This example queries a vehicle's VIN using ISO-TP.
 
 use j2534::{ConfigId, PassThruMsg, Protocol, RxStatus, TxFlags};
+use std::time::Duration;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Get a list of interfaces
@@ -13,7 +14,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     println!("Opening interface '{}'", device.name);
-    let i = j2534::Interface::new(&device.path)?;
+    let i = j2534::Interface::from_driver(&device)?;
     // Open any connected device
     let d = i.open_any()?;
     // Get version information
@@ -40,12 +41,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Send VIN request
     channel.write(
         &mut [PassThruMsg::new_isotp(0x7E0, &[0x09, 0x02]).tx_flags(TxFlags::ISO15765_FRAME_PAD)],
-        1000,
+        Duration::from_secs(1),
     )?;
 
     // Receive response
     loop {
-        let message = channel.read_once(1000)?;
+        let message = channel.read_once(Duration::from_secs(1))?;
         if message.transmitted() || message.first_frame() {
             // Message has not been fully processed yet..
             continue;
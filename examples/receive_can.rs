@@ -1,4 +1,5 @@
 use j2534::{PassThruMsg, Protocol, RxStatus, TxFlags};
+use std::time::Duration;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Get a list of interfaces
@@ -11,7 +12,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     println!("Opening interface '{}'", device.name);
-    let i = j2534::Interface::new(&device.path)?;
+    let i = j2534::Interface::from_driver(&device)?;
     // Open any connected device
     let d = i.open_any()?;
     // Get version information
@@ -30,7 +31,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut messages = [PassThruMsg::new(Protocol::CAN); 32];
     // Read up to 32 messages
-    let count = channel.read(&mut messages, 1000)?;
+    let count = channel.read(&mut messages, Duration::from_secs(1))?;
     for msg in &messages[..count] {
         if let Some((id, data)) = msg.can_message() {
             println!("{:X}: {:X?}", id, data);
@@ -1,4 +1,5 @@
 use j2534::{PassThruMsg, RxStatus, TxFlags};
+use std::time::Duration;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Get a list of interfaces
@@ -11,7 +12,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     println!("Opening interface '{}'", device.name);
-    let i = j2534::Interface::new(&device.path)?;
+    let i = j2534::Interface::from_driver(&device)?;
     // Open any connected device
     let d = i.open_any()?;
     // Get version information
@@ -24,7 +25,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .unwrap();
 
     let message = PassThruMsg::new_can(8, &[0, 1, 2, 3]);
-    while channel.write(&mut [message], 100)? == 0 {}
+    // Retry until the transmit queue accepts the message
+    loop {
+        match channel.write(&mut [message], Duration::from_millis(100)) {
+            Ok(_) => break,
+            Err(j2534::Error::Timeout) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,369 @@
+//! Analog inputs of J2534-2 devices
+
+use std::time::Duration;
+
+use crate::{Channel, ConfigId, ConnectFlags, Device, Error, PassThruMsg};
+
+/// The protocol ID of the first analog subsystem (`ANALOG_IN_1`). J2534-2 numbers the
+/// subsystems up to `ANALOG_IN_32`.
+const ANALOG_IN_1: u32 = 0xC000;
+
+/// The most inputs a subsystem can have, one per bit of `ACTIVE_CHANNELS`
+const MAX_ANALOG_INPUTS: u8 = 32;
+
+/// The number of bytes of each sample in a received message
+const SAMPLE_SIZE: usize = 4;
+
+/// How raw sample counts are converted to millivolts
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Scaling {
+    /// The number of bits of each sample
+    resolution: u32,
+    /// The voltage of the lowest count in millivolts
+    range_low: i32,
+    /// The voltage of the highest count in millivolts
+    range_high: i32,
+}
+
+impl Scaling {
+    fn millivolts(&self, raw: u32) -> i32 {
+        let max_count = (1u64 << self.resolution.min(32)) - 1;
+        let span = i64::from(self.range_high) - i64::from(self.range_low);
+        let raw = u64::from(raw).min(max_count);
+        (i64::from(self.range_low) + span * raw as i64 / max_count as i64) as i32
+    }
+}
+
+/// One sample of every active input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnalogReading {
+    /// The time the message with the reading was received in microseconds
+    pub timestamp: u32,
+    /// Each active input number with its voltage in millivolts, in ascending input order
+    pub millivolts: Vec<(u8, i32)>,
+}
+
+/// The analog inputs of a J2534-2 analog subsystem. Created with [`Device::analog_inputs`].
+///
+/// # Example
+/// ```no_run
+/// use j2534::Interface;
+/// use std::time::Duration;
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let mut analog = device.analog_inputs(1).unwrap();
+/// println!("inputs: {:?}", analog.inputs());
+///
+/// // Sample a wideband O2 controller on input 2 at 100 Hz
+/// analog.configure(&[2], 100).unwrap();
+/// for reading in analog.read(Duration::from_secs(1)).unwrap() {
+///     println!("{}: {:?} mV", reading.timestamp, reading.millivolts);
+/// }
+/// ```
+pub struct AnalogInputs<'a> {
+    channel: Channel<'a>,
+    /// The active inputs in ascending order
+    active: Vec<u8>,
+    scaling: Scaling,
+}
+
+impl<'a> Device<'a> {
+    /// Connects to analog subsystem `subsystem` (1 to 32, `ANALOG_IN_1` to `ANALOG_IN_32`) and
+    /// reads which inputs are active and how samples are scaled.
+    ///
+    /// Returns [`Error::NotSupported`] if the driver doesn't have the subsystem.
+    pub fn analog_inputs(&self, subsystem: u8) -> Result<AnalogInputs<'_>, Error> {
+        if !(1..=32).contains(&subsystem) {
+            return Err(Error::InvalidArgument(format!(
+                "analog subsystem {} is not valid, J2534-2 defines subsystems 1 to 32",
+                subsystem
+            )));
+        }
+        let protocol_id = ANALOG_IN_1 + u32::from(subsystem) - 1;
+        let channel = match self.connect_raw(protocol_id, ConnectFlags::NONE, 0) {
+            Ok(channel) => channel,
+            Err(err)
+                if matches!(
+                    err.inner(),
+                    Error::InvalidProtocolId | Error::InvalidBaudrate
+                ) =>
+            {
+                return Err(Error::NotSupported)
+            }
+            Err(err) => return Err(err),
+        };
+        let mut analog = AnalogInputs {
+            channel,
+            active: Vec::new(),
+            scaling: Scaling {
+                resolution: 0,
+                range_low: 0,
+                range_high: 0,
+            },
+        };
+        analog.refresh()?;
+        Ok(analog)
+    }
+}
+
+impl<'a> AnalogInputs<'a> {
+    /// Returns the inputs that are sampled, in ascending order. Drivers start with every input of
+    /// the subsystem active, so before [`AnalogInputs::configure`] this lists the available inputs.
+    pub fn inputs(&self) -> &[u8] {
+        &self.active
+    }
+
+    /// Returns the number of bits of each sample
+    pub fn resolution(&self) -> u32 {
+        self.scaling.resolution
+    }
+
+    /// Returns the lowest and highest voltage the inputs can measure in millivolts
+    pub fn input_range(&self) -> (i32, i32) {
+        (self.scaling.range_low, self.scaling.range_high)
+    }
+
+    /// Returns the underlying channel, for example to set parameters like `AVERAGING_METHOD`
+    pub fn channel(&self) -> &Channel<'a> {
+        &self.channel
+    }
+
+    /// Samples `inputs` (1 to 32) at `sample_rate` samples per second, then reads the resolution
+    /// and input range that readings are scaled with.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if an input is out of range,
+    /// and [`Error::NotSupported`] if the driver doesn't support the parameters.
+    pub fn configure(&mut self, inputs: &[u8], sample_rate: u32) -> Result<(), Error> {
+        let mut mask = 0u32;
+        for &input in inputs {
+            if !(1..=MAX_ANALOG_INPUTS).contains(&input) {
+                return Err(Error::InvalidArgument(format!(
+                    "analog input {} is not valid, J2534-2 defines inputs 1 to {}",
+                    input, MAX_ANALOG_INPUTS
+                )));
+            }
+            mask |= 1 << (input - 1);
+        }
+        self.channel
+            .set_configs(&[
+                (ConfigId::ACTIVE_CHANNELS, mask),
+                (ConfigId::SAMPLE_RATE, sample_rate),
+            ])
+            .map_err(not_supported)?;
+        self.refresh()
+    }
+
+    /// Reads the samples received before the timeout expires and scales them to millivolts.
+    /// A message can hold several readings, which are returned oldest first.
+    ///
+    /// Returns [`Error::InvalidMessage`] if a message doesn't hold a whole number of readings.
+    pub fn read(&self, timeout: Duration) -> Result<Vec<AnalogReading>, Error> {
+        let msg = self.channel.read_once(timeout)?;
+        self.parse(&msg)
+    }
+
+    /// Splits a received message into readings of every active input
+    fn parse(&self, msg: &PassThruMsg) -> Result<Vec<AnalogReading>, Error> {
+        let reading_size = SAMPLE_SIZE * self.active.len();
+        let timestamp = msg.timestamp;
+        let data = &msg.data[..(msg.data_size as usize).min(msg.data.len())];
+        if reading_size == 0 {
+            return Err(Error::InvalidMessage);
+        }
+        let readings = data.chunks_exact(reading_size);
+        if !readings.remainder().is_empty() {
+            return Err(Error::InvalidMessage);
+        }
+        Ok(readings
+            .map(|reading| AnalogReading {
+                timestamp,
+                millivolts: self
+                    .active
+                    .iter()
+                    .zip(reading.chunks(SAMPLE_SIZE))
+                    .map(|(&input, sample)| {
+                        let raw = u32::from_be_bytes([sample[0], sample[1], sample[2], sample[3]]);
+                        (input, self.scaling.millivolts(raw))
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Reads the active inputs and the scaling from the driver
+    fn refresh(&mut self) -> Result<(), Error> {
+        let mut values = [0u32; 4];
+        let results = self.channel.get_configs(&[
+            ConfigId::ACTIVE_CHANNELS,
+            ConfigId::SAMPLE_RESOLUTION,
+            ConfigId::INPUT_RANGE_LOW,
+            ConfigId::INPUT_RANGE_HIGH,
+        ]);
+        for ((_, result), value) in results.into_iter().zip(values.iter_mut()) {
+            *value = result.map_err(not_supported)?;
+        }
+        let [mask, resolution, range_low, range_high] = values;
+        if resolution == 0 || resolution > 32 {
+            return Err(Error::InvalidIoctlValue);
+        }
+        self.active = (1..=MAX_ANALOG_INPUTS)
+            .filter(|input| mask & (1 << (input - 1)) != 0)
+            .collect();
+        // The range limits are signed millivolts
+        self.scaling = Scaling {
+            resolution,
+            range_low: range_low as i32,
+            range_high: range_high as i32,
+        };
+        Ok(())
+    }
+}
+
+/// Maps the errors of drivers without analog parameters to [`Error::NotSupported`]
+fn not_supported(err: Error) -> Error {
+    match err.inner() {
+        Error::InvalidIoctlId | Error::InvalidIoctlValue => Error::NotSupported,
+        _ => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockChannel, MockDevice};
+    use std::collections::BTreeMap;
+
+    /// Opens a mock device whose analog channels have inputs 1 and 3 active, 8-bit samples and a
+    /// range of `range_low` to `range_high` millivolts
+    fn device(range_low: i32, range_high: i32) -> &'static Device<'static> {
+        let configs: BTreeMap<u32, u32> = [
+            (ConfigId::ACTIVE_CHANNELS, 0b101),
+            (ConfigId::SAMPLE_RESOLUTION, 8),
+            (ConfigId::INPUT_RANGE_LOW, range_low as u32),
+            (ConfigId::INPUT_RANGE_HIGH, range_high as u32),
+        ]
+        .iter()
+        .map(|&(id, value)| (id as u32, value))
+        .collect();
+        mock::set_next_device(MockDevice {
+            new_channel: MockChannel {
+                configs,
+                ..MockChannel::default()
+            },
+            refused_protocols: vec![ANALOG_IN_1 + 1],
+            ..MockDevice::default()
+        });
+        mock::open()
+    }
+
+    fn samples(timestamp: u32, samples: &[u32]) -> PassThruMsg {
+        let mut msg = PassThruMsg::default();
+        for (i, sample) in samples.iter().enumerate() {
+            msg.data[i * SAMPLE_SIZE..][..SAMPLE_SIZE].copy_from_slice(&sample.to_be_bytes());
+        }
+        msg.data_size = (samples.len() * SAMPLE_SIZE) as u32;
+        msg.timestamp = timestamp;
+        msg
+    }
+
+    #[test]
+    fn scaling() {
+        let scaling = Scaling {
+            resolution: 12,
+            range_low: -5000,
+            range_high: 5000,
+        };
+        assert_eq!(scaling.millivolts(0), -5000);
+        assert_eq!(scaling.millivolts(2048), 1);
+        assert_eq!(scaling.millivolts(4095), 5000);
+        // Counts above the resolution are clamped
+        assert_eq!(scaling.millivolts(0xFFFF), 5000);
+
+        let scaling = Scaling {
+            resolution: 32,
+            range_low: 0,
+            range_high: 20000,
+        };
+        assert_eq!(scaling.millivolts(u32::MAX), 20000);
+        assert_eq!(scaling.millivolts(u32::MAX / 2), 9999);
+    }
+
+    #[test]
+    fn readings() {
+        let analog = device(-1000, 1550).analog_inputs(1).unwrap();
+        assert_eq!(analog.inputs(), [1, 3]);
+        assert_eq!(analog.resolution(), 8);
+        assert_eq!(analog.input_range(), (-1000, 1550));
+        let protocol_id = mock::channel(analog.channel().id()).protocol_id;
+        assert_eq!(protocol_id, ANALOG_IN_1);
+
+        // Two readings of both inputs
+        let id = analog.channel().id();
+        mock::push_rx(id, samples(100, &[0, 255, 100, 200]));
+        let readings = analog.read(Duration::ZERO).unwrap();
+        assert_eq!(
+            readings,
+            [
+                AnalogReading {
+                    timestamp: 100,
+                    millivolts: vec![(1, -1000), (3, 1550)],
+                },
+                AnalogReading {
+                    timestamp: 100,
+                    millivolts: vec![(1, 0), (3, 1000)],
+                },
+            ]
+        );
+
+        mock::push_rx(id, samples(200, &[0, 255, 100]));
+        assert!(matches!(
+            analog.read(Duration::ZERO),
+            Err(Error::InvalidMessage)
+        ));
+    }
+
+    #[test]
+    fn configure() {
+        let mut analog = device(0, 5000).analog_inputs(1).unwrap();
+        let id = analog.channel().id();
+        analog.configure(&[2, 4], 100).unwrap();
+        let configs = mock::channel(id).configs;
+        assert_eq!(configs[&(ConfigId::ACTIVE_CHANNELS as u32)], 0b1010);
+        assert_eq!(configs[&(ConfigId::SAMPLE_RATE as u32)], 100);
+        assert_eq!(analog.inputs(), [2, 4]);
+
+        for input in [0, 33] {
+            assert!(matches!(
+                analog.configure(&[input], 100),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert_eq!(
+            mock::channel(id).configs[&(ConfigId::SAMPLE_RATE as u32)],
+            100
+        );
+    }
+
+    #[test]
+    fn not_supported() {
+        let device = device(0, 5000);
+        // The mock refuses the second subsystem
+        assert!(matches!(device.analog_inputs(2), Err(Error::NotSupported)));
+        for subsystem in [0, 33] {
+            assert!(matches!(
+                device.analog_inputs(subsystem),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+
+        let mut analog = device.analog_inputs(1).unwrap();
+        mock::with_channel(analog.channel().id(), |channel| {
+            channel.unknown_configs.push(ConfigId::SAMPLE_RATE as u32)
+        });
+        assert!(matches!(
+            analog.configure(&[1], 100),
+            Err(Error::NotSupported)
+        ));
+    }
+}
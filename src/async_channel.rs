@@ -0,0 +1,291 @@
+//! Async support for channels, enabled with the `async` feature.
+//!
+//! PassThru calls block, so [`AsyncChannel`] runs them on a dedicated worker thread that owns the
+//! library, device and channel. Futures complete when the worker finishes the call. The futures
+//! don't depend on a specific runtime.
+
+use std::ffi::OsString;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{Channel, ConnectFlags, Error, Interface, PassThruMsg, Protocol};
+
+type Request = Box<dyn FnOnce(&Channel) + Send>;
+
+/// A channel that performs PassThru calls on a worker thread
+pub struct AsyncChannel {
+    requests: Option<mpsc::Sender<Request>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl AsyncChannel {
+    /// Loads the J2534 library at `path`, opens any connected device and creates a channel on a new
+    /// worker thread. Blocks until the channel has been created.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{AsyncChannel, ConnectFlags, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// async fn read() -> Result<(), j2534::Error> {
+    ///     let channel = AsyncChannel::connect(
+    ///         "C:\\j2534_driver.dll",
+    ///         Protocol::CAN,
+    ///         ConnectFlags::NONE,
+    ///         500000,
+    ///     )?;
+    ///     let msg = channel.read_msg(Duration::from_secs(1)).await?;
+    ///     println!("{:?}", msg);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn connect<P: Into<OsString>>(
+        path: P,
+        protocol: Protocol,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) -> Result<AsyncChannel, Error> {
+        let path = path.into();
+        AsyncChannel::spawn(
+            move || Ok(Interface::new(&path)?),
+            protocol,
+            flags,
+            baudrate,
+        )
+    }
+
+    /// Creates the channel on a new worker thread with the interface returned by `load`
+    fn spawn<L>(
+        load: L,
+        protocol: Protocol,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) -> Result<AsyncChannel, Error>
+    where
+        L: FnOnce() -> Result<Interface, Error> + Send + 'static,
+    {
+        let (requests, rx) = mpsc::channel::<Request>();
+        let (setup_tx, setup_rx) = mpsc::sync_channel(1);
+
+        let worker = thread::spawn(move || {
+            let interface = match load() {
+                Ok(interface) => interface,
+                Err(err) => {
+                    let _ = setup_tx.send(Err(err));
+                    return;
+                }
+            };
+            let result = interface.open_any().and_then(|device| {
+                let channel = device.connect(protocol, flags, baudrate)?;
+                let _ = setup_tx.send(Ok(()));
+                // Runs until the `AsyncChannel` is dropped
+                for request in rx {
+                    request(&channel);
+                }
+                Ok(())
+            });
+            if let Err(err) = result {
+                let _ = setup_tx.send(Err(err));
+            }
+        });
+
+        match setup_rx.recv() {
+            Ok(Ok(())) => Ok(AsyncChannel {
+                requests: Some(requests),
+                worker: Some(worker),
+            }),
+            Ok(Err(err)) => {
+                let _ = worker.join();
+                Err(err)
+            }
+            // The worker panicked
+            Err(_) => Err(Error::Failed),
+        }
+    }
+
+    /// Runs `f` with the channel on the worker thread. Calls are performed in the order they were made.
+    ///
+    /// Dropping the returned future does not cancel the call, but the channel remains usable and the
+    /// result is discarded.
+    pub fn run<F, T>(&self, f: F) -> Call<T>
+    where
+        F: FnOnce(&Channel) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State {
+            result: None,
+            waker: None,
+            closed: false,
+        }));
+        let completer = Completer(state.clone());
+        let request: Request = Box::new(move |channel| completer.complete(f(channel)));
+        if let Some(requests) = &self.requests {
+            // If the worker has exited, the request is dropped and the future resolves with an error
+            let _ = requests.send(request);
+        }
+        Call { state }
+    }
+
+    /// Reads a single message. See [`Channel::read_once`].
+    pub fn read_msg(&self, timeout: Duration) -> Call<PassThruMsg> {
+        self.run(move |channel| channel.read_once(timeout))
+    }
+
+    /// Writes a single message. See [`Channel::write_msg`].
+    pub fn write_msg(&self, msg: &PassThruMsg, timeout: Duration) -> Call<()> {
+        let msg = *msg;
+        self.run(move |channel| channel.write_msg(&msg, timeout))
+    }
+}
+
+impl Drop for AsyncChannel {
+    fn drop(&mut self) {
+        // Closing the request queue stops the worker once it finishes the current call, which
+        // disconnects the channel and closes the device.
+        self.requests.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct State<T> {
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Completes a [`Call`]. If dropped without completing, the call fails.
+struct Completer<T>(Arc<Mutex<State<T>>>);
+
+impl<T> Completer<T> {
+    fn complete(self, result: Result<T, Error>) {
+        self.0.lock().unwrap().result = Some(result);
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A future resolving to the result of a call made on an [`AsyncChannel`]
+pub struct Call<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Future for Call<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(result) = state.result.take() {
+            Poll::Ready(result)
+        } else if state.closed {
+            // The worker exited or panicked before completing the call
+            Poll::Ready(Err(Error::Failed))
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use std::task::Wake;
+
+    /// Polls `future` on the current thread until it completes
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(thread::Thread);
+
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    fn connect() -> AsyncChannel {
+        AsyncChannel::spawn(
+            || Ok(mock::interface()),
+            Protocol::CAN,
+            ConnectFlags::empty(),
+            500000,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn calls_run_on_the_worker() {
+        let channel = connect();
+        let id = block_on(channel.run(|channel| Ok(channel.id()))).unwrap();
+
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x01, 0x00]);
+        block_on(channel.write_msg(&msg, Duration::from_millis(10))).unwrap();
+        assert_eq!(mock::channel(id).written.len(), 1);
+
+        mock::push_rx(id, PassThruMsg::new_can(0x7E8, &[0x41]));
+        let read = block_on(channel.read_msg(Duration::from_millis(10))).unwrap();
+        assert_eq!(read.can_message().unwrap().0, 0x7E8);
+
+        // Dropping the channel disconnects it on the worker
+        drop(channel);
+        assert_eq!(mock::channel(id).disconnects, 1);
+    }
+
+    #[test]
+    fn dropped_call_keeps_the_channel_usable() {
+        let channel = connect();
+        let id = block_on(channel.run(|channel| Ok(channel.id()))).unwrap();
+
+        // A read that stays pending until the test releases it
+        let (release, released) = mpsc::channel::<()>();
+        let mut pending = Box::pin(channel.run(move |channel| {
+            released.recv().unwrap();
+            channel.read_once(Duration::ZERO)
+        }));
+        let waker = Waker::from(Arc::new(NoWake));
+        assert!(pending
+            .as_mut()
+            .poll(&mut Context::from_waker(&waker))
+            .is_pending());
+        drop(pending);
+        release.send(()).unwrap();
+
+        // Calls run in order, so the dropped read has finished once the write completes
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x01, 0x00]);
+        block_on(channel.write_msg(&msg, Duration::from_millis(10))).unwrap();
+        assert_eq!(mock::channel(id).written.len(), 1);
+        mock::push_rx(id, PassThruMsg::new_can(0x7E8, &[0x41]));
+        let read = block_on(channel.read_msg(Duration::from_millis(10))).unwrap();
+        assert_eq!(read.can_message().unwrap().0, 0x7E8);
+    }
+
+    struct NoWake;
+
+    impl Wake for NoWake {
+        fn wake(self: Arc<Self>) {}
+    }
+}
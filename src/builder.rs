@@ -0,0 +1,435 @@
+//! Open-time device checks with [`DeviceBuilder`] and connect-time channel configuration with
+//! [`ChannelBuilder`]
+
+use crate::{
+    Channel, ConfigId, ConnectFlags, Device, Error, Interface, ParsedVersion, PinPair, Protocol,
+};
+
+/// Opens a device and checks that it meets requirements before it is used. Created with
+/// [`Interface::device`].
+///
+/// # Example
+/// ```no_run
+/// use j2534::Interface;
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface
+///     .device()
+///     .name("CarDAQ-Plus 3")
+///     .require_api(">= 04.04")
+///     .require_vbatt_at_least_mv(9000)
+///     .open()
+///     .unwrap();
+/// ```
+pub struct DeviceBuilder<'a> {
+    interface: &'a Interface,
+    name: Option<String>,
+    api: Option<String>,
+    firmware: Option<String>,
+    min_vbatt: Option<u32>,
+}
+
+/// A requirement of a [`DeviceBuilder`] that the device didn't meet, listed by
+/// [`Error::RequirementNotMet`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnmetRequirement {
+    /// The requirement, like `API version >= 04.04`
+    pub requirement: String,
+    /// The value the device reported, or the error that kept it from being read
+    pub found: String,
+}
+
+/// Describes the requirements of [`Error::RequirementNotMet`]
+pub(crate) fn describe_unmet(unmet: &[UnmetRequirement]) -> String {
+    unmet
+        .iter()
+        .map(|unmet| format!("{}, found {}", unmet.requirement, unmet.found))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl Interface {
+    /// Returns a builder that opens a device
+    pub fn device(&self) -> DeviceBuilder<'_> {
+        DeviceBuilder {
+            interface: self,
+            name: None,
+            api: None,
+            firmware: None,
+            min_vbatt: None,
+        }
+    }
+}
+
+impl<'a> DeviceBuilder<'a> {
+    /// Opens the device with `name` like [`Interface::open_named`]. Defaults to any device.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Requires the driver to implement at least a J2534 API version, like `">= 04.04"`. The
+    /// `>=` is optional.
+    pub fn require_api(mut self, version: &str) -> Self {
+        self.api = Some(version.to_string());
+        self
+    }
+
+    /// Requires at least a firmware version, like `">= 2.1"`. The `>=` is optional.
+    pub fn require_firmware(mut self, version: &str) -> Self {
+        self.firmware = Some(version.to_string());
+        self
+    }
+
+    /// Requires a battery voltage of at least `millivolts` on pin 16
+    pub fn require_vbatt_at_least_mv(mut self, millivolts: u32) -> Self {
+        self.min_vbatt = Some(millivolts);
+        self
+    }
+
+    /// Opens the device, then reads the versions and the battery voltage if there are
+    /// requirements for them. If any aren't met, the device is closed and
+    /// [`Error::RequirementNotMet`] lists each of them with the value that was found. A version
+    /// or voltage that can't be read doesn't meet its requirements, and the error is listed
+    /// instead of the value; a disconnected device is reported as is.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if a version requirement
+    /// can't be parsed.
+    pub fn open(self) -> Result<Device<'a>, Error> {
+        let api = self.api.as_deref().map(parse_minimum).transpose()?;
+        let firmware = self.firmware.as_deref().map(parse_minimum).transpose()?;
+
+        // Dropping the device on error closes it
+        let device = match &self.name {
+            Some(name) => self.interface.open_named(name)?,
+            None => self.interface.open_any()?,
+        };
+        let mut unmet = Vec::new();
+        let versions = [("API version", api), ("firmware version", firmware)];
+        if versions.iter().any(|(_, required)| required.is_some()) {
+            match device.read_version() {
+                Ok(version) => {
+                    let found = [
+                        (version.api(), &version.api_version),
+                        (version.firmware(), &version.firmware_version),
+                    ];
+                    for ((what, required), (found, raw)) in versions.iter().zip(found) {
+                        let required = match required {
+                            Some(required) => required,
+                            None => continue,
+                        };
+                        // Versions that can't be parsed don't meet any requirement
+                        let met = matches!(found, Some(found) if found >= *required);
+                        if !met {
+                            unmet.push(UnmetRequirement {
+                                requirement: format!("{} >= {}", what, required.raw),
+                                found: raw.to_string_lossy().into_owned(),
+                            });
+                        }
+                    }
+                }
+                Err(err) if err.is_disconnect() => return Err(err),
+                Err(err) => {
+                    for (what, required) in &versions {
+                        if let Some(required) = required {
+                            unmet.push(UnmetRequirement {
+                                requirement: format!("{} >= {}", what, required.raw),
+                                found: format!("no version ({})", err),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(min) = self.min_vbatt {
+            let requirement = format!("battery voltage >= {} mV", min);
+            match device.read_battery_voltage() {
+                Ok(millivolts) if millivolts >= min => {}
+                Ok(millivolts) => unmet.push(UnmetRequirement {
+                    requirement,
+                    found: format!("{} mV", millivolts),
+                }),
+                Err(err) if err.is_disconnect() => return Err(err),
+                Err(err) => unmet.push(UnmetRequirement {
+                    requirement,
+                    found: format!("no reading ({})", err),
+                }),
+            }
+        }
+        if !unmet.is_empty() {
+            return Err(Error::RequirementNotMet(unmet));
+        }
+        Ok(device)
+    }
+}
+
+/// Parses a minimum version like `">= 04.04"` or `"04.04"`
+fn parse_minimum(requirement: &str) -> Result<ParsedVersion, Error> {
+    let version = requirement.trim();
+    let version = version.strip_prefix(">=").unwrap_or(version).trim();
+    match ParsedVersion::parse(version) {
+        Some(parsed) if version.starts_with(|c: char| c.is_ascii_digit()) => Ok(parsed),
+        _ => Err(Error::InvalidArgument(format!(
+            "{:?} is not a minimum version like \">= 04.04\"",
+            requirement
+        ))),
+    }
+}
+
+/// Builds a channel and configures it before it is used. Created with [`Device::channel`].
+///
+/// # Example
+/// ```no_run
+/// use j2534::{ConfigId, Interface, Protocol};
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let channel = device
+///     .channel(Protocol::ISO15765)
+///     .baud(500000)
+///     .loopback(true)
+///     .config(ConfigId::ISO15765_STMIN, 0)
+///     .open()
+///     .unwrap();
+/// ```
+pub struct ChannelBuilder<'a> {
+    device: &'a Device<'a>,
+    protocol: Protocol,
+    flags: ConnectFlags,
+    baudrate: u32,
+    pins: Option<PinPair>,
+    config: Vec<(ConfigId, u32)>,
+    pass_all_filter: bool,
+}
+
+impl<'a> Device<'a> {
+    /// Returns a builder for a channel using `protocol`
+    pub fn channel(&'a self, protocol: Protocol) -> ChannelBuilder<'a> {
+        ChannelBuilder {
+            device: self,
+            protocol,
+            flags: ConnectFlags::NONE,
+            baudrate: default_baudrate(protocol),
+            pins: None,
+            config: Vec::new(),
+            pass_all_filter: false,
+        }
+    }
+}
+
+impl<'a> ChannelBuilder<'a> {
+    /// Sets the baud rate. Defaults to the most common rate of the protocol.
+    pub fn baud(mut self, baudrate: u32) -> Self {
+        self.baudrate = baudrate;
+        self
+    }
+
+    /// Sets the connect flags. Defaults to [`ConnectFlags::NONE`].
+    pub fn flags(mut self, flags: ConnectFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Enables or disables echoing of transmitted messages
+    pub fn loopback(self, enabled: bool) -> Self {
+        self.config(ConfigId::LOOPBACK, enabled as u32)
+    }
+
+    /// Selects the J1962 connector pins of a J2534-2 pin-selected protocol. They are set right
+    /// after connecting, before the other configuration parameters. See [`Channel::set_j1962_pins`].
+    pub fn pins(mut self, pins: PinPair) -> Self {
+        self.pins = Some(pins);
+        self
+    }
+
+    /// Sets a configuration parameter after connecting. Parameters are set in the order they were added.
+    pub fn config(mut self, id: ConfigId, value: u32) -> Self {
+        self.config.push((id, value));
+        self
+    }
+
+    /// Installs a filter that passes every message after the configuration has been set, like
+    /// [`Channel::pass_all_filter`]. Not valid on ISO 15765 channels, which need flow control filters.
+    pub fn pass_all_filter(mut self) -> Self {
+        self.pass_all_filter = true;
+        self
+    }
+
+    /// Connects the channel, selects the pins, sets the configuration parameters and installs the
+    /// filter. If any
+    /// step fails, the channel is disconnected and the error is returned.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if a parameter or the filter
+    /// isn't valid for the protocol.
+    pub fn open(self) -> Result<Channel<'a>, Error> {
+        if let Some(pins) = self.pins {
+            if !self.protocol.is_pin_selected() {
+                return Err(Error::InvalidArgument(format!(
+                    "J1962 pins can't be selected for {:?} channels",
+                    self.protocol
+                )));
+            }
+            pins.check()?;
+        }
+        for &(id, _) in &self.config {
+            if !id.applies_to(self.protocol) {
+                return Err(Error::InvalidArgument(format!(
+                    "configuration parameter {:#X} is not valid for {:?} channels",
+                    id as u32, self.protocol
+                )));
+            }
+        }
+        if self.pass_all_filter && self.protocol.base() == Protocol::ISO15765 {
+            return Err(Error::InvalidArgument(
+                "pass filters are not valid on ISO15765 channels".to_string(),
+            ));
+        }
+
+        // Dropping the channel on error disconnects it
+        let channel = self
+            .device
+            .connect(self.protocol, self.flags, self.baudrate)?;
+        if let Some(pins) = self.pins {
+            channel.set_j1962_pins(pins)?;
+        }
+        for &(id, value) in &self.config {
+            channel.set_config(id, value)?;
+        }
+        if self.pass_all_filter {
+            channel.start_pass_all_filter()?;
+        }
+        Ok(channel)
+    }
+}
+
+/// The baud rate used by [`ChannelBuilder`] if none is set
+fn default_baudrate(protocol: Protocol) -> u32 {
+    match protocol {
+        Protocol::CAN | Protocol::CAN_PS | Protocol::ISO15765 | Protocol::ISO15765_PS => 500000,
+        Protocol::SW_CAN_PS => 33333,
+        Protocol::J1850PWM | Protocol::J1850PWM_PS => 41600,
+        Protocol::J1850VPW
+        | Protocol::J1850VPW_PS
+        | Protocol::ISO9141
+        | Protocol::ISO9141_PS
+        | Protocol::ISO14230
+        | Protocol::ISO14230_PS => 10400,
+        Protocol::SCI_A_ENGINE
+        | Protocol::SCI_A_TRANS
+        | Protocol::SCI_B_ENGINE
+        | Protocol::SCI_B_TRANS => 7812,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockDevice};
+    use crate::BaudRates;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn default_baudrates_are_valid() {
+        let protocols = (0..0x8010).filter_map(Protocol::from_u32);
+        for protocol in protocols {
+            let baudrate = default_baudrate(protocol);
+            assert!(BaudRates::of(protocol).contains(baudrate), "{:?}", protocol);
+            assert_eq!(baudrate, default_baudrate(protocol.base()));
+        }
+    }
+
+    #[test]
+    fn minimum_versions() {
+        let numbers = |s| {
+            let version = parse_minimum(s).unwrap();
+            (version.major, version.minor, version.patch)
+        };
+        assert_eq!(numbers(">= 04.04"), (4, 4, 0));
+        assert_eq!(numbers("04.04"), (4, 4, 0));
+        assert_eq!(numbers(" >=2.1.7 "), (2, 1, 7));
+        for s in [">=abc", "", ">=", "v1.2", "> 1.2"] {
+            assert!(
+                matches!(parse_minimum(s), Err(Error::InvalidArgument(_))),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn open_checks_requirements() {
+        let interface = mock::interface();
+        let device = interface
+            .device()
+            .require_api(">= 04.04")
+            .require_firmware("1.2")
+            .require_vbatt_at_least_mv(12000)
+            .open()
+            .unwrap();
+        drop(device);
+
+        assert!(matches!(
+            interface.device().require_api(">=abc").open(),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn open_lists_unmet_requirements() {
+        let interface = mock::interface();
+        mock::set_next_device(MockDevice {
+            vbatt: 9000,
+            versions: Some(["unknown", "1.0", "02.02"]),
+            ..MockDevice::default()
+        });
+        let unmet = match interface
+            .device()
+            .require_api(">= 04.04")
+            .require_firmware("1.0")
+            .require_vbatt_at_least_mv(11000)
+            .open()
+        {
+            Err(Error::RequirementNotMet(unmet)) => unmet,
+            other => panic!("{:?}", other.map(|device| device.id())),
+        };
+        assert_eq!(
+            unmet,
+            [
+                UnmetRequirement {
+                    requirement: "API version >= 04.04".to_string(),
+                    found: "02.02".to_string(),
+                },
+                // A firmware version without a number doesn't meet any requirement
+                UnmetRequirement {
+                    requirement: "firmware version >= 1.0".to_string(),
+                    found: "unknown".to_string(),
+                },
+                UnmetRequirement {
+                    requirement: "battery voltage >= 11000 mV".to_string(),
+                    found: "9000 mV".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            Error::RequirementNotMet(unmet[2..].to_vec()).to_string(),
+            "device does not meet the requirements: battery voltage >= 11000 mV, found 9000 mV"
+        );
+    }
+
+    #[test]
+    fn vbatt_read_error_names_requirement() {
+        let interface = mock::interface();
+        mock::set_next_device(MockDevice {
+            vbatt_error: Some(0x01),
+            ..MockDevice::default()
+        });
+        match interface.device().require_vbatt_at_least_mv(11000).open() {
+            Err(Error::RequirementNotMet(unmet)) => {
+                assert_eq!(unmet.len(), 1);
+                assert_eq!(unmet[0].requirement, "battery voltage >= 11000 mV");
+                assert!(unmet[0].found.starts_with("no reading"));
+            }
+            other => panic!("{:?}", other.map(|device| device.id())),
+        };
+    }
+}
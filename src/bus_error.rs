@@ -0,0 +1,180 @@
+//! Reporting of error indications received on the vehicle network
+
+use std::collections::VecDeque;
+
+use crate::{Channel, PassThruMsg, RxStatus};
+
+/// The most bus errors kept for [`Channel::bus_errors`]. The oldest errors are dropped first.
+const MAX_QUEUED_BUS_ERRORS: usize = 256;
+
+/// The kind of a [`BusError`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BusErrorKind {
+    /// A break was received on an SCI or K-line channel
+    Break,
+    /// An ISO 15765 frame had incorrect padding
+    PaddingError,
+}
+
+/// An error indication received on the vehicle network
+#[derive(Debug)]
+pub struct BusError {
+    pub kind: BusErrorKind,
+    /// The time the error was received in microseconds
+    pub timestamp: u32,
+    /// The message the error was reported with
+    pub raw: PassThruMsg,
+}
+
+impl BusError {
+    /// Returns the error indicated by a message's rx status, if any
+    pub fn from_msg(msg: &PassThruMsg) -> Option<BusError> {
+        Some(BusError {
+            kind: BusErrorKind::of(msg)?,
+            timestamp: msg.timestamp,
+            raw: *msg,
+        })
+    }
+}
+
+impl BusErrorKind {
+    /// Returns the kind of error indicated by a message's rx status, if any
+    pub(crate) fn of(msg: &PassThruMsg) -> Option<BusErrorKind> {
+        let rx_status = RxStatus::from_bits_truncate(msg.rx_status);
+        if rx_status.contains(RxStatus::RX_BREAK) {
+            Some(BusErrorKind::Break)
+        } else if rx_status.contains(RxStatus::ISO15765_PADDING_ERROR) {
+            Some(BusErrorKind::PaddingError)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct BusErrorState {
+    route: bool,
+    queue: VecDeque<BusError>,
+}
+
+impl<'a> Channel<'a> {
+    /// Sets whether messages with error indications are kept out of the messages returned by the
+    /// higher-level read helpers and queued for [`Channel::bus_errors`] instead. Disabled by default.
+    ///
+    /// Like [`EchoPolicy`](crate::EchoPolicy), this doesn't affect [`Channel::read`] and
+    /// [`Channel::read_msgs`], which always return every message.
+    pub fn set_route_bus_errors(&self, route: bool) {
+        self.bus_errors.lock().unwrap().route = route;
+    }
+
+    /// Returns the bus errors queued since the last call, oldest first
+    pub fn bus_errors(&self) -> Vec<BusError> {
+        self.bus_errors.lock().unwrap().queue.drain(..).collect()
+    }
+
+    /// Queues a received message if it indicates an error and routing is enabled. Returns `None`
+    /// if the message was queued.
+    fn route_bus_error(&self, msg: PassThruMsg) -> Option<PassThruMsg> {
+        let mut state = self.bus_errors.lock().unwrap();
+        if !state.route {
+            return Some(msg);
+        }
+        match BusError::from_msg(&msg) {
+            Some(err) => {
+                if state.queue.len() == MAX_QUEUED_BUS_ERRORS {
+                    state.queue.pop_front();
+                }
+                state.queue.push_back(err);
+                None
+            }
+            None => Some(msg),
+        }
+    }
+
+    /// Applies bus error routing and the echo policy to a message received by a higher-level read
+    /// helper. Returns `None` if the message shouldn't be returned.
+    pub(crate) fn filter_received(&self, msg: PassThruMsg) -> Option<PassThruMsg> {
+        self.route_bus_error(msg)
+            .and_then(|msg| self.apply_echo_policy(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, Error};
+    use std::time::Duration;
+
+    fn with_status(id: u32, rx_status: RxStatus, timestamp: u32) -> PassThruMsg {
+        let mut msg = PassThruMsg::new_can(id, &[]);
+        msg.rx_status = rx_status.bits();
+        msg.timestamp = timestamp;
+        msg
+    }
+
+    #[test]
+    fn kinds() {
+        let msg = with_status(0, RxStatus::RX_BREAK, 10);
+        let err = BusError::from_msg(&msg).unwrap();
+        assert_eq!((err.kind, err.timestamp), (BusErrorKind::Break, 10));
+        let msg = with_status(0x7E8, RxStatus::ISO15765_PADDING_ERROR, 0);
+        assert_eq!(BusErrorKind::of(&msg), Some(BusErrorKind::PaddingError));
+        let msg = with_status(0x7E8, RxStatus::TX_MSG_TYPE, 0);
+        assert!(BusError::from_msg(&msg).is_none());
+    }
+
+    #[test]
+    fn routing() {
+        let channel = mock::can_channel();
+        let id = channel.id();
+        mock::push_rx(id, with_status(0, RxStatus::RX_BREAK, 1));
+        // Not routed by default
+        let msg = channel.read_once(Duration::ZERO).unwrap();
+        let rx_status = msg.rx_status;
+        assert_eq!(rx_status, RxStatus::RX_BREAK.bits());
+
+        channel.set_route_bus_errors(true);
+        mock::push_rx(id, with_status(0, RxStatus::RX_BREAK, 2));
+        mock::push_rx(id, with_status(0x7E8, RxStatus::empty(), 3));
+        mock::push_rx(id, with_status(0x7E8, RxStatus::ISO15765_PADDING_ERROR, 4));
+        let msg = channel.read_once(Duration::ZERO).unwrap();
+        let timestamp = msg.timestamp;
+        assert_eq!(timestamp, 3);
+        assert!(matches!(
+            channel.read_once(Duration::ZERO),
+            Err(Error::BufferEmpty)
+        ));
+        let errors: Vec<_> = channel
+            .bus_errors()
+            .iter()
+            .map(|err| (err.kind, err.timestamp))
+            .collect();
+        assert_eq!(
+            errors,
+            [(BusErrorKind::Break, 2), (BusErrorKind::PaddingError, 4)]
+        );
+        assert!(channel.bus_errors().is_empty());
+
+        // Still counted in the statistics, including the unrouted one
+        assert_eq!(channel.stats().bus_errors, 3);
+
+        // The raw reads return errors
+        mock::push_rx(id, with_status(0, RxStatus::RX_BREAK, 5));
+        assert_eq!(channel.read_msgs(4, Duration::ZERO).unwrap().len(), 1);
+        assert!(channel.bus_errors().is_empty());
+    }
+
+    #[test]
+    fn queue_is_capped() {
+        let channel = mock::can_channel();
+        channel.set_route_bus_errors(true);
+        for timestamp in 0..300 {
+            mock::push_rx(channel.id(), with_status(0, RxStatus::RX_BREAK, timestamp));
+        }
+        assert_eq!(channel.try_iter().count(), 0);
+        let errors = channel.bus_errors();
+        assert_eq!(errors.len(), MAX_QUEUED_BUS_ERRORS);
+        assert_eq!(errors[0].timestamp, 300 - MAX_QUEUED_BUS_ERRORS as u32);
+        assert_eq!(errors[MAX_QUEUED_BUS_ERRORS - 1].timestamp, 299);
+    }
+}
@@ -0,0 +1,368 @@
+//! Frame-level API for raw CAN channels
+
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use crate::{
+    deadline_after, is_can_protocol, Channel, ConnectFlags, Device, Error, FilterId, IoctlId,
+    PassThruMsg, Protocol, RxStatus, TxFlags, READ_BATCH_SIZE,
+};
+
+/// The largest standard (11-bit) CAN ID
+const MAX_STANDARD_ID: u32 = 0x7FF;
+/// The largest extended (29-bit) CAN ID
+const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+/// A classic CAN frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CanFrame {
+    /// The arbitration ID
+    pub id: u32,
+    /// True if the ID is a 29-bit ID
+    pub extended: bool,
+    /// The time the frame was received in microseconds
+    pub timestamp: u32,
+    pub rx_status: RxStatus,
+    /// The payload. Only the first `dlc` bytes are used.
+    pub data: [u8; 8],
+    /// The length of the payload
+    pub dlc: u8,
+}
+
+impl CanFrame {
+    /// Returns the payload of the frame
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.dlc as usize]
+    }
+
+    /// Converts the frame to a message of `protocol`, which should be CAN or ISO 15765.
+    /// Sets [`TxFlags::CAN_29BIT_ID`] if the ID is extended.
+    pub fn to_passthru_msg(&self, protocol: Protocol) -> PassThruMsg {
+        let mut msg = PassThruMsg::new(protocol).rx_status(self.rx_status);
+        if self.extended {
+            msg = msg.tx_flags(TxFlags::CAN_29BIT_ID);
+        }
+        msg.data[..4].copy_from_slice(&self.id.to_be_bytes());
+        msg.data[4..4 + self.dlc as usize].copy_from_slice(self.data());
+        msg.data_size = 4 + self.dlc as u32;
+        msg.timestamp = self.timestamp;
+        msg
+    }
+}
+
+impl TryFrom<&PassThruMsg> for CanFrame {
+    type Error = Error;
+
+    /// Converts a CAN or ISO 15765 message with up to eight data bytes.
+    ///
+    /// Returns [`Error::MessageProtocolId`] if the message has another protocol and
+    /// [`Error::InvalidMessage`] if it is shorter than the ID or has more than eight data bytes.
+    fn try_from(msg: &PassThruMsg) -> Result<CanFrame, Error> {
+        if !is_can_protocol(msg.protocol_id) {
+            return Err(Error::MessageProtocolId);
+        }
+        let (id, payload) = msg.can_message().ok_or(Error::InvalidMessage)?;
+        if payload.len() > 8 {
+            return Err(Error::InvalidMessage);
+        }
+        let rx_status = RxStatus::from_bits_truncate(msg.rx_status);
+        let mut data = [0; 8];
+        data[..payload.len()].copy_from_slice(payload);
+        Ok(CanFrame {
+            id,
+            extended: rx_status.contains(RxStatus::CAN_29BIT_ID),
+            timestamp: msg.timestamp,
+            rx_status,
+            data,
+            dlc: payload.len() as u8,
+        })
+    }
+}
+
+/// A raw CAN channel that sends and receives [`CanFrame`]s. Created with [`Device::connect_can`].
+pub struct CanChannel<'a> {
+    channel: Channel<'a>,
+    filter: FilterId,
+}
+
+impl<'a> Device<'a> {
+    /// Creates a raw CAN channel and installs a filter that passes every frame, since drivers don't
+    /// deliver any frames until a filter exists.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let can = device.connect_can(500000, ConnectFlags::NONE).unwrap();
+    ///
+    /// can.send(0x7DF, false, &[0x02, 0x01, 0x00], Duration::from_secs(1)).unwrap();
+    /// let frame = can.recv(Duration::from_secs(1)).unwrap();
+    /// println!("{:X}: {:02X?}", frame.id, frame.data());
+    /// ```
+    pub fn connect_can(&self, baudrate: u32, flags: ConnectFlags) -> Result<CanChannel<'_>, Error> {
+        let channel = self.connect(Protocol::CAN, flags, baudrate)?;
+        let filter = channel.start_pass_all_filter()?;
+        Ok(CanChannel { channel, filter })
+    }
+}
+
+impl<'a> CanChannel<'a> {
+    /// Sends a single frame.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if `data` is longer than eight
+    /// bytes or `id` doesn't fit in an 11-bit or 29-bit ID.
+    pub fn send(
+        &self,
+        id: u32,
+        extended: bool,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        if data.len() > 8 {
+            return Err(Error::InvalidArgument(format!(
+                "CAN payload is {} bytes long, the maximum is 8 bytes",
+                data.len()
+            )));
+        }
+        check_can_id(id, extended)?;
+
+        let mut msg = PassThruMsg::new_can(id, data);
+        if extended {
+            msg = msg.tx_flags(TxFlags::CAN_29BIT_ID);
+        }
+        self.channel.write_msg(&msg, timeout)
+    }
+
+    /// Receives a single frame. Messages that aren't valid CAN frames are skipped.
+    ///
+    /// Returns [`Error::Timeout`] if no frame was received before the timeout expired.
+    pub fn recv(&self, timeout: Duration) -> Result<CanFrame, Error> {
+        self.recv_deadline(deadline_after(timeout))
+    }
+
+    fn recv_deadline(&self, deadline: Instant) -> Result<CanFrame, Error> {
+        loop {
+            let msg = self.channel.read_msg_deadline(deadline)?;
+            if let Ok(frame) = CanFrame::try_from(&msg) {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Receives up to `max` frames, converting them as they are read so that only one batch of
+    /// messages is held at a time. Messages that aren't valid CAN frames are skipped.
+    ///
+    /// Returns [`Error::Timeout`] if no messages were received before the timeout expired.
+    pub fn recv_frames(&self, max: usize, timeout: Duration) -> Result<Vec<CanFrame>, Error> {
+        let deadline = deadline_after(timeout);
+        let mut frames = Vec::with_capacity(max);
+        let mut buf = vec![PassThruMsg::new(Protocol::CAN); max.min(READ_BATCH_SIZE)];
+        while frames.len() < max {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let len = buf.len().min(max - frames.len());
+            let count = match self.channel.read(&mut buf[..len], remaining) {
+                Ok(count) => count,
+                Err(Error::Timeout) | Err(Error::BufferEmpty) if !frames.is_empty() => break,
+                Err(err) => return Err(err),
+            };
+            frames.extend(
+                buf[..count]
+                    .iter()
+                    .filter_map(|msg| CanFrame::try_from(msg).ok()),
+            );
+            if remaining.is_zero() || (count < len && !frames.is_empty()) {
+                break;
+            }
+        }
+        if frames.is_empty() && max > 0 {
+            return Err(Error::Timeout);
+        }
+        Ok(frames)
+    }
+
+    /// Returns the pass-everything filter installed when the channel was created
+    pub fn filter(&self) -> FilterId {
+        self.filter
+    }
+
+    /// Returns the underlying channel
+    pub fn channel(&self) -> &Channel<'a> {
+        &self.channel
+    }
+
+    /// Returns the underlying channel
+    pub fn into_inner(self) -> Channel<'a> {
+        self.channel
+    }
+}
+
+/// Returns the largest 11-bit or 29-bit CAN ID
+pub(crate) fn max_can_id(extended: bool) -> u32 {
+    if extended {
+        MAX_EXTENDED_ID
+    } else {
+        MAX_STANDARD_ID
+    }
+}
+
+/// Returns [`Error::InvalidArgument`] if `id` doesn't fit in an 11-bit or 29-bit ID
+pub(crate) fn check_can_id(id: u32, extended: bool) -> Result<(), Error> {
+    let max_id = max_can_id(extended);
+    if id > max_id {
+        return Err(Error::InvalidArgument(format!(
+            "CAN ID {:#X} is larger than the maximum of {:#X}",
+            id, max_id
+        )));
+    }
+    Ok(())
+}
+
+impl<'a> Channel<'a> {
+    /// Switches a single wire CAN channel to high speed mode, or back to normal mode. Returns
+    /// [`Error::NotSupported`] if the driver doesn't support switching.
+    pub fn sw_can_high_speed(&self, enable: bool) -> Result<(), Error> {
+        let id = if enable {
+            IoctlId::SW_CAN_HS
+        } else {
+            IoctlId::SW_CAN_NS
+        };
+        let res = unsafe {
+            self.device.interface.ioctl(
+                self.id.0,
+                id,
+                std::ptr::null_mut::<libc::c_void>(),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        match res {
+            Ok(_) => Ok(()),
+            Err(err) if matches!(err.inner(), Error::InvalidIoctlId) => Err(Error::NotSupported),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Sends `frame` as a high voltage wake up message on a single wire CAN channel by setting
+    /// [`TxFlags::SW_CAN_HV_TX`]
+    pub fn send_hv_wakeup(&self, frame: &PassThruMsg, timeout: Duration) -> Result<(), Error> {
+        let mut msg = *frame;
+        msg.tx_flags |= TxFlags::SW_CAN_HV_TX.bits();
+        self.write_msg(&msg, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, FilterType};
+
+    fn frame(id: u32, extended: bool, payload: &[u8]) -> CanFrame {
+        let mut data = [0; 8];
+        data[..payload.len()].copy_from_slice(payload);
+        CanFrame {
+            id,
+            extended,
+            timestamp: 1000,
+            rx_status: if extended {
+                RxStatus::CAN_29BIT_ID
+            } else {
+                RxStatus::empty()
+            },
+            data,
+            dlc: payload.len() as u8,
+        }
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        for frame in [
+            frame(0x7E8, false, &[0x03, 0x41, 0x0C, 0x1A]),
+            frame(0x18DAF110, true, &[0x02, 0x7E, 0x00]),
+            frame(0x123, false, &[]),
+            frame(0x7FF, false, &[0xFF; 8]),
+        ] {
+            let msg = frame.to_passthru_msg(Protocol::CAN);
+            let (protocol_id, data_size) = (msg.protocol_id, msg.data_size);
+            assert_eq!(protocol_id, Protocol::CAN as u32);
+            assert_eq!(data_size, 4 + frame.dlc as u32);
+            assert_eq!(msg.data[..4], frame.id.to_be_bytes());
+            let tx_flags = TxFlags::from_bits_truncate(msg.tx_flags);
+            assert_eq!(tx_flags.contains(TxFlags::CAN_29BIT_ID), frame.extended);
+            assert_eq!(CanFrame::try_from(&msg).unwrap(), frame);
+        }
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let msg = frame(0x7E8, false, &[0x01]).to_passthru_msg(Protocol::ISO15765);
+        assert!(CanFrame::try_from(&msg).is_ok());
+
+        let mut msg = PassThruMsg::new_can(0x7E8, &[0x01]);
+        msg.protocol_id = Protocol::ISO9141 as u32;
+        assert!(matches!(
+            CanFrame::try_from(&msg),
+            Err(Error::MessageProtocolId)
+        ));
+
+        let mut msg = PassThruMsg::new_can(0x7E8, &[]);
+        msg.data_size = 3;
+        assert!(matches!(
+            CanFrame::try_from(&msg),
+            Err(Error::InvalidMessage)
+        ));
+
+        let msg = PassThruMsg::new_can(0x7E8, &[0; 9]);
+        assert!(matches!(
+            CanFrame::try_from(&msg),
+            Err(Error::InvalidMessage)
+        ));
+    }
+
+    #[test]
+    fn can_id_bounds() {
+        assert!(check_can_id(0x7FF, false).is_ok());
+        assert!(check_can_id(0x800, false).is_err());
+        assert!(check_can_id(0x1FFF_FFFF, true).is_ok());
+        assert!(check_can_id(0x2000_0000, true).is_err());
+    }
+
+    #[test]
+    fn send_and_recv() {
+        let device = mock::open();
+        let can = device.connect_can(500000, ConnectFlags::empty()).unwrap();
+        let id = can.channel().id();
+        let filters = mock::channel(id).filter_msgs;
+        assert_eq!(filters.len(), 1);
+        let filter = filters.values().next().unwrap();
+        assert_eq!(filter.filter_type, FilterType::Pass as u32);
+
+        can.send(0x18DB33F1, true, &[0x02, 0x01, 0x00], Duration::ZERO)
+            .unwrap();
+        let written = mock::channel(id).written;
+        let tx_flags = written[0].tx_flags;
+        assert_eq!(tx_flags, TxFlags::CAN_29BIT_ID.bits());
+        assert_eq!(written[0].data[..4], 0x18DB33F1u32.to_be_bytes());
+
+        // Invalid frames are rejected before calling the driver
+        assert!(matches!(
+            can.send(0x800, false, &[], Duration::ZERO),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            can.send(0x7DF, false, &[0; 9], Duration::ZERO),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(mock::channel(id).written.len(), 1);
+
+        // Messages that aren't frames are skipped
+        let mut short = PassThruMsg::new_can(0, &[]);
+        short.data_size = 2;
+        mock::push_rx(id, short);
+        mock::push_rx(id, PassThruMsg::new_can(0x7E8, &[0x41, 0x00]));
+        let frame = can.recv(Duration::ZERO).unwrap();
+        assert_eq!((frame.id, frame.data()), (0x7E8, &[0x41, 0x00][..]));
+        assert!(matches!(can.recv(Duration::ZERO), Err(Error::Timeout)));
+    }
+}
@@ -0,0 +1,631 @@
+//! Recording messages to capture files
+//!
+//! A capture file starts with the 8 byte magic `J2534CAP` followed by the format version as a
+//! little-endian `u16` and two reserved bytes. Each message is stored as a record made of a
+//! little-endian `u32` length of the rest of the record and the following little-endian fields:
+//!
+//! | Field              | Type   |
+//! |--------------------|--------|
+//! | Channel protocol   | `u32`  |
+//! | Host receive time  | `u64`, microseconds since the Unix epoch |
+//! | `protocol_id`      | `u32`  |
+//! | `rx_status`        | `u32`  |
+//! | `tx_flags`         | `u32`  |
+//! | `timestamp`        | `u32`  |
+//! | `extra_data_index` | `u32`  |
+//! | `data_size`        | `u32`  |
+//! | Data               | `data_size` bytes |
+//!
+//! Captures are read back with [`CaptureReader`] and can be transmitted again with [`replay`].
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{Channel, Error, PassThruMsg, RxStatus, MAX_DATA_SIZE};
+
+/// The magic at the start of every capture file
+pub const MAGIC: [u8; 8] = *b"J2534CAP";
+
+/// The version of the capture format written by [`CaptureWriter`]
+pub const VERSION: u16 = 1;
+
+/// The size of the file header
+const HEADER_SIZE: u64 = 12;
+
+/// The size of a record's fields before the data
+const RECORD_HEADER_SIZE: usize = 36;
+
+/// Writes messages to a capture file. Writes are buffered; call [`CaptureWriter::flush`] to
+/// make sure recorded messages have reached the file.
+pub struct CaptureWriter<W: Write = BufWriter<File>> {
+    writer: W,
+}
+
+impl CaptureWriter {
+    /// Creates a capture file at `path`, replacing any existing file
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<CaptureWriter> {
+        CaptureWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the capture header to `writer`. The writer should be buffered.
+    pub fn new(mut writer: W) -> io::Result<CaptureWriter<W>> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[0; 2])?;
+        Ok(CaptureWriter { writer })
+    }
+
+    /// Records a message received or written at `time` on a channel using `channel_protocol`
+    pub fn write_msg(
+        &mut self,
+        channel_protocol: u32,
+        msg: &PassThruMsg,
+        time: SystemTime,
+    ) -> io::Result<()> {
+        let size = (msg.data_size as usize).min(msg.data.len());
+        let micros = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_micros() as u64;
+
+        let mut record = Vec::with_capacity(4 + RECORD_HEADER_SIZE + size);
+        record.extend_from_slice(&((RECORD_HEADER_SIZE + size) as u32).to_le_bytes());
+        record.extend_from_slice(&channel_protocol.to_le_bytes());
+        record.extend_from_slice(&micros.to_le_bytes());
+        for field in [
+            msg.protocol_id,
+            msg.rx_status,
+            msg.tx_flags,
+            msg.timestamp,
+            msg.extra_data_index,
+            size as u32,
+        ] {
+            record.extend_from_slice(&field.to_le_bytes());
+        }
+        record.extend_from_slice(&msg.data[..size]);
+        self.writer.write_all(&record)
+    }
+
+    /// Flushes buffered records to the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<'a> Channel<'a> {
+    /// Reads up to `max` messages like [`Channel::read_msgs`] and records them to `capture`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::capture::CaptureWriter;
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let mut capture = CaptureWriter::create("bus.cap").unwrap();
+    /// for _ in 0..100 {
+    ///     match channel.read_msgs_logged(&mut capture, 64, Duration::from_millis(100)) {
+    ///         Ok(_) | Err(j2534::Error::Timeout) => {}
+    ///         Err(err) => panic!("{}", err),
+    ///     }
+    /// }
+    /// capture.flush().unwrap();
+    /// ```
+    pub fn read_msgs_logged<W: Write>(
+        &self,
+        capture: &mut CaptureWriter<W>,
+        max: usize,
+        timeout: Duration,
+    ) -> Result<Vec<PassThruMsg>, Error> {
+        let msgs = self.read_msgs(max, timeout)?;
+        let time = SystemTime::now();
+        for msg in &msgs {
+            capture.write_msg(self.protocol_id, msg, time)?;
+        }
+        Ok(msgs)
+    }
+}
+
+/// A message read from a capture file
+#[derive(Debug)]
+pub struct Record {
+    /// The protocol of the channel the message was recorded on
+    pub channel_protocol: u32,
+    /// The host time the message was recorded at
+    pub time: SystemTime,
+    pub msg: PassThruMsg,
+}
+
+impl Record {
+    /// Returns true if the message is an echo of a message transmitted by the device
+    pub fn transmitted(&self) -> bool {
+        self.msg.transmitted()
+    }
+}
+
+/// Reads messages from a capture file written by [`CaptureWriter`]
+pub struct CaptureReader<R: Read = BufReader<File>> {
+    reader: R,
+    version: u16,
+}
+
+impl CaptureReader {
+    /// Opens the capture file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CaptureReader> {
+        CaptureReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Reads the capture header from `reader`. Returns [`io::ErrorKind::InvalidData`] if the data
+    /// isn't a capture or uses a newer version of the format.
+    pub fn new(mut reader: R) -> io::Result<CaptureReader<R>> {
+        let mut header = [0; HEADER_SIZE as usize];
+        reader.read_exact(&mut header)?;
+        if header[..8] != MAGIC {
+            return Err(invalid_data("not a capture file"));
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        if version > VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported capture version {}",
+                version
+            )));
+        }
+        Ok(CaptureReader { reader, version })
+    }
+
+    /// Returns the format version of the capture
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Reads the next record. Returns `None` at the end of the file.
+    pub fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let mut len = [0; 4];
+        // Distinguish the end of the file from a truncated record
+        let mut read = 0;
+        while read < len.len() {
+            match self.reader.read(&mut len[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if !(RECORD_HEADER_SIZE..=RECORD_HEADER_SIZE + MAX_DATA_SIZE).contains(&len) {
+            return Err(invalid_data(&format!("invalid record length {}", len)));
+        }
+        let mut record = vec![0; len];
+        self.reader.read_exact(&mut record)?;
+
+        let field = |offset: usize| {
+            u32::from_le_bytes([
+                record[offset],
+                record[offset + 1],
+                record[offset + 2],
+                record[offset + 3],
+            ])
+        };
+        let mut micros = [0; 8];
+        micros.copy_from_slice(&record[4..12]);
+        let data_size = field(32) as usize;
+        if data_size != len - RECORD_HEADER_SIZE {
+            return Err(invalid_data("record data size doesn't match its length"));
+        }
+
+        let mut data = [0; MAX_DATA_SIZE];
+        data[..data_size].copy_from_slice(&record[RECORD_HEADER_SIZE..]);
+        let msg = PassThruMsg {
+            protocol_id: field(12),
+            rx_status: field(16),
+            tx_flags: field(20),
+            timestamp: field(24),
+            extra_data_index: field(28),
+            data_size: data_size as u32,
+            data,
+        };
+        Ok(Some(Record {
+            channel_protocol: field(0),
+            time: UNIX_EPOCH + Duration::from_micros(u64::from_le_bytes(micros)),
+            msg,
+        }))
+    }
+}
+
+impl<R: Read + Seek> CaptureReader<R> {
+    /// Goes back to the first record
+    pub fn rewind(&mut self) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(HEADER_SIZE))?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// How [`replay`] times the messages it transmits
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Timing {
+    /// Transmit messages as fast as the device accepts them
+    AsFastAsPossible,
+    /// Keep the time between messages from their recorded timestamps
+    Original,
+}
+
+/// Which recorded messages [`replay`] transmits
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Every message
+    All,
+    /// Only echoes of messages transmitted by the recording device
+    Tx,
+    /// Only messages received from the vehicle network
+    Rx,
+}
+
+/// Options for [`replay`]
+#[derive(Copy, Clone, Debug)]
+pub struct ReplayOptions {
+    pub timing: Timing,
+    /// The longest gap between messages with [`Timing::Original`]. Longer gaps are shortened to this.
+    pub max_gap: Duration,
+    /// Which messages to transmit. Defaults to [`Direction::Rx`], so that echoes of messages the
+    /// recording device transmitted aren't sent along with the traffic they were recorded with.
+    pub direction: Direction,
+    /// Whether to transmit ISO 15765 first frame indications and transmit indications, which don't
+    /// hold a complete message. Defaults to false.
+    pub indications: bool,
+    /// The protocol ID to transmit every message with instead of the recorded one, for example to
+    /// replay a capture taken on an ISO 15765 channel on a raw CAN channel
+    pub protocol: Option<u32>,
+    /// The amount of times to replay the capture
+    pub loops: usize,
+    /// The amount of time to wait for each message to be written
+    pub timeout: Duration,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> ReplayOptions {
+        ReplayOptions {
+            timing: Timing::Original,
+            max_gap: Duration::from_secs(1),
+            direction: Direction::Rx,
+            indications: false,
+            protocol: None,
+            loops: 1,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Transmits the messages recorded in `capture` on `channel`. Returns the amount of messages written.
+///
+/// By default only received messages are transmitted, without echoes or indications. See
+/// [`ReplayOptions`].
+///
+/// # Example
+/// ```no_run
+/// use j2534::capture::{self, CaptureReader, ReplayOptions, Timing};
+/// use j2534::{ConnectFlags, Interface, Protocol};
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+///
+/// let mut reader = CaptureReader::open("bus.cap").unwrap();
+/// let options = ReplayOptions {
+///     timing: Timing::AsFastAsPossible,
+///     ..ReplayOptions::default()
+/// };
+/// capture::replay(&channel, &mut reader, &options).unwrap();
+/// ```
+pub fn replay<R: Read + Seek>(
+    channel: &Channel,
+    capture: &mut CaptureReader<R>,
+    options: &ReplayOptions,
+) -> Result<usize, Error> {
+    let mut written = 0;
+    for i in 0..options.loops {
+        if i > 0 {
+            capture.rewind()?;
+        }
+        // The timestamp of the previous message and the time it was written
+        let mut previous: Option<(u32, Instant)> = None;
+        for record in &mut *capture {
+            let record = record?;
+            let included = match options.direction {
+                Direction::All => true,
+                Direction::Tx => record.transmitted(),
+                Direction::Rx => !record.transmitted(),
+            };
+            let rx_status = RxStatus::from_bits_truncate(record.msg.rx_status);
+            let indication = rx_status.intersects(RxStatus::START_OF_MESSAGE | RxStatus::TX_DONE);
+            if !included || (indication && !options.indications) {
+                continue;
+            }
+
+            if let (Timing::Original, Some((timestamp, sent))) = (options.timing, previous) {
+                let delta = record.msg.timestamp.wrapping_sub(timestamp);
+                // A gap of more than half the timestamp range means the messages are out of order
+                let gap = if delta < 1 << 31 {
+                    Duration::from_micros(delta as u64).min(options.max_gap)
+                } else {
+                    Duration::ZERO
+                };
+                let wait = (sent + gap).saturating_duration_since(Instant::now());
+                if !wait.is_zero() {
+                    thread::sleep(wait);
+                }
+            }
+
+            let mut msg = record.msg;
+            // Received messages have no meaningful rx status when transmitted
+            msg.rx_status = RxStatus::NONE.bits();
+            if let Some(protocol) = options.protocol {
+                msg.protocol_id = protocol;
+            }
+            let sent = Instant::now();
+            channel.write_msg(&msg, options.timeout)?;
+            previous = Some((record.msg.timestamp, sent));
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, Protocol};
+    use std::io::Cursor;
+
+    #[test]
+    fn record_layout() {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        let mut msg = PassThruMsg::new_can(0x7E8, &[0x02, 0x41]);
+        msg.rx_status = 0x01;
+        msg.timestamp = 0x11223344;
+        let time = UNIX_EPOCH + Duration::from_micros(0x0102030405);
+        writer.write_msg(Protocol::CAN as u32, &msg, time).unwrap();
+        let bytes = writer.into_inner();
+
+        assert_eq!(&bytes[..12], b"J2534CAP\x01\x00\x00\x00");
+        let record = &bytes[12..];
+        assert_eq!(record.len(), 4 + RECORD_HEADER_SIZE + 6);
+        assert_eq!(record[..4], 42u32.to_le_bytes());
+        assert_eq!(record[4..8], 5u32.to_le_bytes());
+        assert_eq!(record[8..16], 0x0102030405u64.to_le_bytes());
+        assert_eq!(record[16..20], 5u32.to_le_bytes());
+        assert_eq!(record[20..24], 1u32.to_le_bytes());
+        assert_eq!(record[28..32], 0x11223344u32.to_le_bytes());
+        assert_eq!(record[36..40], 6u32.to_le_bytes());
+        assert_eq!(record[40..], [0x00, 0x00, 0x07, 0xE8, 0x02, 0x41]);
+    }
+
+    #[test]
+    fn oversized_data_is_clamped() {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        let mut msg = PassThruMsg::new_can(0x7E8, &[0x02, 0x41]);
+        msg.data_size = u32::MAX;
+        writer.write_msg(5, &msg, UNIX_EPOCH).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), 12 + 4 + RECORD_HEADER_SIZE + MAX_DATA_SIZE);
+    }
+
+    #[test]
+    fn read_msgs_logged_records_reads() {
+        let channel = mock::can_channel();
+        for id in [0x7E8, 0x7E9] {
+            mock::push_rx(channel.id(), PassThruMsg::new_can(id, &[0x01]));
+        }
+
+        let mut capture = CaptureWriter::new(Vec::new()).unwrap();
+        let msgs = channel
+            .read_msgs_logged(&mut capture, 4, Duration::ZERO)
+            .unwrap();
+        assert_eq!(msgs.len(), 2);
+        let bytes = capture.into_inner();
+        assert_eq!(bytes.len(), 12 + 2 * (4 + RECORD_HEADER_SIZE + 5));
+    }
+
+    /// Returns a capture of `msgs` recorded on a CAN channel one millisecond apart
+    fn capture_of(msgs: &[PassThruMsg]) -> Vec<u8> {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        for (i, msg) in msgs.iter().enumerate() {
+            let time = UNIX_EPOCH + Duration::from_millis(i as u64);
+            writer.write_msg(Protocol::CAN as u32, msg, time).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn data(msg: &PassThruMsg) -> &[u8] {
+        &msg.data[..msg.data_size as usize]
+    }
+
+    fn echo(mut msg: PassThruMsg) -> PassThruMsg {
+        msg.rx_status = RxStatus::TX_MSG_TYPE.bits();
+        msg
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut msg = PassThruMsg::new_can(0x7E8, &[0x02, 0x41, 0x0C]);
+        msg.timestamp = 1234;
+        msg.extra_data_index = 7;
+        let bytes = capture_of(&[msg, echo(PassThruMsg::new_can(0x7DF, &[]))]);
+
+        let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.version(), VERSION);
+        let record = reader.read_record().unwrap().unwrap();
+        assert_eq!(record.channel_protocol, Protocol::CAN as u32);
+        assert_eq!(record.time, UNIX_EPOCH);
+        assert!(!record.transmitted());
+        let (timestamp, extra_data_index) = (record.msg.timestamp, record.msg.extra_data_index);
+        assert_eq!((timestamp, extra_data_index), (1234, 7));
+        assert_eq!(data(&record.msg), data(&msg));
+
+        let record = reader.read_record().unwrap().unwrap();
+        assert_eq!(record.time, UNIX_EPOCH + Duration::from_millis(1));
+        assert!(record.transmitted());
+        assert_eq!(data(&record.msg), [0x00, 0x00, 0x07, 0xDF]);
+
+        // The end of the file is not an error, and rewinding starts over
+        assert!(reader.read_record().unwrap().is_none());
+        reader.rewind().unwrap();
+        assert_eq!(reader.count(), 2);
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let kind = |bytes: &[u8]| CaptureReader::new(bytes).err().map(|err| err.kind());
+        assert_eq!(
+            kind(b"PCAPFILE\x01\x00\x00\x00"),
+            Some(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(
+            kind(b"J2534CAP\x02\x00\x00\x00"),
+            Some(io::ErrorKind::InvalidData)
+        );
+        assert_eq!(kind(b"J2534CAP"), Some(io::ErrorKind::UnexpectedEof));
+        assert_eq!(kind(b"J2534CAP\x01\x00\x00\x00"), None);
+    }
+
+    #[test]
+    fn invalid_records() {
+        let bytes = capture_of(&[PassThruMsg::new_can(0x7E8, &[0x01])]);
+        let kind = |bytes: &[u8]| {
+            let mut reader = CaptureReader::new(bytes).unwrap();
+            reader.read_record().err().map(|err| err.kind())
+        };
+        assert_eq!(kind(&bytes), None);
+
+        // Truncated in the length and in the record
+        assert_eq!(kind(&bytes[..14]), Some(io::ErrorKind::UnexpectedEof));
+        assert_eq!(
+            kind(&bytes[..bytes.len() - 1]),
+            Some(io::ErrorKind::UnexpectedEof)
+        );
+
+        // A length shorter than the record header
+        let mut short = bytes.clone();
+        short[12..16].copy_from_slice(&8u32.to_le_bytes());
+        assert_eq!(kind(&short), Some(io::ErrorKind::InvalidData));
+
+        // A data size that doesn't match the length
+        let mut mismatched = bytes;
+        mismatched[48..52].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(kind(&mismatched), Some(io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn replay_filters_and_rewrites() {
+        let channel = mock::can_channel();
+        let mut received = PassThruMsg::new_can(0x7E8, &[0x01]);
+        received.rx_status = RxStatus::CAN_29BIT_ID.bits();
+        let bytes = capture_of(&[received, echo(PassThruMsg::new_can(0x7DF, &[0x02]))]);
+        let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+
+        let options = ReplayOptions {
+            timing: Timing::AsFastAsPossible,
+            direction: Direction::Rx,
+            protocol: Some(Protocol::ISO15765 as u32),
+            loops: 2,
+            ..ReplayOptions::default()
+        };
+        assert_eq!(replay(&channel, &mut reader, &options).unwrap(), 2);
+        let written = mock::channel(channel.id()).written;
+        assert_eq!(written.len(), 2);
+        for msg in &written {
+            let (protocol_id, rx_status) = (msg.protocol_id, msg.rx_status);
+            assert_eq!(protocol_id, Protocol::ISO15765 as u32);
+            assert_eq!(rx_status, 0);
+            assert_eq!(data(msg), [0x00, 0x00, 0x07, 0xE8, 0x01]);
+        }
+
+        reader.rewind().unwrap();
+        let options = ReplayOptions {
+            timing: Timing::AsFastAsPossible,
+            direction: Direction::Tx,
+            ..ReplayOptions::default()
+        };
+        assert_eq!(replay(&channel, &mut reader, &options).unwrap(), 1);
+        let written = mock::channel(channel.id()).written;
+        let protocol_id = written[2].protocol_id;
+        assert_eq!(protocol_id, Protocol::CAN as u32);
+        assert_eq!(data(&written[2]), [0x00, 0x00, 0x07, 0xDF, 0x02]);
+    }
+
+    #[test]
+    fn replay_skips_indications_and_echoes() {
+        let channel = mock::can_channel();
+        let with_status = |mut msg: PassThruMsg, rx_status: RxStatus| {
+            msg.rx_status = rx_status.bits();
+            msg
+        };
+        let bytes = capture_of(&[
+            with_status(PassThruMsg::new_can(0x7E8, &[]), RxStatus::START_OF_MESSAGE),
+            PassThruMsg::new_can(0x7E8, &[0x01]),
+            echo(PassThruMsg::new_can(0x7E0, &[0x02])),
+            with_status(
+                PassThruMsg::new_can(0x7E0, &[]),
+                RxStatus::TX_MSG_TYPE | RxStatus::TX_DONE,
+            ),
+            PassThruMsg::new_can(0x7E9, &[0x03]),
+        ]);
+        let mut reader = CaptureReader::new(Cursor::new(bytes)).unwrap();
+        let options = ReplayOptions {
+            timing: Timing::AsFastAsPossible,
+            ..ReplayOptions::default()
+        };
+        assert_eq!(replay(&channel, &mut reader, &options).unwrap(), 2);
+        let written = mock::channel(channel.id()).written;
+        assert_eq!(data(&written[0]), [0x00, 0x00, 0x07, 0xE8, 0x01]);
+        assert_eq!(data(&written[1]), [0x00, 0x00, 0x07, 0xE9, 0x03]);
+
+        // Every record
+        reader.rewind().unwrap();
+        let options = ReplayOptions {
+            direction: Direction::All,
+            indications: true,
+            ..options
+        };
+        assert_eq!(replay(&channel, &mut reader, &options).unwrap(), 5);
+
+        // Echoes without the transmit indication
+        reader.rewind().unwrap();
+        let options = ReplayOptions {
+            direction: Direction::Tx,
+            indications: false,
+            ..options
+        };
+        assert_eq!(replay(&channel, &mut reader, &options).unwrap(), 1);
+        let written = mock::channel(channel.id()).written;
+        assert_eq!(written.len(), 8);
+        assert_eq!(data(&written[7]), [0x00, 0x00, 0x07, 0xE0, 0x02]);
+    }
+}
@@ -0,0 +1,174 @@
+//! Polling multiple channels from a single thread
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{deadline_after, Channel, Error, PassThruMsg, READ_BATCH_SIZE};
+
+/// The time to sleep between sweeps over the channels when none had any messages
+const SWEEP_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A set of channels that can be polled for messages together
+///
+/// # Example
+/// ```no_run
+/// use j2534::{ChannelSet, ConnectFlags, Interface, Protocol};
+/// use std::time::Duration;
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let can = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+/// let isotp = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+///
+/// let set = ChannelSet::new();
+/// let can_key = set.insert(&can);
+/// set.insert(&isotp);
+///
+/// for (key, msgs) in set.poll(Duration::from_secs(1)) {
+///     println!("{} {}: {:?}", key, if key == can_key { "CAN" } else { "ISO-TP" }, msgs);
+/// }
+/// ```
+#[derive(Default)]
+pub struct ChannelSet<'a> {
+    inner: Mutex<Channels<'a>>,
+}
+
+#[derive(Default)]
+struct Channels<'a> {
+    channels: Vec<(usize, &'a Channel<'a>)>,
+    next_key: usize,
+    /// The channel read first by the next sweep
+    start: usize,
+}
+
+impl<'a> ChannelSet<'a> {
+    /// Creates an empty set
+    pub fn new() -> ChannelSet<'a> {
+        ChannelSet::default()
+    }
+
+    /// Adds a channel to the set. Returns the key identifying the channel in poll results.
+    ///
+    /// Channels can be added and removed while another thread is in [`ChannelSet::poll`]. The
+    /// change takes effect at the next sweep.
+    pub fn insert(&self, channel: &'a Channel<'a>) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let key = inner.next_key;
+        inner.next_key += 1;
+        inner.channels.push((key, channel));
+        key
+    }
+
+    /// Removes a channel from the set. Returns false if no channel has that key.
+    ///
+    /// If another thread is polling the set, the channel isn't read again once this returns.
+    pub fn remove(&self, key: usize) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.channels.iter().position(|(k, _)| *k == key) {
+            Some(index) => {
+                inner.channels.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the amount of channels in the set
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().channels.len()
+    }
+
+    /// Returns true if the set has no channels
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().channels.is_empty()
+    }
+
+    /// Polls every channel for buffered messages until at least one channel has messages or the timeout
+    /// expires. Returns the key of each channel that had messages or failed along with the result.
+    /// An empty result means the timeout expired.
+    ///
+    /// Each sweep reads at most one batch from each channel, and starts at a different channel each
+    /// time, so a busy channel can't keep the others from being read.
+    pub fn poll(&self, timeout: Duration) -> Vec<(usize, Result<Vec<PassThruMsg>, Error>)> {
+        let deadline = deadline_after(timeout);
+        loop {
+            let results = self.inner.lock().unwrap().sweep();
+            if !results.is_empty() || Instant::now() >= deadline {
+                return results;
+            }
+            thread::sleep(SWEEP_INTERVAL.min(deadline.saturating_duration_since(Instant::now())));
+        }
+    }
+}
+
+impl<'a> Channels<'a> {
+    fn sweep(&mut self) -> Vec<(usize, Result<Vec<PassThruMsg>, Error>)> {
+        let mut results = Vec::new();
+        let len = self.channels.len();
+        for i in 0..len {
+            let (key, channel) = self.channels[(self.start + i) % len];
+            match channel.read_msgs(READ_BATCH_SIZE, Duration::ZERO) {
+                Ok(msgs) if msgs.is_empty() => {}
+                Err(Error::BufferEmpty) | Err(Error::Timeout) => {}
+                result => results.push((key, result)),
+            }
+        }
+        if len > 0 {
+            self.start = (self.start + 1) % len;
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn busy_channel_does_not_starve_others() {
+        let busy = mock::can_channel();
+        let quiet = mock::can_channel();
+        for _ in 0..READ_BATCH_SIZE * 4 {
+            mock::push_rx(busy.id(), PassThruMsg::new_can(0x100, &[0x00]));
+        }
+        mock::push_rx(quiet.id(), PassThruMsg::new_can(0x7E8, &[0x41]));
+
+        let set = ChannelSet::new();
+        let busy_key = set.insert(&busy);
+        let quiet_key = set.insert(&quiet);
+        let results = set.poll(Duration::from_millis(10));
+        // The busy channel gets one batch per sweep, the quiet one is read in the same sweep
+        assert_eq!(results.len(), 2);
+        for (key, result) in results {
+            let expected = if key == busy_key { READ_BATCH_SIZE } else { 1 };
+            assert!(key == busy_key || key == quiet_key);
+            assert_eq!(result.unwrap().len(), expected);
+        }
+        assert_eq!(mock::channel(busy.id()).rx.len(), READ_BATCH_SIZE * 3);
+    }
+
+    #[test]
+    fn remove_while_polling() {
+        let kept = mock::can_channel();
+        let removed = mock::can_channel();
+        let set = ChannelSet::new();
+        let kept_key = set.insert(&kept);
+        let removed_key = set.insert(&removed);
+
+        let results = thread::scope(|s| {
+            let poll = s.spawn(|| set.poll(Duration::from_secs(5)));
+            thread::sleep(Duration::from_millis(10));
+            assert!(set.remove(removed_key));
+            mock::push_rx(removed.id(), PassThruMsg::new_can(0x7E9, &[0x41]));
+            mock::push_rx(kept.id(), PassThruMsg::new_can(0x7E8, &[0x41]));
+            poll.join().unwrap()
+        });
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, kept_key);
+        assert_eq!(mock::channel(removed.id()).rx.len(), 1);
+        assert!(!set.remove(removed_key));
+        assert_eq!(set.len(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+//! Relating device timestamps to the host clock
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Channel, Error, PassThruMsg};
+
+/// A device timestamp sampled together with the host clock. Converts device timestamps to host
+/// time. Created with [`Channel::calibrate_clock`].
+#[derive(Copy, Clone, Debug)]
+pub struct ClockAnchor {
+    /// The device timestamp in microseconds
+    raw: u32,
+    instant: Instant,
+    system: SystemTime,
+    uncertainty: Duration,
+    /// How much faster the device clock runs than the host clock in parts per million
+    drift_ppm: f64,
+}
+
+impl ClockAnchor {
+    /// Returns the device timestamp the anchor was sampled at
+    pub fn raw_timestamp(&self) -> u32 {
+        self.raw
+    }
+
+    /// Returns the host time the anchor was sampled at
+    pub fn system_time(&self) -> SystemTime {
+        self.system
+    }
+
+    /// Returns how far the anchor may be off, which is half the time the sample took
+    pub fn uncertainty(&self) -> Duration {
+        self.uncertainty
+    }
+
+    /// Returns how much faster the device clock runs than the host clock in parts per million,
+    /// estimated by [`Channel::recalibrate_clock`]. Zero until the clock has been recalibrated.
+    pub fn drift_ppm(&self) -> f64 {
+        self.drift_ppm
+    }
+
+    /// Converts a device timestamp to host time. Timestamps within about 35 minutes of the anchor
+    /// are converted correctly; recalibrate periodically for longer captures.
+    pub fn to_system_time(&self, raw_ts: u32) -> SystemTime {
+        let delta = raw_ts.wrapping_sub(self.raw);
+        // Timestamps more than half the range ahead are from before the anchor
+        let (micros, before) = if delta < 1 << 31 {
+            (delta as f64, false)
+        } else {
+            (self.raw.wrapping_sub(raw_ts) as f64, true)
+        };
+        let host = Duration::from_secs_f64(micros / (1.0 + self.drift_ppm / 1e6) / 1e6);
+        if before {
+            self.system - host
+        } else {
+            self.system + host
+        }
+    }
+}
+
+impl<'a> Channel<'a> {
+    /// Estimates the offset between device timestamps and host time by writing `ping` with
+    /// [`Channel::write_confirmed`] and sampling the host clock around it. The device timestamp of
+    /// the echo is assumed to be halfway through the round trip.
+    ///
+    /// `ping` is transmitted on the vehicle network, so use a message that is harmless to the
+    /// ECUs on the bus. Other messages received while waiting for the echo are discarded.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, PassThruMsg, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let ping = PassThruMsg::new_can(0x7FF, &[]);
+    /// let anchor = channel.calibrate_clock(&ping, Duration::from_secs(1)).unwrap();
+    /// for msg in channel.read_msgs(16, Duration::from_secs(1)).unwrap() {
+    ///     println!("{:?}: {:?}", anchor.to_system_time(msg.timestamp), msg);
+    /// }
+    /// ```
+    pub fn calibrate_clock(
+        &self,
+        ping: &PassThruMsg,
+        timeout: Duration,
+    ) -> Result<ClockAnchor, Error> {
+        let instant = Instant::now();
+        let system = SystemTime::now();
+        let confirmation = self.write_confirmed(ping, timeout)?;
+        let uncertainty = instant.elapsed() / 2;
+        Ok(ClockAnchor {
+            raw: confirmation.timestamp,
+            instant: instant + uncertainty,
+            system: system + uncertainty,
+            uncertainty,
+            drift_ppm: 0.0,
+        })
+    }
+
+    /// Samples the clocks again like [`Channel::calibrate_clock`] and updates `anchor`. The drift
+    /// between the clocks is estimated from the time since the previous sample.
+    pub fn recalibrate_clock(
+        &self,
+        anchor: &mut ClockAnchor,
+        ping: &PassThruMsg,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        let mut new = self.calibrate_clock(ping, timeout)?;
+        let host = new.instant.duration_since(anchor.instant).as_micros() as f64;
+        // Account for timestamps that wrapped between the samples using the host clock
+        let delta = new.raw.wrapping_sub(anchor.raw) as f64;
+        let range = (1u64 << 32) as f64;
+        let device = delta + ((host - delta) / range).round() * range;
+        new.drift_ppm = if host > 0.0 {
+            (device - host) / host * 1e6
+        } else {
+            anchor.drift_ppm
+        };
+        *anchor = new;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    fn anchor(raw: u32, drift_ppm: f64) -> ClockAnchor {
+        ClockAnchor {
+            raw,
+            instant: Instant::now(),
+            system: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000),
+            uncertainty: Duration::ZERO,
+            drift_ppm,
+        }
+    }
+
+    fn assert_near(time: SystemTime, expected: SystemTime) {
+        let diff = match time.duration_since(expected) {
+            Ok(diff) => diff,
+            Err(err) => err.duration(),
+        };
+        assert!(
+            diff < Duration::from_micros(1),
+            "{:?} != {:?}",
+            time,
+            expected
+        );
+    }
+
+    #[test]
+    fn calibrate() {
+        let channel = mock::can_channel();
+        let mut ping = PassThruMsg::new_can(0x7FF, &[]);
+        ping.timestamp = 5000;
+        let before = SystemTime::now();
+        let anchor = channel
+            .calibrate_clock(&ping, Duration::from_millis(100))
+            .unwrap();
+        let after = SystemTime::now();
+        assert_eq!(anchor.raw_timestamp(), 5000);
+        assert!(anchor.system_time() >= before && anchor.system_time() <= after);
+        assert!(anchor.uncertainty() <= after.duration_since(before).unwrap());
+        assert_eq!(anchor.drift_ppm(), 0.0);
+        // Loopback is switched back off
+        assert_eq!(mock::channel(channel.id()).configs.get(&0x03), Some(&0));
+    }
+
+    #[test]
+    fn system_time_across_a_wrap() {
+        let anchor = anchor(u32::MAX - 499, 0.0);
+        let base = anchor.system_time();
+        assert_near(
+            anchor.to_system_time(500),
+            base + Duration::from_micros(1000),
+        );
+        assert_near(
+            anchor.to_system_time(u32::MAX - 1499),
+            base - Duration::from_micros(1000),
+        );
+
+        // Device time is scaled by the drift
+        let anchor = self::anchor(1000, 1000.0);
+        assert_near(
+            anchor.to_system_time(1000 + 1_001_000),
+            anchor.system_time() + Duration::from_secs(1),
+        );
+    }
+
+    #[test]
+    fn recalibrate_estimates_drift() {
+        let channel = mock::can_channel();
+        // The device clock advanced 10.01 s, wrapping, while the host clock advanced 10 s
+        let mut anchor = anchor(u32::MAX - 5_000_000, 0.0);
+        anchor.instant = Instant::now().checked_sub(Duration::from_secs(10)).unwrap();
+        let mut ping = PassThruMsg::new_can(0x7FF, &[]);
+        let raw = anchor.raw.wrapping_add(10_010_000);
+        ping.timestamp = raw;
+        channel
+            .recalibrate_clock(&mut anchor, &ping, Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(anchor.raw_timestamp(), raw);
+        assert!(
+            (anchor.drift_ppm() - 1000.0).abs() < 100.0,
+            "drift {}",
+            anchor.drift_ppm()
+        );
+    }
+}
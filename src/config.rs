@@ -0,0 +1,926 @@
+//! Typed helpers for protocol-specific configuration parameters
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::{Channel, ConfigId, Error, Protocol};
+
+/// The configuration parameters defined by J2534-1
+const STANDARD_CONFIG_IDS: [ConfigId; 36] = [
+    ConfigId::DATA_RATE,
+    ConfigId::LOOPBACK,
+    ConfigId::NODE_ADDRESS,
+    ConfigId::NETWORK_LINE,
+    ConfigId::P1_MIN,
+    ConfigId::P1_MAX,
+    ConfigId::P2_MIN,
+    ConfigId::P2_MAX,
+    ConfigId::P3_MIN,
+    ConfigId::P3_MAX,
+    ConfigId::P4_MIN,
+    ConfigId::P4_MAX,
+    ConfigId::W0,
+    ConfigId::W1,
+    ConfigId::W2,
+    ConfigId::W3,
+    ConfigId::W4,
+    ConfigId::W5,
+    ConfigId::TIDLE,
+    ConfigId::TINIL,
+    ConfigId::TWUP,
+    ConfigId::PARITY,
+    ConfigId::BIT_SAMPLE_POINT,
+    ConfigId::SYNC_JUMP_WIDTH,
+    ConfigId::T1_MAX,
+    ConfigId::T2_MAX,
+    ConfigId::T3_MAX,
+    ConfigId::T4_MAX,
+    ConfigId::T5_MAX,
+    ConfigId::ISO15765_BS,
+    ConfigId::ISO15765_STMIN,
+    ConfigId::DATA_BITS,
+    ConfigId::FIVE_BAUD_MOD,
+    ConfigId::BS_TX,
+    ConfigId::STMIN_TX,
+    ConfigId::ISO15765_WFT_MAX,
+];
+
+/// Returns true if `stmin` is a separation time defined by ISO 15765-2: 0 to 127 milliseconds
+/// (`0x00` to `0x7F`) or 100 to 900 microseconds (`0xF1` to `0xF9`)
+fn valid_stmin(stmin: u8) -> bool {
+    matches!(stmin, 0x00..=0x7F | 0xF1..=0xF9)
+}
+
+/// The value of `BS_TX` and `STMIN_TX` that uses the values sent by the ECU
+const USE_ECU_FLOW_CONTROL: u32 = 0xFFFF;
+
+/// The P timing parameters of an ISO 9141 or ISO 14230 channel in units of 0.5 milliseconds, as
+/// J2534 defines them. Parameters that are `None` aren't changed by
+/// [`Channel::set_kline_timings`] or weren't reported by the driver in
+/// [`Channel::kline_timings`].
+///
+/// # Example
+/// ```no_run
+/// use j2534::{ConfigId, ConnectFlags, Interface, KLineTimings, Protocol};
+/// use std::time::Duration;
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let channel = device.connect(Protocol::ISO14230, ConnectFlags::NONE, 10400).unwrap();
+///
+/// let timings = KLineTimings::iso14230_defaults()
+///     .with(ConfigId::P3_MIN, Duration::from_millis(20))
+///     .unwrap();
+/// channel.set_kline_timings(&timings).unwrap();
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct KLineTimings {
+    /// Minimum time between bytes of an ECU response
+    pub p1_min: Option<u16>,
+    /// Maximum time between bytes of an ECU response
+    pub p1_max: Option<u16>,
+    /// Minimum time between a request and the ECU response
+    pub p2_min: Option<u16>,
+    /// Maximum time between a request and the ECU response
+    pub p2_max: Option<u16>,
+    /// Minimum time between the end of an ECU response and the next request
+    pub p3_min: Option<u16>,
+    /// Maximum time between the end of an ECU response and the next request
+    pub p3_max: Option<u16>,
+    /// Minimum time between bytes of a request
+    pub p4_min: Option<u16>,
+    /// Maximum time between bytes of a request
+    pub p4_max: Option<u16>,
+}
+
+impl KLineTimings {
+    /// The default timings of ISO 14230-2, which are also the J2534 defaults
+    pub fn iso14230_defaults() -> KLineTimings {
+        KLineTimings {
+            p1_min: Some(0),
+            p1_max: Some(40),
+            p2_min: Some(50),
+            p2_max: Some(100),
+            p3_min: Some(110),
+            p3_max: Some(10000),
+            p4_min: Some(10),
+            p4_max: Some(40),
+        }
+    }
+
+    /// Sets the P timing parameter `id` to `time`, rounded to the nearest 0.5 milliseconds.
+    ///
+    /// Returns [`Error::InvalidArgument`] if `id` isn't a P timing parameter or `time` is longer
+    /// than J2534 allows, which is 32767.5 milliseconds.
+    pub fn with(mut self, id: ConfigId, time: Duration) -> Result<KLineTimings, Error> {
+        let units = (time.as_micros() + 250) / 500;
+        if units > u16::MAX as u128 {
+            return Err(Error::InvalidArgument(format!(
+                "{:?} is longer than the maximum K-line timing of 32767.5ms",
+                time
+            )));
+        }
+        *self.field(id).ok_or_else(|| {
+            Error::InvalidArgument(format!("{} is not a P timing parameter", id))
+        })? = Some(units as u16);
+        Ok(self)
+    }
+
+    fn field(&mut self, id: ConfigId) -> Option<&mut Option<u16>> {
+        Some(match id {
+            ConfigId::P1_MIN => &mut self.p1_min,
+            ConfigId::P1_MAX => &mut self.p1_max,
+            ConfigId::P2_MIN => &mut self.p2_min,
+            ConfigId::P2_MAX => &mut self.p2_max,
+            ConfigId::P3_MIN => &mut self.p3_min,
+            ConfigId::P3_MAX => &mut self.p3_max,
+            ConfigId::P4_MIN => &mut self.p4_min,
+            ConfigId::P4_MAX => &mut self.p4_max,
+            _ => return None,
+        })
+    }
+
+    fn params(&self) -> [(ConfigId, Option<u16>); 8] {
+        [
+            (ConfigId::P1_MIN, self.p1_min),
+            (ConfigId::P1_MAX, self.p1_max),
+            (ConfigId::P2_MIN, self.p2_min),
+            (ConfigId::P2_MAX, self.p2_max),
+            (ConfigId::P3_MIN, self.p3_min),
+            (ConfigId::P3_MAX, self.p3_max),
+            (ConfigId::P4_MIN, self.p4_min),
+            (ConfigId::P4_MAX, self.p4_max),
+        ]
+    }
+}
+
+/// The initialization timing parameters of an ISO 9141 or ISO 14230 channel, used by
+/// [`Channel::five_baud_init`] and [`Channel::fast_init`]. Parameters that are `None` aren't changed
+/// by [`Channel::set_init_timings`] or weren't reported by the driver in
+/// [`Channel::init_timings`]. J2534 sets them in milliseconds, so they are rounded to the nearest
+/// millisecond.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InitTimings {
+    /// Minimum time the bus is high before the tester sends the address byte
+    pub w0: Option<Duration>,
+    /// Maximum time from the end of the address byte to the start of the synchronization pattern
+    pub w1: Option<Duration>,
+    /// Maximum time from the synchronization pattern to the start of key byte 1
+    pub w2: Option<Duration>,
+    /// Maximum time between key byte 1 and key byte 2
+    pub w3: Option<Duration>,
+    /// Time between key byte 2 and its inversion sent by the tester
+    pub w4: Option<Duration>,
+    /// Minimum bus idle time before the tester sends the address byte
+    pub w5: Option<Duration>,
+    /// Minimum bus idle time before a fast initialization
+    pub tidle: Option<Duration>,
+    /// Duration of the low pulse of a fast initialization
+    pub tinil: Option<Duration>,
+    /// Duration of the wake up pulse of a fast initialization
+    pub twup: Option<Duration>,
+}
+
+impl InitTimings {
+    /// The default timings of J2534-1
+    pub fn spec_defaults() -> InitTimings {
+        let ms = |ms| Some(Duration::from_millis(ms));
+        InitTimings {
+            w0: ms(300),
+            w1: ms(300),
+            w2: ms(20),
+            w3: ms(20),
+            w4: ms(50),
+            w5: ms(300),
+            tidle: ms(300),
+            tinil: ms(25),
+            twup: ms(50),
+        }
+    }
+
+    fn params(&self) -> [(ConfigId, Option<Duration>); 9] {
+        [
+            (ConfigId::W0, self.w0),
+            (ConfigId::W1, self.w1),
+            (ConfigId::W2, self.w2),
+            (ConfigId::W3, self.w3),
+            (ConfigId::W4, self.w4),
+            (ConfigId::W5, self.w5),
+            (ConfigId::TIDLE, self.tidle),
+            (ConfigId::TINIL, self.tinil),
+            (ConfigId::TWUP, self.twup),
+        ]
+    }
+
+    fn field(&mut self, id: ConfigId) -> Option<&mut Option<Duration>> {
+        Some(match id {
+            ConfigId::W0 => &mut self.w0,
+            ConfigId::W1 => &mut self.w1,
+            ConfigId::W2 => &mut self.w2,
+            ConfigId::W3 => &mut self.w3,
+            ConfigId::W4 => &mut self.w4,
+            ConfigId::W5 => &mut self.w5,
+            ConfigId::TIDLE => &mut self.tidle,
+            ConfigId::TINIL => &mut self.tinil,
+            ConfigId::TWUP => &mut self.twup,
+            _ => return None,
+        })
+    }
+}
+
+/// The lines a J1850PWM channel uses, set with [`Channel::set_network_line`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NetworkLine {
+    /// Both lines (`BUS_NORMAL`)
+    Normal = 0,
+    /// Only the plus line (`BUS_PLUS`)
+    Plus = 1,
+    /// Only the minus line (`BUS_MINUS`)
+    Minus = 2,
+}
+
+/// Which frames an ISO 15765 channel passes on besides ISO-TP messages, set with
+/// [`Channel::set_mixed_format`]. Raw frames are received with [`Protocol::CAN`] as their
+/// protocol ID and reported as [`RxEvent::RawFrame`](crate::RxEvent::RawFrame).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MixedFormat {
+    /// Only ISO-TP messages (`CAN_MIXED_FORMAT_OFF`)
+    Off = 0,
+    /// ISO-TP messages and the CAN frames that don't match a flow control filter
+    /// (`CAN_MIXED_FORMAT_ON`)
+    On = 1,
+    /// ISO-TP messages and every CAN frame that passes the filters, including the frames of
+    /// ISO-TP messages (`CAN_MIXED_FORMAT_ALL_FRAMES`)
+    AllFrames = 2,
+}
+
+/// The highest pin number of the J1962 connector
+const MAX_J1962_PIN: u8 = 16;
+
+/// The J1962 connector pins a J2534-2 pin-selected channel uses, set with
+/// [`Channel::set_j1962_pins`]. Protocols using a single line, like K-line or single wire CAN,
+/// leave `pin2` at 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PinPair {
+    /// The pin of the first line, like CAN high or K-line
+    pub pin1: u8,
+    /// The pin of the second line, like CAN low or L-line, or 0 if it isn't used
+    pub pin2: u8,
+}
+
+impl PinPair {
+    pub fn new(pin1: u8, pin2: u8) -> PinPair {
+        PinPair { pin1, pin2 }
+    }
+
+    /// Returns the value of the `J1962_PINS` parameter, with `pin1` in bits 8 to 15 and `pin2` in
+    /// bits 0 to 7
+    pub fn value(self) -> u32 {
+        (u32::from(self.pin1) << 8) | u32::from(self.pin2)
+    }
+
+    /// Decodes a `J1962_PINS` parameter value. Returns `None` if it has bits above bit 15 set.
+    pub fn from_value(value: u32) -> Option<PinPair> {
+        if value > 0xFFFF {
+            return None;
+        }
+        Some(PinPair::new((value >> 8) as u8, value as u8))
+    }
+
+    pub(crate) fn check(self) -> Result<(), Error> {
+        if self.pin1 == 0 || self.pin1 > MAX_J1962_PIN || self.pin2 > MAX_J1962_PIN {
+            return Err(Error::InvalidArgument(format!(
+                "J1962 pins {} and {} are not valid, the connector has pins 1 to {}",
+                self.pin1, self.pin2, MAX_J1962_PIN
+            )));
+        }
+        if self.pin1 == self.pin2 {
+            return Err(Error::InvalidArgument(format!(
+                "J1962 pin {} can't be used for both lines",
+                self.pin1
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The values of the configuration parameters of a channel, returned by
+/// [`Channel::config_snapshot`]. Displayed as a table with one parameter per line.
+#[derive(Clone, Debug)]
+pub struct ConfigSnapshot {
+    /// Each parameter with its value, or `None` if the driver didn't report it
+    pub entries: Vec<(ConfigId, Option<u32>)>,
+}
+
+impl fmt::Display for ConfigSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .entries
+            .iter()
+            .map(|(id, _)| id.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for (id, value) in &self.entries {
+            match value {
+                Some(value) => writeln!(
+                    f,
+                    "{:<width$}  {:>10}  {:#010X}",
+                    id.to_string(),
+                    value,
+                    value,
+                    width = width
+                )?,
+                None => writeln!(f, "{:<width$}  not reported", id.to_string(), width = width)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Channel<'a> {
+    /// Returns [`Error::InvalidArgument`] if the channel doesn't use one of `protocols`
+    fn require_protocol(&self, protocols: &[Protocol], what: &str) -> Result<(), Error> {
+        match self.protocol {
+            Some(protocol) if protocols.contains(&protocol.base()) => Ok(()),
+            _ => Err(Error::InvalidArgument(format!(
+                "{} can only be configured on {:?} channels",
+                what, protocols
+            ))),
+        }
+    }
+
+    /// Sets the block size, separation time and maximum number of wait frames that the device
+    /// sends in flow control frames when receiving on an ISO 15765 channel. Parameters that are
+    /// `None` are left unchanged; the others are set with one `SET_CONFIG` call.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// ISO 15765 or `stmin` is a reserved separation time. Valid separation times are `0x00` to
+    /// `0x7F` milliseconds and `0xF1` to `0xF9` for 100 to 900 microseconds.
+    pub fn set_iso15765_params(
+        &self,
+        bs: Option<u8>,
+        stmin: Option<u8>,
+        wft_max: Option<u8>,
+    ) -> Result<(), Error> {
+        self.require_protocol(&[Protocol::ISO15765], "ISO 15765 parameters")?;
+        if let Some(stmin) = stmin.filter(|&stmin| !valid_stmin(stmin)) {
+            return Err(Error::InvalidArgument(format!(
+                "separation time {:#04X} is reserved by ISO 15765-2",
+                stmin
+            )));
+        }
+        let configs: Vec<(ConfigId, u32)> = [
+            (ConfigId::ISO15765_BS, bs),
+            (ConfigId::ISO15765_STMIN, stmin),
+            (ConfigId::ISO15765_WFT_MAX, wft_max),
+        ]
+        .iter()
+        .filter_map(|&(id, value)| Some((id, value? as u32)))
+        .collect();
+        if configs.is_empty() {
+            return Ok(());
+        }
+        self.set_configs(&configs)
+    }
+
+    /// Returns the block size the device sends in flow control frames
+    pub fn iso15765_bs(&self) -> Result<u8, Error> {
+        Ok(self.get_config(ConfigId::ISO15765_BS)? as u8)
+    }
+
+    /// Returns the separation time the device sends in flow control frames
+    pub fn iso15765_stmin(&self) -> Result<u8, Error> {
+        Ok(self.get_config(ConfigId::ISO15765_STMIN)? as u8)
+    }
+
+    /// Returns the maximum number of wait frames the device accepts when transmitting
+    pub fn iso15765_wft_max(&self) -> Result<u8, Error> {
+        Ok(self.get_config(ConfigId::ISO15765_WFT_MAX)? as u8)
+    }
+
+    /// Overrides the block size and separation time the device uses when transmitting, instead
+    /// of the ones the ECU sends in its flow control frames. `None` goes back to the ECU's value.
+    /// Not every driver supports these parameters.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// ISO 15765 or `stmin_tx` is a reserved separation time.
+    pub fn set_iso15765_tx_params(
+        &self,
+        bs_tx: Option<u8>,
+        stmin_tx: Option<u8>,
+    ) -> Result<(), Error> {
+        self.require_protocol(&[Protocol::ISO15765], "ISO 15765 parameters")?;
+        if let Some(stmin) = stmin_tx.filter(|&stmin| !valid_stmin(stmin)) {
+            return Err(Error::InvalidArgument(format!(
+                "separation time {:#04X} is reserved by ISO 15765-2",
+                stmin
+            )));
+        }
+        let value = |value: Option<u8>| value.map_or(USE_ECU_FLOW_CONTROL, u32::from);
+        self.set_configs(&[
+            (ConfigId::BS_TX, value(bs_tx)),
+            (ConfigId::STMIN_TX, value(stmin_tx)),
+        ])
+    }
+
+    /// Sets the P timing parameters that are `Some` with one `SET_CONFIG` call.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// ISO 9141 or ISO 14230.
+    pub fn set_kline_timings(&self, timings: &KLineTimings) -> Result<(), Error> {
+        self.require_protocol(&[Protocol::ISO9141, Protocol::ISO14230], "K-line timings")?;
+        let configs: Vec<(ConfigId, u32)> = timings
+            .params()
+            .iter()
+            .filter_map(|&(id, value)| Some((id, value? as u32)))
+            .collect();
+        if configs.is_empty() {
+            return Ok(());
+        }
+        self.set_configs(&configs)
+    }
+
+    /// Reads the P timing parameters. Parameters the driver doesn't report are `None`.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// ISO 9141 or ISO 14230.
+    pub fn kline_timings(&self) -> Result<KLineTimings, Error> {
+        self.require_protocol(&[Protocol::ISO9141, Protocol::ISO14230], "K-line timings")?;
+        let mut timings = KLineTimings::default();
+        let ids: Vec<ConfigId> = timings.params().iter().map(|&(id, _)| id).collect();
+        for (id, value) in self.get_configs(&ids) {
+            if let (Some(field), Ok(value)) = (timings.field(id), value) {
+                *field = Some(value as u16);
+            }
+        }
+        Ok(timings)
+    }
+
+    /// Sets the initialization timing parameters that are `Some` with one `SET_CONFIG` call.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// ISO 9141 or ISO 14230 or a timing is longer than 65535 milliseconds.
+    pub fn set_init_timings(&self, timings: &InitTimings) -> Result<(), Error> {
+        self.require_protocol(
+            &[Protocol::ISO9141, Protocol::ISO14230],
+            "initialization timings",
+        )?;
+        let mut configs = Vec::new();
+        for &(id, time) in timings.params().iter() {
+            let time = match time {
+                Some(time) => time,
+                None => continue,
+            };
+            let millis = (time.as_micros() + 500) / 1000;
+            if millis > u16::MAX as u128 {
+                return Err(Error::InvalidArgument(format!(
+                    "{} is {:?}, the maximum is 65535ms",
+                    id, time
+                )));
+            }
+            configs.push((id, millis as u32));
+        }
+        if configs.is_empty() {
+            return Ok(());
+        }
+        self.set_configs(&configs)
+    }
+
+    /// Reads the initialization timing parameters. Parameters the driver doesn't report are `None`.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// ISO 9141 or ISO 14230.
+    pub fn init_timings(&self) -> Result<InitTimings, Error> {
+        self.require_protocol(
+            &[Protocol::ISO9141, Protocol::ISO14230],
+            "initialization timings",
+        )?;
+        let mut timings = InitTimings::default();
+        let ids: Vec<ConfigId> = timings.params().iter().map(|&(id, _)| id).collect();
+        for (id, value) in self.get_configs(&ids) {
+            if let (Some(field), Ok(value)) = (timings.field(id), value) {
+                *field = Some(Duration::from_millis(value.into()));
+            }
+        }
+        Ok(timings)
+    }
+
+    /// Sets the address the device answers to on a J1850PWM channel. Messages won't be
+    /// transmitted correctly until it is set.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// J1850PWM.
+    pub fn set_node_address(&self, address: u8) -> Result<(), Error> {
+        self.require_protocol(&[Protocol::J1850PWM], "the node address")?;
+        self.set_config(ConfigId::NODE_ADDRESS, address.into())
+    }
+
+    /// Returns the address the device answers to on a J1850PWM channel
+    pub fn node_address(&self) -> Result<u8, Error> {
+        self.require_protocol(&[Protocol::J1850PWM], "the node address")?;
+        Ok(self.get_config(ConfigId::NODE_ADDRESS)? as u8)
+    }
+
+    /// Sets the lines a J1850PWM channel uses.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// J1850PWM.
+    pub fn set_network_line(&self, line: NetworkLine) -> Result<(), Error> {
+        self.require_protocol(&[Protocol::J1850PWM], "the network line")?;
+        self.set_config(ConfigId::NETWORK_LINE, line as u32)
+    }
+
+    /// Returns the lines a J1850PWM channel uses. Returns [`Error::InvalidIoctlValue`] if the
+    /// driver reports a value J2534 doesn't define.
+    pub fn network_line(&self) -> Result<NetworkLine, Error> {
+        self.require_protocol(&[Protocol::J1850PWM], "the network line")?;
+        let value = self.get_config(ConfigId::NETWORK_LINE)?;
+        match value {
+            0 => Ok(NetworkLine::Normal),
+            1 => Ok(NetworkLine::Plus),
+            2 => Ok(NetworkLine::Minus),
+            _ => Err(Error::InvalidIoctlValue),
+        }
+    }
+
+    /// Sets whether an ISO 15765 channel also receives and transmits raw CAN frames. Raw frames
+    /// are sent by writing messages with [`Protocol::CAN`] as their protocol ID.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use
+    /// ISO 15765.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, MixedFormat, Protocol, RxEvent};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    /// channel.set_mixed_format(MixedFormat::On).unwrap();
+    ///
+    /// match channel.read_event(Duration::from_secs(1)).unwrap() {
+    ///     RxEvent::Message(msg) => println!("ISO-TP: {:02X?}", msg.isotp_message()),
+    ///     RxEvent::RawFrame(msg) => println!("CAN: {:02X?}", msg.can_message()),
+    ///     _ => {}
+    /// }
+    /// ```
+    pub fn set_mixed_format(&self, mode: MixedFormat) -> Result<(), Error> {
+        self.require_protocol(&[Protocol::ISO15765], "the mixed format")?;
+        self.set_config(ConfigId::CAN_MIXED_FORMAT, mode as u32)
+    }
+
+    /// Returns which frames an ISO 15765 channel passes on besides ISO-TP messages. Returns
+    /// [`Error::InvalidIoctlValue`] if the driver reports a value J2534-2 doesn't define.
+    pub fn mixed_format(&self) -> Result<MixedFormat, Error> {
+        self.require_protocol(&[Protocol::ISO15765], "the mixed format")?;
+        let value = self.get_config(ConfigId::CAN_MIXED_FORMAT)?;
+        match value {
+            0 => Ok(MixedFormat::Off),
+            1 => Ok(MixedFormat::On),
+            2 => Ok(MixedFormat::AllFrames),
+            _ => Err(Error::InvalidIoctlValue),
+        }
+    }
+
+    /// Selects the J1962 connector pins a J2534-2 pin-selected channel uses, like
+    /// [`Protocol::ISO15765_PS`]. The driver won't transmit or receive on the channel until the
+    /// pins are set. Use [`ChannelBuilder::pins`](crate::ChannelBuilder::pins) to set them right
+    /// after connecting.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the channel doesn't use a
+    /// pin-selected protocol or the pins aren't on the connector. Drivers return
+    /// [`Error::PinInvalid`] for pins that can't carry the protocol.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, PinPair, Protocol};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// // A second CAN bus on pins 3 and 11
+    /// let channel = device.connect(Protocol::ISO15765_PS, ConnectFlags::NONE, 500000).unwrap();
+    /// channel.set_j1962_pins(PinPair::new(3, 11)).unwrap();
+    /// ```
+    pub fn set_j1962_pins(&self, pins: PinPair) -> Result<(), Error> {
+        self.require_pin_selected()?;
+        pins.check()?;
+        self.set_config(ConfigId::J1962_PINS, pins.value())
+    }
+
+    /// Returns the J1962 connector pins a J2534-2 pin-selected channel uses. Returns
+    /// [`Error::InvalidIoctlValue`] if the driver reports a value that isn't a pin pair.
+    pub fn j1962_pins(&self) -> Result<PinPair, Error> {
+        self.require_pin_selected()?;
+        let value = self.get_config(ConfigId::J1962_PINS)?;
+        PinPair::from_value(value).ok_or(Error::InvalidIoctlValue)
+    }
+
+    fn require_pin_selected(&self) -> Result<(), Error> {
+        match self.protocol {
+            Some(protocol) if protocol.is_pin_selected() => Ok(()),
+            _ => Err(Error::InvalidArgument(
+                "J1962 pins can only be configured on J2534-2 pin-selected channels".to_string(),
+            )),
+        }
+    }
+
+    /// Reads every J2534-1 configuration parameter that applies to the channel's protocol, for
+    /// example to include in a bug report. Parameters the driver rejects are recorded as `None`
+    /// without affecting the others.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    /// print!("{}", channel.config_snapshot());
+    /// ```
+    pub fn config_snapshot(&self) -> ConfigSnapshot {
+        let ids: Vec<ConfigId> = STANDARD_CONFIG_IDS
+            .iter()
+            .copied()
+            .filter(|id| match self.protocol {
+                Some(protocol) => id.applies_to(protocol),
+                None => true,
+            })
+            .collect();
+        ConfigSnapshot {
+            entries: self
+                .get_configs(&ids)
+                .into_iter()
+                .map(|(id, value)| (id, value.ok()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, ConnectFlags};
+
+    #[test]
+    fn stmin_values() {
+        for stmin in [0x00, 0x7F, 0xF1, 0xF9] {
+            assert!(valid_stmin(stmin));
+        }
+        for stmin in [0x80, 0xF0, 0xFA, 0xFF] {
+            assert!(!valid_stmin(stmin));
+        }
+    }
+
+    #[test]
+    fn iso15765_params() {
+        let device = mock::open();
+        let channel = device
+            .connect(Protocol::ISO15765, ConnectFlags::empty(), 500000)
+            .unwrap();
+        channel
+            .set_iso15765_params(Some(8), Some(0xF5), None)
+            .unwrap();
+        assert_eq!(channel.iso15765_bs().unwrap(), 8);
+        assert_eq!(channel.iso15765_stmin().unwrap(), 0xF5);
+        assert!(!mock::channel(channel.id())
+            .configs
+            .contains_key(&(ConfigId::ISO15765_WFT_MAX as u32)));
+        assert!(channel.set_iso15765_params(None, Some(0x80), None).is_err());
+
+        channel.set_iso15765_tx_params(Some(0), None).unwrap();
+        let configs = mock::channel(channel.id()).configs;
+        assert_eq!(configs[&(ConfigId::BS_TX as u32)], 0);
+        assert_eq!(configs[&(ConfigId::STMIN_TX as u32)], USE_ECU_FLOW_CONTROL);
+        drop(channel);
+
+        let channel = device
+            .connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+            .unwrap();
+        assert!(matches!(
+            channel.set_iso15765_params(Some(8), None, None),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn kline_timing_units() {
+        let timings = KLineTimings::default()
+            .with(ConfigId::P3_MIN, Duration::from_millis(20))
+            .unwrap()
+            .with(ConfigId::P1_MAX, Duration::from_micros(1249))
+            .unwrap()
+            .with(ConfigId::P4_MIN, Duration::from_micros(1250))
+            .unwrap();
+        assert_eq!(timings.p3_min, Some(40));
+        assert_eq!(timings.p1_max, Some(2));
+        assert_eq!(timings.p4_min, Some(3));
+        assert_eq!(timings.p2_max, None);
+
+        let max = Duration::from_micros(32767500);
+        assert_eq!(
+            KLineTimings::default()
+                .with(ConfigId::P2_MAX, max)
+                .unwrap()
+                .p2_max,
+            Some(u16::MAX)
+        );
+        assert!(KLineTimings::default()
+            .with(ConfigId::P2_MAX, max + Duration::from_millis(1))
+            .is_err());
+        assert!(KLineTimings::default()
+            .with(ConfigId::W0, Duration::from_millis(1))
+            .is_err());
+    }
+
+    #[test]
+    fn kline_timings_round_trip() {
+        let device = mock::open();
+        let channel = device
+            .connect(Protocol::ISO14230, ConnectFlags::empty(), 10400)
+            .unwrap();
+        let timings = KLineTimings::iso14230_defaults();
+        channel.set_kline_timings(&timings).unwrap();
+        assert_eq!(channel.kline_timings().unwrap(), timings);
+
+        // Only the parameters that are set are written
+        let partial = KLineTimings {
+            p3_min: Some(20),
+            ..KLineTimings::default()
+        };
+        channel.set_kline_timings(&partial).unwrap();
+        let read = channel.kline_timings().unwrap();
+        assert_eq!(read.p3_min, Some(20));
+        assert_eq!(read.p3_max, Some(10000));
+        drop(channel);
+
+        let channel = device
+            .connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+            .unwrap();
+        assert!(channel.kline_timings().is_err());
+    }
+
+    #[test]
+    fn init_timings_round_trip() {
+        let channel = mock::connect(Protocol::ISO9141, ConnectFlags::empty(), 10400);
+        let timings = InitTimings::spec_defaults();
+        channel.set_init_timings(&timings).unwrap();
+        assert_eq!(channel.init_timings().unwrap(), timings);
+
+        // Rounded to the nearest millisecond
+        let rounded = InitTimings {
+            w4: Some(Duration::from_micros(24500)),
+            ..InitTimings::default()
+        };
+        channel.set_init_timings(&rounded).unwrap();
+        assert_eq!(
+            channel.init_timings().unwrap().w4,
+            Some(Duration::from_millis(25))
+        );
+
+        let too_long = InitTimings {
+            tidle: Some(Duration::from_secs(66)),
+            ..InitTimings::default()
+        };
+        assert!(matches!(
+            channel.set_init_timings(&too_long),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn j1850pwm_node_address_and_line() {
+        let device = mock::open();
+        let channel = device
+            .connect(Protocol::J1850PWM, ConnectFlags::empty(), 41600)
+            .unwrap();
+        channel.set_node_address(0xF1).unwrap();
+        assert_eq!(channel.node_address().unwrap(), 0xF1);
+        channel.set_network_line(NetworkLine::Minus).unwrap();
+        assert_eq!(channel.network_line().unwrap(), NetworkLine::Minus);
+
+        mock::with_channel(channel.id(), |channel| {
+            channel.configs.insert(ConfigId::NETWORK_LINE as u32, 3)
+        });
+        assert!(matches!(
+            channel.network_line(),
+            Err(Error::InvalidIoctlValue)
+        ));
+        drop(channel);
+
+        let channel = device
+            .connect(Protocol::J1850VPW, ConnectFlags::empty(), 10400)
+            .unwrap();
+        assert!(matches!(
+            channel.set_node_address(0xF1),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(channel.network_line().is_err());
+    }
+
+    #[test]
+    fn snapshot_display() {
+        let snapshot = ConfigSnapshot {
+            entries: vec![
+                (ConfigId::DATA_RATE, Some(500000)),
+                (ConfigId::LOOPBACK, None),
+            ],
+        };
+        assert_eq!(
+            snapshot.to_string(),
+            "DATA_RATE      500000  0x0007A120\nLOOPBACK   not reported\n"
+        );
+        assert_eq!(ConfigSnapshot { entries: vec![] }.to_string(), "");
+    }
+
+    #[test]
+    fn snapshot_applies_to_protocol() {
+        let channel = mock::can_channel();
+        let snapshot = channel.config_snapshot();
+        assert!(snapshot
+            .entries
+            .iter()
+            .all(|(id, value)| id.applies_to(Protocol::CAN) && value.is_some()));
+        assert!(!snapshot
+            .entries
+            .iter()
+            .any(|(id, _)| *id == ConfigId::ISO15765_BS));
+    }
+
+    #[test]
+    fn pin_pair_values() {
+        let pins = PinPair::new(6, 14);
+        assert_eq!(pins.value(), 0x060E);
+        assert_eq!(PinPair::from_value(0x060E), Some(pins));
+        assert_eq!(PinPair::from_value(0x0700), Some(PinPair::new(7, 0)));
+        assert_eq!(PinPair::from_value(0x10000), None);
+
+        assert!(pins.check().is_ok());
+        assert!(PinPair::new(7, 0).check().is_ok());
+        for pins in [(0, 14), (17, 1), (1, 17), (6, 6)] {
+            assert!(PinPair::new(pins.0, pins.1).check().is_err());
+        }
+    }
+
+    #[test]
+    fn j1962_pins_round_trip() {
+        let device = mock::open();
+        let channel = device
+            .connect(Protocol::CAN_PS, ConnectFlags::empty(), 500000)
+            .unwrap();
+        channel.set_j1962_pins(PinPair::new(3, 11)).unwrap();
+        assert_eq!(channel.j1962_pins().unwrap(), PinPair::new(3, 11));
+        assert!(channel.set_j1962_pins(PinPair::new(3, 3)).is_err());
+
+        mock::with_channel(channel.id(), |channel| {
+            channel.configs.insert(ConfigId::J1962_PINS as u32, 0x10000);
+        });
+        assert!(matches!(
+            channel.j1962_pins(),
+            Err(Error::InvalidIoctlValue)
+        ));
+        drop(channel);
+
+        let channel = device
+            .connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+            .unwrap();
+        assert!(channel.j1962_pins().is_err());
+    }
+
+    #[test]
+    fn mixed_format_round_trip() {
+        let device = mock::open();
+        let channel = device
+            .connect(Protocol::ISO15765, ConnectFlags::empty(), 500000)
+            .unwrap();
+        assert_eq!(channel.mixed_format().unwrap(), MixedFormat::Off);
+        channel.set_mixed_format(MixedFormat::AllFrames).unwrap();
+        assert_eq!(channel.mixed_format().unwrap(), MixedFormat::AllFrames);
+        assert_eq!(
+            mock::channel(channel.id()).configs[&(ConfigId::CAN_MIXED_FORMAT as u32)],
+            2
+        );
+
+        mock::with_channel(channel.id(), |channel| {
+            channel.configs.insert(ConfigId::CAN_MIXED_FORMAT as u32, 3);
+        });
+        assert!(matches!(
+            channel.mixed_format(),
+            Err(Error::InvalidIoctlValue)
+        ));
+        drop(channel);
+
+        let channel = device
+            .connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+            .unwrap();
+        assert!(matches!(
+            channel.set_mixed_format(MixedFormat::On),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            channel.mixed_format(),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(mock::channel(channel.id()).configs.is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+//! Enumeration of connected Drew Technologies devices with `PassThruGetNextCarDAQ`
+
+use std::ffi::{CStr, CString};
+
+use crate::{Error, Interface};
+
+/// Stops the enumeration if a driver never reports the end of the list
+const MAX_DREWTECH_DEVICES: usize = 64;
+
+/// A device found by [`Interface::enumerate_drewtech_devices`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DrewtechDevice {
+    /// The name as reported by the driver, usually in the ANSI code page. Pass it to
+    /// [`Interface::open`] to open the device.
+    pub raw_name: CString,
+    /// The name converted to UTF-8 for display, with invalid sequences replaced
+    pub name: String,
+    /// The firmware version as reported by the driver
+    pub version: u32,
+    /// The address of the device, like a USB port or an IP address
+    pub address: String,
+}
+
+/// Copies a string owned by the driver, which may be overwritten by the next call
+unsafe fn copy_driver_string(s: *const libc::c_char) -> CString {
+    if s.is_null() {
+        return CString::default();
+    }
+    CStr::from_ptr(s).to_owned()
+}
+
+impl Interface {
+    /// Returns the connected devices of a Drew Technologies driver, like the units of a CarDAQ,
+    /// using the `PassThruGetNextCarDAQ` extension that some other vendors implement too.
+    ///
+    /// Returns [`Error::NotSupported`] if the driver doesn't export `PassThruGetNextCarDAQ`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let found = interface.enumerate_drewtech_devices().unwrap();
+    /// if let Some(first) = found.first() {
+    ///     let device = interface.open(first.raw_name.clone()).unwrap();
+    /// }
+    /// ```
+    pub fn enumerate_drewtech_devices(&self) -> Result<Vec<DrewtechDevice>, Error> {
+        let next = match self.c_pass_thru_get_next_car_daq {
+            Some(next) => next,
+            None => return Err(Error::NotSupported),
+        };
+
+        // The driver keeps the position in the list, so the whole enumeration is one call
+        let (devices, res) = self.call(|| unsafe {
+            // Passing null restarts the enumeration
+            let res = next(
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            );
+            if res != 0 {
+                return (Vec::new(), res);
+            }
+
+            let mut devices = Vec::new();
+            while devices.len() < MAX_DREWTECH_DEVICES {
+                let mut name: *mut libc::c_char = std::ptr::null_mut();
+                let mut version: u32 = 0;
+                let mut address: *mut libc::c_char = std::ptr::null_mut();
+                let res = next(&mut name, &mut version, &mut address);
+                match Error::from_code(res) {
+                    Error::NoError => {}
+                    // Some drivers report the end of the list as an error
+                    Error::BufferEmpty => break,
+                    _ => return (devices, res),
+                }
+                // The end of the list is reported with a null or empty name
+                if name.is_null() || *name == 0 {
+                    break;
+                }
+                let raw_name = copy_driver_string(name);
+                devices.push(DrewtechDevice {
+                    name: raw_name.to_string_lossy().into_owned(),
+                    raw_name,
+                    version,
+                    address: copy_driver_string(address).to_string_lossy().into_owned(),
+                });
+            }
+            (devices, 0)
+        });
+        if res != 0 {
+            return Err(self.error(res));
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use std::cell::RefCell;
+
+    /// The name, version and address of a device of the mock `PassThruGetNextCarDAQ`
+    type CarDaq = (CString, u32, CString);
+
+    thread_local! {
+        /// The devices of the mock `PassThruGetNextCarDAQ` and the position in the list
+        static CAR_DAQS: RefCell<(Vec<CarDaq>, usize)> = const { RefCell::new((Vec::new(), 0)) };
+    }
+
+    unsafe extern "stdcall" fn get_next_car_daq(
+        name: *mut *mut libc::c_char,
+        version: *mut u32,
+        address: *mut *mut libc::c_char,
+    ) -> i32 {
+        CAR_DAQS.with(|car_daqs| {
+            let (devices, next) = &mut *car_daqs.borrow_mut();
+            if name.is_null() {
+                *next = 0;
+                return 0;
+            }
+            match devices.get(*next) {
+                Some((device_name, device_version, device_address)) => {
+                    *name = device_name.as_ptr() as *mut libc::c_char;
+                    *version = *device_version;
+                    *address = device_address.as_ptr() as *mut libc::c_char;
+                    *next += 1;
+                }
+                None => *name = std::ptr::null_mut(),
+            }
+            0
+        })
+    }
+
+    #[test]
+    fn enumerate_keeps_raw_names() {
+        let mut interface = mock::interface();
+        assert!(matches!(
+            interface.enumerate_drewtech_devices(),
+            Err(Error::NotSupported)
+        ));
+
+        interface.c_pass_thru_get_next_car_daq = Some(get_next_car_daq);
+        CAR_DAQS.with(|car_daqs| {
+            car_daqs.borrow_mut().0 = vec![
+                (
+                    CString::new("CarDAQ-Plus 3 1234").unwrap(),
+                    0x0301,
+                    CString::new("USB").unwrap(),
+                ),
+                (
+                    CString::new(&b"Ger\xE4t"[..]).unwrap(),
+                    2,
+                    CString::new("192.168.0.2").unwrap(),
+                ),
+            ]
+        });
+        let found = interface.enumerate_drewtech_devices().unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].name, "CarDAQ-Plus 3 1234");
+        assert_eq!(found[0].version, 0x0301);
+        assert_eq!(found[0].address, "USB");
+        assert_eq!(found[1].raw_name.as_bytes(), b"Ger\xE4t");
+        assert_eq!(found[1].name, "Ger\u{FFFD}t");
+
+        // The enumeration restarts on every call
+        assert_eq!(interface.enumerate_drewtech_devices().unwrap(), found);
+
+        let device = interface.open(found[1].raw_name.clone()).unwrap();
+        assert_eq!(
+            mock::device(device.id()).name.as_deref(),
+            Some(&b"Ger\xE4t"[..])
+        );
+    }
+}
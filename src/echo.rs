@@ -0,0 +1,172 @@
+//! Handling of transmit echoes by the higher-level read helpers
+
+use std::collections::VecDeque;
+
+use crate::{Channel, PassThruMsg};
+
+/// What the higher-level read helpers do with echoes of transmitted messages, which are received
+/// when loopback is enabled. Set with [`Channel::set_echo_policy`].
+///
+/// The policy applies to [`Channel::read_once`] and the helpers built on it, the message
+/// iterators and [`IsoTpChannel`](crate::IsoTpChannel). [`Channel::read`] and
+/// [`Channel::read_msgs`] always return every message.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EchoPolicy {
+    /// Return echoes like any other message
+    #[default]
+    Keep,
+    /// Discard echoes
+    Drop,
+    /// Keep echoes out of the received messages and queue them for [`Channel::take_tx_confirmations`]
+    Separate,
+}
+
+#[derive(Default)]
+pub(crate) struct EchoState {
+    policy: EchoPolicy,
+    confirmations: VecDeque<PassThruMsg>,
+}
+
+impl<'a> Channel<'a> {
+    /// Sets what the higher-level read helpers do with echoes of transmitted messages
+    pub fn set_echo_policy(&self, policy: EchoPolicy) {
+        self.echo.lock().unwrap().policy = policy;
+    }
+
+    /// Returns what the higher-level read helpers do with echoes of transmitted messages
+    pub fn echo_policy(&self) -> EchoPolicy {
+        self.echo.lock().unwrap().policy
+    }
+
+    /// Returns the echoes queued with [`EchoPolicy::Separate`] since the last call, oldest first
+    pub fn take_tx_confirmations(&self) -> Vec<PassThruMsg> {
+        self.echo.lock().unwrap().confirmations.drain(..).collect()
+    }
+
+    /// Applies the echo policy to a received message. Returns `None` if the message was an echo
+    /// that was dropped or queued.
+    pub(crate) fn apply_echo_policy(&self, msg: PassThruMsg) -> Option<PassThruMsg> {
+        if !msg.transmitted() {
+            return Some(msg);
+        }
+        let mut echo = self.echo.lock().unwrap();
+        match echo.policy {
+            EchoPolicy::Keep => Some(msg),
+            EchoPolicy::Drop => None,
+            EchoPolicy::Separate => {
+                echo.confirmations.push_back(msg);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, ConnectFlags, Protocol, RxStatus};
+    use std::time::Duration;
+
+    fn echo(id: u32) -> PassThruMsg {
+        let mut msg = PassThruMsg::new_can(id, &[]);
+        msg.rx_status = RxStatus::TX_MSG_TYPE.bits();
+        msg
+    }
+
+    fn id(msg: &PassThruMsg) -> u32 {
+        u32::from_be_bytes([msg.data[0], msg.data[1], msg.data[2], msg.data[3]])
+    }
+
+    /// Queues an echo on 0x7E0 between responses on 0x7E8 and 0x7E9
+    fn push_traffic(channel: &Channel) {
+        mock::push_rx(channel.id(), PassThruMsg::new_can(0x7E8, &[]));
+        mock::push_rx(channel.id(), echo(0x7E0));
+        mock::push_rx(channel.id(), PassThruMsg::new_can(0x7E9, &[]));
+    }
+
+    fn read_ids(channel: &Channel) -> Vec<u32> {
+        let mut ids = Vec::new();
+        loop {
+            match channel.read_once(Duration::ZERO) {
+                Ok(msg) => ids.push(id(&msg)),
+                Err(crate::Error::BufferEmpty) => return ids,
+                Err(err) => panic!("read failed: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn policies() {
+        let channel = mock::can_channel();
+        assert_eq!(channel.echo_policy(), EchoPolicy::Keep);
+        push_traffic(&channel);
+        assert_eq!(read_ids(&channel), [0x7E8, 0x7E0, 0x7E9]);
+
+        channel.set_echo_policy(EchoPolicy::Drop);
+        assert_eq!(channel.echo_policy(), EchoPolicy::Drop);
+        push_traffic(&channel);
+        assert_eq!(read_ids(&channel), [0x7E8, 0x7E9]);
+        assert!(channel.take_tx_confirmations().is_empty());
+
+        channel.set_echo_policy(EchoPolicy::Separate);
+        push_traffic(&channel);
+        mock::push_rx(channel.id(), echo(0x7DF));
+        assert_eq!(read_ids(&channel), [0x7E8, 0x7E9]);
+        let confirmations: Vec<u32> = channel.take_tx_confirmations().iter().map(id).collect();
+        assert_eq!(confirmations, [0x7E0, 0x7DF]);
+        assert!(channel.take_tx_confirmations().is_empty());
+
+        // The raw reads return every message
+        push_traffic(&channel);
+        let ids: Vec<u32> = channel
+            .read_msgs(8, Duration::ZERO)
+            .unwrap()
+            .iter()
+            .map(id)
+            .collect();
+        assert_eq!(ids, [0x7E8, 0x7E0, 0x7E9]);
+        assert!(channel.take_tx_confirmations().is_empty());
+    }
+
+    #[test]
+    fn iterators_follow_the_policy() {
+        let channel = mock::can_channel();
+        channel.set_echo_policy(EchoPolicy::Separate);
+        push_traffic(&channel);
+        let ids: Vec<u32> = channel.try_iter().map(|msg| id(&msg.unwrap())).collect();
+        assert_eq!(ids, [0x7E8, 0x7E9]);
+        assert_eq!(channel.take_tx_confirmations().len(), 1);
+
+        channel.set_echo_policy(EchoPolicy::Drop);
+        push_traffic(&channel);
+        let ids: Vec<u32> = channel
+            .messages(Duration::ZERO)
+            .take(2)
+            .map(|msg| id(&msg.unwrap()))
+            .collect();
+        assert_eq!(ids, [0x7E8, 0x7E9]);
+        assert!(channel.take_tx_confirmations().is_empty());
+    }
+
+    #[test]
+    fn isotp_follows_the_policy() {
+        let device = mock::open();
+        let mut isotp = device
+            .connect_iso15765(500000, ConnectFlags::empty())
+            .unwrap();
+        isotp.add_pair(0x7E0, 0x7E8).unwrap();
+        isotp.channel().set_echo_policy(EchoPolicy::Separate);
+
+        let id = isotp.channel().id();
+        let mut sent = PassThruMsg::new_isotp(0x7E0, &[0x3E, 0x00]);
+        sent.rx_status = RxStatus::TX_MSG_TYPE.bits();
+        mock::push_rx(id, sent);
+        mock::push_rx(id, PassThruMsg::new_isotp(0x7E8, &[0x7E, 0x00]));
+        let (rx_id, payload) = isotp.recv(Duration::from_millis(10)).unwrap();
+        assert_eq!((rx_id, payload), (0x7E8, vec![0x7E, 0x00]));
+        let confirmations = isotp.channel().take_tx_confirmations();
+        assert_eq!(confirmations.len(), 1);
+        let protocol_id = confirmations[0].protocol_id;
+        assert_eq!(protocol_id, Protocol::ISO15765 as u32);
+    }
+}
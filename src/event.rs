@@ -0,0 +1,239 @@
+//! Classification of received messages by their rx status
+
+use std::time::Duration;
+
+use num_traits::FromPrimitive;
+
+use crate::{Channel, Error, PassThruMsg, Protocol, RxStatus};
+
+/// A message received from the device, classified by its rx status
+#[derive(Debug)]
+pub enum RxEvent {
+    /// A message received from the vehicle network, or an echo of a transmitted message if
+    /// [`PassThruMsg::transmitted`] returns true
+    Message(PassThruMsg),
+    /// The first frame of an ISO 15765 message was received on `id`. The complete message follows.
+    ///
+    /// `expected_len` is the length announced by the first frame, if the driver included it after the ID.
+    StartOfMessage { id: u32, expected_len: Option<u32> },
+    /// A message was transmitted. The data contains the ID the message was sent on.
+    TxDone(PassThruMsg),
+    /// A break was received on an SCI or K-line channel
+    Break { timestamp: u32 },
+    /// A raw CAN frame received on an ISO 15765 channel in mixed format mode, or its echo if
+    /// [`PassThruMsg::transmitted`] returns true. See [`Channel::set_mixed_format`].
+    RawFrame(PassThruMsg),
+}
+
+impl From<PassThruMsg> for RxEvent {
+    fn from(msg: PassThruMsg) -> RxEvent {
+        let rx_status = RxStatus::from_bits_truncate(msg.rx_status);
+        let data = &msg.data[..(msg.data_size as usize).min(msg.data.len())];
+        if rx_status.contains(RxStatus::TX_DONE) {
+            RxEvent::TxDone(msg)
+        } else if rx_status.contains(RxStatus::RX_BREAK) {
+            RxEvent::Break {
+                timestamp: msg.timestamp,
+            }
+        } else if rx_status.contains(RxStatus::START_OF_MESSAGE) && data.len() >= 4 {
+            let id = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            // A first frame starts with 0x1 in the upper nibble followed by a 12-bit length. With
+            // extended addressing the address byte comes first.
+            let pci_start = if rx_status.contains(RxStatus::ISO15765_EXT_ADDR) {
+                5
+            } else {
+                4
+            };
+            let expected_len = match data.get(pci_start..pci_start + 2) {
+                Some(&[pci, len]) if pci >> 4 == 1 => {
+                    Some((((pci & 0x0F) as u32) << 8) | len as u32)
+                }
+                _ => None,
+            };
+            RxEvent::StartOfMessage { id, expected_len }
+        } else {
+            RxEvent::Message(msg)
+        }
+    }
+}
+
+impl<'a> Channel<'a> {
+    /// Reads a single message and classifies it. Use [`Channel::read_once`] to get messages verbatim.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol, RxEvent};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// match channel.read_event(Duration::from_secs(1)).unwrap() {
+    ///     RxEvent::Message(msg) => println!("{:?}", msg.isotp_message()),
+    ///     RxEvent::StartOfMessage { id, .. } => println!("receiving on {:X}", id),
+    ///     _ => {}
+    /// }
+    /// ```
+    pub fn read_event(&self, timeout: Duration) -> Result<RxEvent, Error> {
+        self.read_once(timeout).map(|msg| self.classify(msg))
+    }
+
+    /// Classifies a message received on this channel. Unlike `RxEvent::from`, this knows the
+    /// channel's protocol, so frames passed through in mixed format mode are told apart from
+    /// ISO-TP messages.
+    fn classify(&self, msg: PassThruMsg) -> RxEvent {
+        let iso15765 = self.protocol.map(Protocol::base) == Some(Protocol::ISO15765);
+        let rx_status = RxStatus::from_bits_truncate(msg.rx_status);
+        // Drivers set the protocol ID of frames passed through in mixed format mode to CAN
+        if iso15765
+            && Protocol::from_u32(msg.protocol_id).map(Protocol::base) == Some(Protocol::CAN)
+            && !rx_status.intersects(RxStatus::TX_DONE | RxStatus::START_OF_MESSAGE)
+        {
+            RxEvent::RawFrame(msg)
+        } else {
+            RxEvent::from(msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, ConnectFlags};
+
+    fn with_status(mut msg: PassThruMsg, rx_status: RxStatus) -> PassThruMsg {
+        msg.rx_status = rx_status.bits();
+        msg
+    }
+
+    #[test]
+    fn first_frame_indication() {
+        let msg = PassThruMsg::new_isotp(0x7E8, &[0x10, 0x14]);
+        match RxEvent::from(with_status(msg, RxStatus::START_OF_MESSAGE)) {
+            RxEvent::StartOfMessage { id, expected_len } => {
+                assert_eq!(id, 0x7E8);
+                assert_eq!(expected_len, Some(0x014));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Most drivers only report the ID
+        let msg = PassThruMsg::new_isotp(0x18DAF110, &[]);
+        assert!(matches!(
+            RxEvent::from(with_status(msg, RxStatus::START_OF_MESSAGE)),
+            RxEvent::StartOfMessage {
+                id: 0x18DAF110,
+                expected_len: None
+            }
+        ));
+
+        // Too short to hold an ID
+        let mut msg = with_status(
+            PassThruMsg::new(Protocol::ISO15765),
+            RxStatus::START_OF_MESSAGE,
+        );
+        msg.data_size = 2;
+        assert!(matches!(RxEvent::from(msg), RxEvent::Message(_)));
+    }
+
+    #[test]
+    fn other_statuses() {
+        let msg = PassThruMsg::new_isotp(0x7E0, &[]);
+        assert!(matches!(
+            RxEvent::from(with_status(msg, RxStatus::TX_DONE)),
+            RxEvent::TxDone(_)
+        ));
+        let mut msg = with_status(PassThruMsg::new(Protocol::ISO9141), RxStatus::RX_BREAK);
+        msg.timestamp = 1234;
+        assert!(matches!(
+            RxEvent::from(msg),
+            RxEvent::Break { timestamp: 1234 }
+        ));
+        let msg = PassThruMsg::new_isotp(0x7E8, &[0x62]);
+        assert!(matches!(RxEvent::from(msg), RxEvent::Message(_)));
+    }
+
+    #[test]
+    fn combined_statuses() {
+        // Indications for transmitted messages carry TX_MSG_TYPE as well
+        let msg = PassThruMsg::new_isotp(0x7E0, &[0x10, 0x20]);
+        let status = RxStatus::TX_MSG_TYPE | RxStatus::START_OF_MESSAGE;
+        assert!(matches!(
+            RxEvent::from(with_status(msg, status)),
+            RxEvent::StartOfMessage {
+                id: 0x7E0,
+                expected_len: Some(0x020)
+            }
+        ));
+        let msg = PassThruMsg::new_isotp(0x7E0, &[]);
+        let status = RxStatus::TX_MSG_TYPE | RxStatus::TX_DONE | RxStatus::CAN_29BIT_ID;
+        assert!(matches!(
+            RxEvent::from(with_status(msg, status)),
+            RxEvent::TxDone(_)
+        ));
+        let msg = PassThruMsg::new_isotp(0x7E0, &[0x3E, 0x00]);
+        match RxEvent::from(with_status(msg, RxStatus::TX_MSG_TYPE)) {
+            RxEvent::Message(msg) => assert!(msg.transmitted()),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // Error bits don't hide the indication, and are kept on messages
+        let msg = PassThruMsg::new_isotp(0x7E8, &[0x10, 0x0A]);
+        let status = RxStatus::START_OF_MESSAGE | RxStatus::ISO15765_PADDING_ERROR;
+        assert!(matches!(
+            RxEvent::from(with_status(msg, status)),
+            RxEvent::StartOfMessage {
+                id: 0x7E8,
+                expected_len: Some(0x00A)
+            }
+        ));
+        let msg = PassThruMsg::new_isotp(0x7E8, &[0x62]);
+        match RxEvent::from(with_status(msg, RxStatus::ISO15765_PADDING_ERROR)) {
+            RxEvent::Message(msg) => {
+                let rx_status = msg.rx_status;
+                assert_eq!(rx_status, RxStatus::ISO15765_PADDING_ERROR.bits());
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        // The extended address comes before the first frame's length
+        let msg = PassThruMsg::new_isotp(0x7E8, &[0x11, 0x10, 0x08]);
+        let status = RxStatus::START_OF_MESSAGE | RxStatus::ISO15765_EXT_ADDR;
+        assert!(matches!(
+            RxEvent::from(with_status(msg, status)),
+            RxEvent::StartOfMessage {
+                id: 0x7E8,
+                expected_len: Some(0x008)
+            }
+        ));
+    }
+
+    #[test]
+    fn mixed_format_frames() {
+        let channel = mock::connect(Protocol::ISO15765, ConnectFlags::empty(), 500000);
+        mock::push_rx(channel.id(), PassThruMsg::new_can(0x123, &[1, 2, 3]));
+        mock::push_rx(channel.id(), PassThruMsg::new_isotp(0x7E8, &[0x62]));
+        let echo = PassThruMsg::new_can(0x124, &[4]);
+        mock::push_rx(channel.id(), with_status(echo, RxStatus::TX_MSG_TYPE));
+        let indication = PassThruMsg::new_can(0x125, &[]);
+        mock::push_rx(channel.id(), with_status(indication, RxStatus::TX_DONE));
+        let timeout = Duration::from_millis(10);
+        assert!(matches!(
+            channel.read_event(timeout).unwrap(),
+            RxEvent::RawFrame(_)
+        ));
+        assert!(matches!(
+            channel.read_event(timeout).unwrap(),
+            RxEvent::Message(_)
+        ));
+        match channel.read_event(timeout).unwrap() {
+            RxEvent::RawFrame(msg) => assert!(msg.transmitted()),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(
+            channel.read_event(timeout).unwrap(),
+            RxEvent::TxDone(_)
+        ));
+    }
+}
@@ -0,0 +1,577 @@
+//! Guards for message filters
+
+use crate::can::{check_can_id, max_can_id};
+use crate::{
+    is_can_protocol, Channel, ConnectFlags, Error, FilterId, FilterType, PassThruMsg, Protocol,
+    TxFlags,
+};
+
+/// The longest mask, pattern or flow control message of a filter in bytes
+const MAX_FILTER_LEN: usize = 12;
+
+/// A message filter that is stopped when dropped. Created with [`Channel::msg_filter`].
+#[must_use = "the filter is stopped when the guard is dropped"]
+pub struct MessageFilter<'a> {
+    channel: &'a Channel<'a>,
+    id: FilterId,
+}
+
+impl<'a> Channel<'a> {
+    /// Starts a message filter that is stopped when the returned guard is dropped.
+    /// See [`Channel::start_message_filter`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, FilterType, Interface, PassThruMsg, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let mask = PassThruMsg::new_can(0xFFFF_FFFF, &[]);
+    /// let pattern = PassThruMsg::new_can(0x7E8, &[]);
+    /// let _filter = channel
+    ///     .msg_filter(FilterType::Pass, Some(&mask), Some(&pattern), None)
+    ///     .unwrap();
+    /// let msgs = channel.read_msgs(16, Duration::from_secs(1)).unwrap();
+    /// ```
+    pub fn msg_filter(
+        &'a self,
+        filter_type: FilterType,
+        mask_msg: Option<&PassThruMsg>,
+        pattern_msg: Option<&PassThruMsg>,
+        flow_control_msg: Option<&PassThruMsg>,
+    ) -> Result<MessageFilter<'a>, Error> {
+        let id = self.start_message_filter(filter_type, mask_msg, pattern_msg, flow_control_msg)?;
+        Ok(MessageFilter { channel: self, id })
+    }
+
+    /// Starts a pass filter that lets every message through, so that messages can be read right
+    /// after connecting. The mask and pattern are all zeroes and as long as the protocol requires:
+    /// four bytes for the CAN ID on CAN channels and one byte on other channels.
+    ///
+    /// Returns [`Error::InvalidArgument`] on ISO 15765 channels, which need flow control filters
+    /// such as [`Channel::iso15765_flow_filter`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let _filter = channel.pass_all_filter().unwrap();
+    /// let msgs = channel.read_msgs(16, Duration::from_secs(1)).unwrap();
+    /// ```
+    pub fn pass_all_filter(&'a self) -> Result<MessageFilter<'a>, Error> {
+        let id = self.start_pass_all_filter()?;
+        Ok(MessageFilter { channel: self, id })
+    }
+
+    /// Starts a pass filter that lets every message through
+    pub(crate) fn start_pass_all_filter(&self) -> Result<FilterId, Error> {
+        if self.protocol.map(Protocol::base) == Some(Protocol::ISO15765) {
+            return Err(Error::InvalidArgument(
+                "pass filters are not valid on ISO15765 channels".to_string(),
+            ));
+        }
+        let mut filter = self.new_msg();
+        filter.data_size = if is_can_protocol(self.protocol_id) {
+            4
+        } else {
+            1
+        };
+        if self.flags.contains(ConnectFlags::CAN_29_BIT_ID) {
+            filter = filter.tx_flags(TxFlags::CAN_29BIT_ID);
+        }
+        self.start_message_filter(FilterType::Pass, Some(&filter), Some(&filter), None)
+    }
+
+    /// Starts a pass filter for CAN frames whose ID matches `id` in the bits set in `mask`.
+    /// If `extended` is false, only 11-bit IDs are matched and `mask` is limited to 11 bits.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if `id` doesn't fit in an
+    /// 11-bit or 29-bit ID.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// // Receive the responses of all OBD-II ECUs, 0x7E8 to 0x7EF
+    /// let _filter = channel.can_pass_filter(0x7E8, 0x7F8, false).unwrap();
+    /// ```
+    pub fn can_pass_filter(
+        &'a self,
+        id: u32,
+        mask: u32,
+        extended: bool,
+    ) -> Result<MessageFilter<'a>, Error> {
+        self.can_filter(FilterType::Pass, id, mask, extended)
+    }
+
+    /// Starts a block filter for CAN frames whose ID matches `id` in the bits set in `mask`, like
+    /// [`Channel::can_pass_filter`].
+    ///
+    /// A block filter only removes messages that a pass filter lets through, since nothing is
+    /// received without one. This returns [`Error::InvalidArgument`] without calling the driver
+    /// if no pass filter was started through the channel, or if `id` doesn't fit in an 11-bit or
+    /// 29-bit ID.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// // Receive everything except 0x100 to 0x10F
+    /// let _pass = channel.can_pass_filter(0, 0, false).unwrap();
+    /// let _block = channel.can_block_filter(0x100, 0x7F0, false).unwrap();
+    /// ```
+    pub fn can_block_filter(
+        &'a self,
+        id: u32,
+        mask: u32,
+        extended: bool,
+    ) -> Result<MessageFilter<'a>, Error> {
+        let has_pass_filter = self
+            .tracked
+            .lock()
+            .unwrap()
+            .filters
+            .iter()
+            .any(|filter| filter.filter_type == FilterType::Pass as u32);
+        if !has_pass_filter {
+            return Err(Error::InvalidArgument(
+                "block filters have no effect without a pass filter".to_string(),
+            ));
+        }
+        self.can_filter(FilterType::Block, id, mask, extended)
+    }
+
+    /// Starts a flow control filter for talking to an ECU over ISO 15765: messages are received on
+    /// `ecu_id` and flow control frames are sent on `tester_id`, the ID requests are sent on.
+    /// The filter messages are padded like the messages sent by [`IsoTpChannel`](crate::IsoTpChannel).
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if an ID doesn't fit in an
+    /// 11-bit or 29-bit ID.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// // Send requests to the engine ECU on 0x7E0 and receive its responses on 0x7E8
+    /// let _filter = channel.iso15765_flow_filter(0x7E0, 0x7E8, false).unwrap();
+    /// ```
+    pub fn iso15765_flow_filter(
+        &'a self,
+        tester_id: u32,
+        ecu_id: u32,
+        extended: bool,
+    ) -> Result<MessageFilter<'a>, Error> {
+        check_can_id(tester_id, extended)?;
+        check_can_id(ecu_id, extended)?;
+        let id = self.start_flow_filter(tester_id, ecu_id, extended)?;
+        Ok(MessageFilter { channel: self, id })
+    }
+
+    /// Starts a flow control filter receiving on `ecu_id` and sending flow control frames on
+    /// `tester_id`
+    pub(crate) fn start_flow_filter(
+        &self,
+        tester_id: u32,
+        ecu_id: u32,
+        extended: bool,
+    ) -> Result<FilterId, Error> {
+        let tx_flags = if extended {
+            TxFlags::ISO15765_FRAME_PAD | TxFlags::CAN_29BIT_ID
+        } else {
+            TxFlags::ISO15765_FRAME_PAD
+        };
+        let mask = PassThruMsg::new_isotp(0xFFFF_FFFF, &[]).tx_flags(tx_flags);
+        let pattern = PassThruMsg::new_isotp(ecu_id, &[]).tx_flags(tx_flags);
+        let flow_control = PassThruMsg::new_isotp(tester_id, &[]).tx_flags(tx_flags);
+        self.start_message_filter(
+            FilterType::FlowControl,
+            Some(&mask),
+            Some(&pattern),
+            Some(&flow_control),
+        )
+    }
+
+    /// Starts a pass or block filter matching CAN IDs
+    fn can_filter(
+        &'a self,
+        filter_type: FilterType,
+        id: u32,
+        mask: u32,
+        extended: bool,
+    ) -> Result<MessageFilter<'a>, Error> {
+        check_can_id(id, extended)?;
+        let mut mask_msg = PassThruMsg::new_can(mask & max_can_id(extended), &[]);
+        let mut pattern_msg = PassThruMsg::new_can(id, &[]);
+        if extended {
+            mask_msg = mask_msg.tx_flags(TxFlags::CAN_29BIT_ID);
+            pattern_msg = pattern_msg.tx_flags(TxFlags::CAN_29BIT_ID);
+        }
+        self.msg_filter(filter_type, Some(&mask_msg), Some(&pattern_msg), None)
+    }
+}
+
+impl<'a> MessageFilter<'a> {
+    /// Returns the handle of the filter, which can be used with [`Channel::stop_message_filter`]
+    pub fn id(&self) -> FilterId {
+        self.id
+    }
+
+    /// Returns the channel the filter was started on
+    pub fn channel(&self) -> &'a Channel<'a> {
+        self.channel
+    }
+
+    /// Stops the filter. Unlike dropping the guard, this reports errors returned by the driver.
+    pub fn stop(self) -> Result<(), Error> {
+        let result = self.channel.stop_message_filter(self.id);
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl<'a> Drop for MessageFilter<'a> {
+    fn drop(&mut self) {
+        // Fails if the filter was already stopped or cleared with `Channel::clear_message_filters`
+        let _ = self.channel.stop_message_filter(self.id);
+    }
+}
+
+/// Builds a filter from raw bytes and checks it against the J2534 rules before it is passed to the
+/// driver, which usually only reports `ERR_INVALID_MSG`.
+///
+/// # Example
+/// ```no_run
+/// use j2534::{ConnectFlags, FilterBuilder, FilterType, Interface, Protocol};
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let channel = device.connect(Protocol::ISO9141, ConnectFlags::NONE, 10400).unwrap();
+///
+/// // Receive messages with the header 48 6B
+/// let _filter = FilterBuilder::new()
+///     .kind(FilterType::Pass)
+///     .mask_bytes(&[0xFF, 0xFF])
+///     .pattern_bytes(&[0x48, 0x6B])
+///     .apply(&channel)
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct FilterBuilder {
+    kind: FilterType,
+    mask: Vec<u8>,
+    pattern: Vec<u8>,
+    flow_control: Option<Vec<u8>>,
+    tx_flags: TxFlags,
+    lenient: bool,
+}
+
+impl FilterBuilder {
+    /// Creates a builder for a pass filter with an empty mask and pattern
+    pub fn new() -> FilterBuilder {
+        FilterBuilder {
+            kind: FilterType::Pass,
+            mask: Vec::new(),
+            pattern: Vec::new(),
+            flow_control: None,
+            tx_flags: TxFlags::NONE,
+            lenient: false,
+        }
+    }
+
+    /// Sets the type of the filter. Defaults to [`FilterType::Pass`].
+    pub fn kind(mut self, kind: FilterType) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Sets the bits of received messages that are compared with the pattern
+    pub fn mask_bytes(mut self, mask: &[u8]) -> Self {
+        self.mask = mask.to_vec();
+        self
+    }
+
+    /// Sets the values that the masked bits of received messages must have
+    pub fn pattern_bytes(mut self, pattern: &[u8]) -> Self {
+        self.pattern = pattern.to_vec();
+        self
+    }
+
+    /// Sets the flow control message. Only valid for [`FilterType::FlowControl`] filters.
+    pub fn flow_control_bytes(mut self, flow_control: &[u8]) -> Self {
+        self.flow_control = Some(flow_control.to_vec());
+        self
+    }
+
+    /// Sets the transmit flags of the filter messages, for example [`TxFlags::CAN_29BIT_ID`]
+    pub fn tx_flags(mut self, tx_flags: TxFlags) -> Self {
+        self.tx_flags = tx_flags;
+        self
+    }
+
+    /// Lets [`FilterBuilder::apply`] pass patterns with bits set that aren't in the mask to the
+    /// driver. By the J2534 rules such a filter can never match, but some drivers ignore the extra
+    /// bits.
+    pub fn lenient(mut self) -> Self {
+        self.lenient = true;
+        self
+    }
+
+    /// Checks the filter and starts it on `channel`.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the mask and pattern have
+    /// different lengths, if a message is shorter or longer than the protocol allows, if a flow
+    /// control message is given for a pass or block filter or missing for a flow control filter,
+    /// or if the pattern has bits set that aren't in the mask, unless [`FilterBuilder::lenient`]
+    /// was used.
+    pub fn apply<'a>(&self, channel: &'a Channel<'a>) -> Result<MessageFilter<'a>, Error> {
+        if self.mask.len() != self.pattern.len() {
+            return Err(Error::InvalidArgument(format!(
+                "filter mask is {} bytes long but the pattern is {} bytes long",
+                self.mask.len(),
+                self.pattern.len()
+            )));
+        }
+        let can = is_can_protocol(channel.protocol_id);
+        // CAN filters must cover the 4-byte ID
+        let min_len = if can { 4 } else { 1 };
+        let check_len = |name: &str, bytes: &[u8]| {
+            if (min_len..=MAX_FILTER_LEN).contains(&bytes.len()) {
+                Ok(())
+            } else {
+                Err(Error::InvalidArgument(format!(
+                    "filter {} is {} bytes long, it must be {} to {} bytes long on this channel",
+                    name,
+                    bytes.len(),
+                    min_len,
+                    MAX_FILTER_LEN
+                )))
+            }
+        };
+        check_len("mask", &self.mask)?;
+        check_len("pattern", &self.pattern)?;
+        match (self.kind, &self.flow_control) {
+            (FilterType::FlowControl, Some(flow_control)) => {
+                check_len("flow control message", flow_control)?
+            }
+            (FilterType::FlowControl, None) => {
+                return Err(Error::InvalidArgument(
+                    "flow control filters need a flow control message".to_string(),
+                ))
+            }
+            (kind, Some(_)) => {
+                return Err(Error::InvalidArgument(format!(
+                    "{:?} filters don't take a flow control message",
+                    kind
+                )))
+            }
+            (_, None) => {}
+        }
+        for (i, (mask, pattern)) in self.mask.iter().zip(&self.pattern).enumerate() {
+            if !self.lenient && pattern & !mask != 0 {
+                return Err(Error::InvalidArgument(format!(
+                    "filter pattern byte {} is {:#04X} but the mask is {:#04X}, so the filter can never match",
+                    i, pattern, mask
+                )));
+            }
+        }
+
+        let msg = |bytes: &[u8]| {
+            let mut msg = channel.new_msg().tx_flags(self.tx_flags);
+            msg.data[..bytes.len()].copy_from_slice(bytes);
+            msg.data_size = bytes.len() as u32;
+            msg
+        };
+        let mask = msg(&self.mask);
+        let pattern = msg(&self.pattern);
+        let flow_control = self.flow_control.as_deref().map(msg);
+        channel.msg_filter(
+            self.kind,
+            Some(&mask),
+            Some(&pattern),
+            flow_control.as_ref(),
+        )
+    }
+}
+
+impl Default for FilterBuilder {
+    fn default() -> FilterBuilder {
+        FilterBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn flow_filter_messages() {
+        let channel = mock::connect(Protocol::ISO15765, ConnectFlags::empty(), 500000);
+        let filter = channel.iso15765_flow_filter(0x7E0, 0x7E8, false).unwrap();
+        let started = mock::channel(channel.id()).filter_msgs;
+        let started = started.values().next().unwrap();
+
+        assert_eq!(started.filter_type, FilterType::FlowControl as u32);
+        let mask = started.mask.unwrap();
+        assert_eq!(mask.can_message(), Some((0xFFFF_FFFF, &[][..])));
+        assert_eq!(
+            started.pattern.unwrap().can_message(),
+            Some((0x7E8, &[][..]))
+        );
+        let flow_control = started.flow_control.unwrap();
+        assert_eq!(flow_control.can_message(), Some((0x7E0, &[][..])));
+        assert_eq!(
+            TxFlags::from_bits_truncate(flow_control.tx_flags),
+            TxFlags::ISO15765_FRAME_PAD
+        );
+
+        drop(filter);
+        assert!(mock::channel(channel.id()).filters.is_empty());
+    }
+
+    #[test]
+    fn flow_filter_extended_ids() {
+        let channel = mock::connect(Protocol::ISO15765, ConnectFlags::empty(), 500000);
+        assert!(matches!(
+            channel.iso15765_flow_filter(0x18DA10F1, 0x7E8, false),
+            Err(Error::InvalidArgument(_))
+        ));
+        let _filter = channel
+            .iso15765_flow_filter(0x18DA10F1, 0x18DAF110, true)
+            .unwrap();
+        let started = mock::channel(channel.id()).filter_msgs;
+        let pattern = started.values().next().unwrap().pattern.unwrap();
+        assert_eq!(pattern.can_message(), Some((0x18DAF110, &[][..])));
+        assert!(TxFlags::from_bits_truncate(pattern.tx_flags).contains(TxFlags::CAN_29BIT_ID));
+    }
+
+    #[test]
+    fn clear_forgets_filters() {
+        let channel = mock::can_channel();
+        let filter = channel.can_pass_filter(0x7E8, 0x7F8, false).unwrap();
+        let id = channel.start_pass_all_filter().unwrap();
+
+        channel.clear_message_filters().unwrap();
+        assert!(mock::channel(channel.id()).filters.is_empty());
+        assert!(matches!(
+            channel.stop_message_filter(id),
+            Err(Error::InvalidFilterId)
+        ));
+        assert!(matches!(filter.stop(), Err(Error::InvalidFilterId)));
+        // Block filters need a pass filter again
+        assert!(channel.can_block_filter(0x100, 0x7F0, false).is_err());
+    }
+
+    #[test]
+    fn builder_checks() {
+        let channel = mock::connect(Protocol::ISO9141, ConnectFlags::empty(), 10400);
+        let builder = FilterBuilder::new()
+            .mask_bytes(&[0xFF, 0x00])
+            .pattern_bytes(&[0x48, 0x6B]);
+
+        // Pattern bits outside the mask are rejected unless the builder is lenient
+        assert!(matches!(
+            builder.clone().apply(&channel),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(mock::channel(channel.id()).filters.is_empty());
+        let _lenient = builder.clone().lenient().apply(&channel).unwrap();
+        let builder = builder.pattern_bytes(&[0x48, 0x00]);
+        let _filter = builder.clone().apply(&channel).unwrap();
+
+        assert!(builder
+            .clone()
+            .pattern_bytes(&[0x48])
+            .apply(&channel)
+            .is_err());
+        assert!(FilterBuilder::new().apply(&channel).is_err());
+        assert!(builder
+            .clone()
+            .mask_bytes(&[0xFF; 13])
+            .pattern_bytes(&[0; 13])
+            .apply(&channel)
+            .is_err());
+        assert!(builder
+            .clone()
+            .flow_control_bytes(&[0x48])
+            .apply(&channel)
+            .is_err());
+        assert!(builder
+            .kind(FilterType::FlowControl)
+            .apply(&channel)
+            .is_err());
+        assert_eq!(mock::channel(channel.id()).filters.len(), 2);
+    }
+
+    #[test]
+    fn builder_can_id_length() {
+        let channel = mock::can_channel();
+        let builder = FilterBuilder::new()
+            .mask_bytes(&[0xFF, 0xFF])
+            .pattern_bytes(&[0x07, 0xE8]);
+        assert!(builder.apply(&channel).is_err());
+        let filter = FilterBuilder::new()
+            .mask_bytes(&[0, 0, 0xFF, 0xFF])
+            .pattern_bytes(&[0, 0, 0x07, 0xE8])
+            .apply(&channel)
+            .unwrap();
+        let started = mock::channel(channel.id()).filter_msgs;
+        let pattern = started[&mock::channel(channel.id()).filters[0]]
+            .pattern
+            .unwrap();
+        assert_eq!(pattern.can_message(), Some((0x7E8, &[][..])));
+        drop(filter);
+    }
+
+    #[test]
+    fn pass_all_lengths() {
+        let device = mock::open();
+        for (protocol, flags, baudrate, len) in [
+            (Protocol::CAN, ConnectFlags::empty(), 500000, 4),
+            (Protocol::CAN, ConnectFlags::CAN_29_BIT_ID, 500000, 4),
+            (Protocol::J1850VPW, ConnectFlags::empty(), 10400, 1),
+        ] {
+            let channel = device.connect(protocol, flags, baudrate).unwrap();
+            let _filter = channel.pass_all_filter().unwrap();
+            let started = mock::channel(channel.id()).filter_msgs;
+            let started = started.values().next().unwrap();
+            assert_eq!(started.filter_type, FilterType::Pass as u32);
+            for msg in [started.mask.unwrap(), started.pattern.unwrap()] {
+                assert_eq!({ msg.data_size }, len);
+                assert_eq!({ msg.protocol_id }, protocol as u32);
+                assert_eq!(
+                    TxFlags::from_bits_truncate(msg.tx_flags).contains(TxFlags::CAN_29BIT_ID),
+                    flags.contains(ConnectFlags::CAN_29_BIT_ID)
+                );
+            }
+        }
+
+        let channel = device
+            .connect(Protocol::ISO15765, ConnectFlags::empty(), 500000)
+            .unwrap();
+        assert!(matches!(
+            channel.pass_all_filter(),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}
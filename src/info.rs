@@ -0,0 +1,163 @@
+//! Diagnostic summary of a device created by [`Device::info`]
+
+use std::fmt;
+
+use crate::{Device, Driver, Error, OpenChannel, VersionInfo};
+
+/// Everything known about a device, returned by [`Device::info`]. Displayed as a summary that
+/// can be attached to a bug report.
+///
+/// Fields are `None` when the driver failed to report them.
+#[derive(Debug)]
+pub struct DeviceInfo {
+    /// The driver the library was loaded from, if it was loaded with
+    /// [`Interface::from_driver`](crate::Interface::from_driver)
+    pub driver: Option<Driver>,
+    /// The versions of the firmware, library and API
+    pub version: Option<VersionInfo>,
+    /// The battery voltage in millivolts, see [`Device::read_battery_voltage`]
+    pub battery_millivolts: Option<u32>,
+    /// The programming voltage in millivolts, see [`Device::read_programming_voltage`]
+    pub programming_millivolts: Option<u32>,
+    /// The channels connected through the device
+    pub channels: Vec<OpenChannel>,
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(driver) = &self.driver {
+            writeln!(
+                f,
+                "driver: {} ({}) at {}",
+                driver.name, driver.vendor, driver.path
+            )?;
+        }
+        match &self.version {
+            Some(version) => {
+                writeln!(f, "firmware version: {:?}", version.firmware_version)?;
+                writeln!(f, "library version: {:?}", version.dll_version)?;
+                writeln!(f, "api version: {:?}", version.api_version)?;
+            }
+            None => writeln!(f, "version: unknown")?,
+        }
+        match self.battery_millivolts {
+            Some(millivolts) => writeln!(f, "battery voltage: {} mV", millivolts)?,
+            None => writeln!(f, "battery voltage: unknown")?,
+        }
+        match self.programming_millivolts {
+            Some(millivolts) => writeln!(f, "programming voltage: {} mV", millivolts)?,
+            None => writeln!(f, "programming voltage: unknown")?,
+        }
+        if self.channels.is_empty() {
+            return writeln!(f, "channels: none");
+        }
+        writeln!(f, "channels:")?;
+        for channel in &self.channels {
+            write!(f, "  {}: ", channel.id)?;
+            match channel.protocol {
+                Some(protocol) => write!(f, "{:?}", protocol)?,
+                None => write!(f, "protocol {:#x}", channel.protocol_id)?,
+            }
+            writeln!(
+                f,
+                " at {} baud, flags {:?}",
+                channel.baudrate, channel.flags
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `Some` if the probe succeeded, `None` if it failed and the error if the device is no
+/// longer usable
+fn probe<T>(result: Result<T, Error>) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(err) if err.is_disconnect() => Err(err),
+        Err(_) => Ok(None),
+    }
+}
+
+impl<'a> Device<'a> {
+    /// Collects the driver, versions, voltages and connected channels of the device.
+    ///
+    /// Values the driver fails to report are left out of the result. Returns an error only if
+    /// the device is no longer usable because it was unplugged.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// print!("{}", device.info().unwrap());
+    /// ```
+    pub fn info(&self) -> Result<DeviceInfo, Error> {
+        Ok(DeviceInfo {
+            driver: self.interface.driver().cloned(),
+            version: probe(self.read_version())?,
+            battery_millivolts: probe(self.read_battery_voltage())?,
+            programming_millivolts: probe(self.read_programming_voltage())?,
+            channels: self.open_channels(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mock, ConnectFlags, Protocol};
+
+    #[test]
+    fn collects_everything() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.vbatt = 12600);
+        let channel = device
+            .connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+            .unwrap();
+
+        let info = device.info().unwrap();
+        assert_eq!(
+            info.version
+                .as_ref()
+                .unwrap()
+                .firmware_version
+                .to_str()
+                .unwrap(),
+            "1.2.3"
+        );
+        assert_eq!(info.battery_millivolts, Some(12600));
+        assert_eq!(info.programming_millivolts, Some(0));
+        assert_eq!(info.channels, device.open_channels());
+        assert_eq!(info.channels[0].id, channel.id());
+        let summary = info.to_string();
+        assert!(summary.contains("battery voltage: 12600 mV"));
+        assert!(summary.contains("CAN at 500000 baud"));
+    }
+
+    #[test]
+    fn failed_probes_are_left_out() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.vbatt_error = Some(0x07));
+        let info = device.info().unwrap();
+        assert!(info.version.is_some());
+        assert_eq!(info.battery_millivolts, None);
+        assert_eq!(info.programming_millivolts, Some(0));
+        assert!(info.to_string().contains("battery voltage: unknown"));
+
+        // Every probe failing still isn't an error
+        mock::with_device(device.id(), |device| device.fail = Some(0x01));
+        let info = device.info().unwrap();
+        assert!(info.version.is_none());
+        assert_eq!(info.battery_millivolts, None);
+        assert_eq!(info.programming_millivolts, None);
+        assert!(info.to_string().contains("version: unknown"));
+    }
+
+    #[test]
+    fn unplugged_device_is_an_error() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.fail = Some(0x08));
+        let err = device.info().unwrap_err();
+        assert!(err.is_disconnect(), "{:?}", err);
+    }
+}
@@ -0,0 +1,255 @@
+//! Whole-payload API for ISO 15765-2 (ISO-TP) channels
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    deadline_after, Channel, ConnectFlags, Device, Error, FilterId, PassThruMsg, Protocol,
+    RxStatus, TxFlags,
+};
+
+/// The largest payload of an ISO-TP message
+pub const MAX_ISOTP_PAYLOAD: usize = 4095;
+
+/// A pair of CAN IDs used to talk to one ECU
+#[derive(Copy, Clone, Debug)]
+struct Pair {
+    tx_id: u32,
+    rx_id: u32,
+    filter: FilterId,
+}
+
+/// An ISO 15765 channel that sends and receives complete payloads.
+/// Created with [`Device::connect_iso15765`].
+pub struct IsoTpChannel<'a> {
+    channel: Channel<'a>,
+    pairs: Vec<Pair>,
+    clear_before_request: bool,
+}
+
+impl<'a> Device<'a> {
+    /// Creates an ISO 15765 channel. Add the IDs to communicate on with [`IsoTpChannel::add_pair`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let mut isotp = device.connect_iso15765(500000, ConnectFlags::NONE).unwrap();
+    /// isotp.add_pair(0x7E0, 0x7E8).unwrap();
+    ///
+    /// // Read the VIN
+    /// isotp.send(0x7E0, &[0x22, 0xF1, 0x90], Duration::from_secs(1)).unwrap();
+    /// let (id, payload) = isotp.recv(Duration::from_secs(1)).unwrap();
+    /// println!("{:X}: {:02X?}", id, payload);
+    /// ```
+    pub fn connect_iso15765(
+        &self,
+        baudrate: u32,
+        flags: ConnectFlags,
+    ) -> Result<IsoTpChannel<'_>, Error> {
+        let channel = self.connect(Protocol::ISO15765, flags, baudrate)?;
+        Ok(IsoTpChannel {
+            channel,
+            pairs: Vec::new(),
+            clear_before_request: true,
+        })
+    }
+}
+
+impl<'a> IsoTpChannel<'a> {
+    /// The transmit flags set on every message sent over the channel
+    pub(crate) fn tx_flags(&self) -> TxFlags {
+        if self.channel.flags().contains(ConnectFlags::CAN_29_BIT_ID) {
+            TxFlags::ISO15765_FRAME_PAD | TxFlags::CAN_29BIT_ID
+        } else {
+            TxFlags::ISO15765_FRAME_PAD
+        }
+    }
+
+    /// Installs a flow control filter so that messages can be sent on `tx_id` and received on `rx_id`.
+    pub fn add_pair(&mut self, tx_id: u32, rx_id: u32) -> Result<FilterId, Error> {
+        let extended = self.channel.flags().contains(ConnectFlags::CAN_29_BIT_ID);
+        let filter = self.channel.start_flow_filter(tx_id, rx_id, extended)?;
+        self.pairs.push(Pair {
+            tx_id,
+            rx_id,
+            filter,
+        });
+        Ok(filter)
+    }
+
+    /// Removes the pair using `tx_id` and stops its flow control filter
+    pub fn remove_pair(&mut self, tx_id: u32) -> Result<(), Error> {
+        let index = self
+            .pairs
+            .iter()
+            .position(|pair| pair.tx_id == tx_id)
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!("no pair is registered for tx ID {:#X}", tx_id))
+            })?;
+        self.channel.stop_message_filter(self.pairs[index].filter)?;
+        self.pairs.remove(index);
+        Ok(())
+    }
+
+    /// Sends a payload of up to [`MAX_ISOTP_PAYLOAD`] bytes on `tx_id`. The frames are padded.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the payload is too long or
+    /// no pair was added for `tx_id`.
+    pub fn send(&self, tx_id: u32, payload: &[u8], timeout: Duration) -> Result<(), Error> {
+        if payload.len() > MAX_ISOTP_PAYLOAD {
+            return Err(Error::InvalidArgument(format!(
+                "ISO-TP payload is {} bytes long, the maximum is {} bytes",
+                payload.len(),
+                MAX_ISOTP_PAYLOAD
+            )));
+        }
+        if !self.pairs.iter().any(|pair| pair.tx_id == tx_id) {
+            return Err(Error::InvalidArgument(format!(
+                "no pair is registered for tx ID {:#X}",
+                tx_id
+            )));
+        }
+        let msg = PassThruMsg::new_isotp(tx_id, payload).tx_flags(self.tx_flags());
+        self.channel.write_msg(&msg, timeout)
+    }
+
+    /// Receives a complete payload. Returns the ID it was received on and the payload.
+    ///
+    /// First frame indications and transmit confirmations are skipped. Returns
+    /// [`Error::UnknownId`] if a message is received on an ID with no pair, and [`Error::Timeout`]
+    /// if no payload was received before the timeout expired.
+    pub fn recv(&self, timeout: Duration) -> Result<(u32, Vec<u8>), Error> {
+        let deadline = deadline_after(timeout);
+        loop {
+            let msg = self.channel.read_msg_deadline(deadline)?;
+            let rx_status = RxStatus::from_bits_truncate(msg.rx_status);
+            if rx_status
+                .intersects(RxStatus::START_OF_MESSAGE | RxStatus::TX_MSG_TYPE | RxStatus::TX_DONE)
+            {
+                continue;
+            }
+            let (id, payload) = match msg.isotp_message() {
+                Some(message) => message,
+                None => continue,
+            };
+            if !self.pairs.iter().any(|pair| pair.rx_id == id) {
+                return Err(Error::UnknownId(id));
+            }
+            return Ok((id, payload.to_vec()));
+        }
+    }
+
+    /// Sends a request on `tx_id` and returns the response received on the ID it is paired with.
+    /// Payloads received on any other ID are skipped.
+    ///
+    /// The receive buffer is cleared before sending, so that a late response to an earlier
+    /// request isn't returned. This can be disabled with [`IsoTpChannel::set_clear_before_request`].
+    pub fn request(&self, tx_id: u32, payload: &[u8], timeout: Duration) -> Result<Vec<u8>, Error> {
+        let rx_id = self
+            .pairs
+            .iter()
+            .find(|pair| pair.tx_id == tx_id)
+            .map(|pair| pair.rx_id)
+            .ok_or_else(|| {
+                Error::InvalidArgument(format!("no pair is registered for tx ID {:#X}", tx_id))
+            })?;
+        if self.clear_before_request {
+            self.channel.clear_receive_buffer()?;
+        }
+        let deadline = deadline_after(timeout);
+        self.send(tx_id, payload, timeout)?;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout == Duration::ZERO {
+                return Err(Error::Timeout);
+            }
+            match self.recv(timeout) {
+                Ok((id, response)) if id == rx_id => return Ok(response),
+                Ok(_) | Err(Error::UnknownId(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Sets whether [`IsoTpChannel::request`] clears the receive buffer before sending. Enabled by
+    /// default.
+    pub fn set_clear_before_request(&mut self, clear: bool) {
+        self.clear_before_request = clear;
+    }
+
+    /// Returns the underlying channel
+    pub fn channel(&self) -> &Channel<'a> {
+        &self.channel
+    }
+
+    /// Returns the underlying channel. Flow control filters stay active.
+    pub fn into_inner(self) -> Channel<'a> {
+        self.channel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    fn received(id: u32, payload: &[u8], rx_status: RxStatus) -> PassThruMsg {
+        let mut msg = PassThruMsg::new_isotp(id, payload);
+        msg.rx_status = rx_status.bits();
+        msg
+    }
+
+    #[test]
+    fn recv_skips_indications() {
+        let device = mock::open();
+        let mut isotp = device
+            .connect_iso15765(500000, ConnectFlags::empty())
+            .unwrap();
+        isotp.add_pair(0x7E0, 0x7E8).unwrap();
+        let id = isotp.channel().id();
+
+        mock::push_rx(id, received(0x7E0, &[], RxStatus::TX_DONE));
+        mock::push_rx(id, received(0x7E8, &[], RxStatus::START_OF_MESSAGE));
+        mock::push_rx(id, received(0x7E8, &[0x62, 0xF1, 0x90], RxStatus::empty()));
+        let (rx_id, payload) = isotp.recv(Duration::from_millis(10)).unwrap();
+        assert_eq!(rx_id, 0x7E8);
+        assert_eq!(payload, [0x62, 0xF1, 0x90]);
+
+        mock::push_rx(id, received(0x7E9, &[0x7F], RxStatus::empty()));
+        assert!(matches!(
+            isotp.recv(Duration::from_millis(10)),
+            Err(Error::UnknownId(0x7E9))
+        ));
+        assert!(matches!(
+            isotp.recv(Duration::from_millis(10)),
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[test]
+    fn request_skips_other_ids() {
+        let device = mock::open();
+        let mut isotp = device
+            .connect_iso15765(500000, ConnectFlags::empty())
+            .unwrap();
+        isotp.add_pair(0x7E0, 0x7E8).unwrap();
+        isotp.add_pair(0x7E1, 0x7E9).unwrap();
+        isotp.set_clear_before_request(false);
+        let id = isotp.channel().id();
+
+        mock::push_rx(id, received(0x7E0, &[], RxStatus::TX_DONE));
+        mock::push_rx(id, received(0x7E9, &[0x50, 0x03], RxStatus::empty()));
+        mock::push_rx(id, received(0x7EA, &[0x50, 0x03], RxStatus::empty()));
+        mock::push_rx(id, received(0x7E8, &[0x50, 0x01], RxStatus::empty()));
+        let response = isotp
+            .request(0x7E0, &[0x10, 0x01], Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(response, [0x50, 0x01]);
+
+        let written = mock::channel(id).written;
+        assert_eq!(written[0].can_message(), Some((0x7E0, &[0x10, 0x01][..])));
+    }
+}
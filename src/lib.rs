@@ -6,6 +6,7 @@
 //! ### Example
 //! ```rust
 //! use j2534::{Interface, PassThruMsg, Protocol, ConnectFlags, RxStatus, TxFlags};
+//! use std::time::Duration;
 //!
 //! fn main() -> j2534::Result<()> {
 //!     // Open the library and connect to a device
@@ -19,7 +20,7 @@
 //!
 //!     // Create a new message with an arbitration id of `8` and payload of `[0, 1, 2, 3]`.
 //!     let message = PassThruMsg::new_can(8, &[0, 1, 2, 3]);
-//!     channel.write(&mut [message], 1000)?;
+//!     channel.write(&mut [message], Duration::from_secs(1))?;
 //!     Ok(())
 //! }
 //! ```
@@ -27,14 +28,19 @@
 #[macro_use]
 extern crate bitflags;
 
+use std::cell::RefCell;
+use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::ffi::{self, CStr, CString};
 use std::fmt;
 use std::fmt::Debug;
 use std::io;
 use std::marker::PhantomData;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::Utf8Error;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use bitflags::_core::fmt::Formatter;
 use libloading::{Library, Symbol};
@@ -44,6 +50,78 @@ use num_traits::FromPrimitive;
 #[cfg(windows)]
 use winreg::{enums::*, RegKey};
 
+mod analog;
+#[cfg(feature = "async")]
+mod async_channel;
+mod builder;
+mod bus_error;
+mod can;
+pub mod capture;
+mod channel_set;
+mod clock;
+mod config;
+mod drewtech;
+mod echo;
+mod event;
+mod filter;
+mod info;
+mod isotp;
+mod library;
+mod manager;
+#[cfg(test)]
+mod mock;
+mod paced;
+mod periodic;
+mod pin_control;
+mod reader;
+mod registry;
+mod scan;
+mod self_test;
+mod stats;
+mod timestamp;
+mod tracking;
+mod vbatt;
+mod version;
+
+pub use analog::{AnalogInputs, AnalogReading};
+#[cfg(feature = "async")]
+pub use async_channel::{AsyncChannel, Call};
+pub use builder::{ChannelBuilder, DeviceBuilder, UnmetRequirement};
+use bus_error::BusErrorState;
+pub use bus_error::{BusError, BusErrorKind};
+pub use can::{CanChannel, CanFrame};
+pub use channel_set::ChannelSet;
+pub use clock::ClockAnchor;
+pub use config::{ConfigSnapshot, InitTimings, KLineTimings, MixedFormat, NetworkLine, PinPair};
+pub use drewtech::DrewtechDevice;
+pub use echo::EchoPolicy;
+use echo::EchoState;
+pub use event::RxEvent;
+pub use filter::{FilterBuilder, MessageFilter};
+pub use info::DeviceInfo;
+pub use isotp::{IsoTpChannel, MAX_ISOTP_PAYLOAD};
+use library::LoadedLibrary;
+pub use manager::{DriverEntry, DriverFailure, DriverManager};
+pub use paced::PacedWriter;
+pub use periodic::{PeriodicInfo, PeriodicMsg, TesterPresent};
+use pin_control::EnergizedPins;
+pub use pin_control::{PinControl, PinGuard};
+pub use reader::{Overflow, ReaderConfig, ReaderHandle};
+pub use registry::OpenChannel;
+pub use scan::{
+    SDevice, SDeviceRaw, DEVICE_AVAILABLE, DEVICE_DLL_FW_COMPATIBILITY_UNKNOWN,
+    DEVICE_DLL_FW_COMPATIBLE, DEVICE_DLL_NOT_COMPATIBLE, DEVICE_DLL_OR_FW_NOT_COMPATIBLE,
+    DEVICE_FW_NOT_COMPATIBLE, DEVICE_IN_USE, DEVICE_STATE_UNKNOWN,
+};
+pub use self_test::{SelfTestFailure, SelfTestReport, SelfTestStage};
+pub use stats::ChannelStats;
+use stats::StatsCounters;
+pub use timestamp::{TimedMsg, TimestampTracker};
+pub use tracking::ReconnectReport;
+use tracking::{Tracked, TrackedFilter, TrackedPeriodic};
+pub use vbatt::{VbattMonitor, VbattReading, VbattThresholds};
+pub use version::ParsedVersion;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("success")]
@@ -64,6 +142,10 @@ pub enum Error {
     Failed,
     #[error("a PassThru device is not connected")]
     DeviceNotConnected,
+    /// An earlier call failed with [`Error::DeviceNotConnected`], so the driver wasn't called.
+    /// See [`Device::clear_connection_lost`].
+    #[error("the device was disconnected earlier")]
+    PreviouslyDisconnected,
     #[error("timed out")]
     Timeout,
     #[error("invalid message")]
@@ -102,17 +184,74 @@ pub enum Error {
     InvalidBaudrate,
     #[error("invalid device id")]
     InvalidDeviceId,
+    /// The transmit queue stayed full until the timeout expired and only some messages were written
+    #[error("transmit queue full after writing {written} messages")]
+    PartialWrite { written: usize },
+    #[error("failed to load library: {0}")]
+    Library(#[from] libloading::Error),
+    /// The operation was cancelled with a [`CancelToken`]
+    #[error("cancelled")]
+    Cancelled,
+    /// No loopback echo of a written message was received before the timeout expired
+    #[error("message was not confirmed before the timeout expired (written: {written})")]
+    NotConfirmed { written: bool },
+    /// Some writes weren't confirmed before [`Channel::flush`] timed out. `received` holds the
+    /// messages that were read while waiting and weren't confirmations.
+    #[error("{pending} writes were not confirmed before the timeout expired")]
+    FlushTimeout {
+        pending: usize,
+        received: Vec<PassThruMsg>,
+    },
+    /// A message was received on an ID that wasn't registered with [`IsoTpChannel::add_pair`]
+    #[error("received a message on ID {0:#X}, which has no registered pair")]
+    UnknownId(u32),
+    /// A channel using the protocol, or another protocol on the same bus, is already connected
+    /// through the device. See [`Device::open_channels`].
+    #[error(
+        "protocol {protocol_id:#X} can't be connected, channel {channel_id} uses the same bus"
+    )]
+    AlreadyConnected { protocol_id: u32, channel_id: u32 },
+    /// A device opened with [`DeviceBuilder`] doesn't meet some of the requirements, each listed
+    /// with the value that was found. The device was closed.
+    #[error("device does not meet the requirements: {}", builder::describe_unmet(.0))]
+    RequirementNotMet(Vec<UnmetRequirement>),
+    /// No ECU responded to a five baud or fast initialization
+    #[error("no ECU responded to the initialization")]
+    NoInitResponse,
+    /// No installed driver has the name or vendor passed to [`Interface::open_driver`]
+    #[error("no installed driver is named {0:?}")]
+    DriverNotFound(String),
+    /// More than one installed driver has the name or vendor passed to
+    /// [`Interface::open_driver`]
+    #[error("{name:?} matches more than one installed driver: {}", candidates.join(", "))]
+    AmbiguousDriver {
+        name: String,
+        /// The names of the matching drivers
+        candidates: Vec<String>,
+    },
+    /// None of the drivers of a [`DriverManager`] could open a device
+    #[error("no device is available: {}", manager::describe_failures(.0))]
+    NoDeviceAvailable(Vec<DriverFailure>),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// An argument was rejected before calling into the driver
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
     #[error("unknown j2534 error code {0}")]
     Unknown(i32),
+    /// An error returned by the driver with the description from `PassThruGetLastError`. See
+    /// [`Interface::set_vendor_messages`].
+    #[error("{error}: {message}")]
+    Vendor { error: Box<Error>, message: String },
 }
 
 impl FromPrimitive for Error {
     fn from_i64(n: i64) -> Option<Self> {
-        todo!()
+        i32::from_i64(n).map(Error::from_code)
     }
 
     fn from_u64(n: u64) -> Option<Self> {
-        todo!()
+        i32::from_u64(n).map(Error::from_code)
     }
 }
 
@@ -150,8 +289,10 @@ impl Error {
         }
     }
 
-    pub fn as_code(&self) -> i32 {
-        match *self {
+    /// Returns the J2534 status code of an error returned by the driver, or `None` if the error
+    /// was raised by this crate without a driver result, like [`Error::InvalidArgument`].
+    pub fn as_code(&self) -> Option<u32> {
+        let code = match *self {
             Error::NoError => 0x00,
             Error::NotSupported => 0x01,
             Error::InvalidChannelId => 0x02,
@@ -179,9 +320,79 @@ impl Error {
             Error::NotUnique => 0x18,
             Error::InvalidBaudrate => 0x19,
             Error::InvalidDeviceId => 0x1A,
-            Error::Unknown(n) => n,
+            // Errors raised by this crate have no status code
+            Error::Library(_)
+            | Error::Io(_)
+            | Error::Cancelled
+            | Error::UnknownId(_)
+            | Error::InvalidArgument(_)
+            | Error::RequirementNotMet(_)
+            | Error::DriverNotFound(_)
+            | Error::AmbiguousDriver { .. }
+            | Error::PreviouslyDisconnected
+            | Error::PartialWrite { .. }
+            | Error::NotConfirmed { .. }
+            | Error::FlushTimeout { .. }
+            | Error::NoInitResponse
+            | Error::AlreadyConnected { .. }
+            | Error::NoDeviceAvailable(_) => return None,
+            Error::Unknown(n) => n as u32,
+            Error::Vendor { ref error, .. } => return error.as_code(),
+        };
+        Some(code)
+    }
+
+    /// Returns the vendor's description captured when the driver returned the error, see
+    /// [`Interface::set_vendor_messages`]
+    pub fn vendor_message(&self) -> Option<&str> {
+        match self {
+            Error::Vendor { message, .. } => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without the vendor's description. Match on this to handle specific
+    /// errors regardless of whether a description was captured.
+    ///
+    /// # Example
+    /// ```
+    /// use j2534::Error;
+    ///
+    /// let err = Error::Vendor {
+    ///     error: Box::new(Error::Failed),
+    ///     message: "adapter firmware fault".to_string(),
+    /// };
+    /// assert!(matches!(err.inner(), Error::Failed));
+    /// assert_eq!(err.vendor_message(), Some("adapter firmware fault"));
+    /// ```
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::Vendor { error, .. } => error.inner(),
+            other => other,
         }
     }
+
+    /// Returns true if the error means that the device was unplugged
+    pub(crate) fn is_disconnect(&self) -> bool {
+        matches!(
+            self.inner(),
+            Error::DeviceNotConnected | Error::PreviouslyDisconnected | Error::InvalidDeviceId
+        )
+    }
+}
+
+/// Which errors returned by the driver get the vendor's description from
+/// `PassThruGetLastError` attached. See [`Interface::set_vendor_messages`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum VendorMessages {
+    /// Never call `PassThruGetLastError`, so that errors are returned as is
+    #[default]
+    Never,
+    /// Only for [`Error::Failed`], whose meaning is given by the description alone
+    OnFailed,
+    /// For every error except [`Error::Timeout`] and [`Error::BufferEmpty`], which report
+    /// that a read or write didn't complete in time rather than a failure
+    Always,
 }
 
 pub type PassThruOpenFn =
@@ -239,9 +450,19 @@ pub type PassThruIoctlFn = unsafe extern "stdcall" fn(
     input: *mut libc::c_void,
     output: *mut libc::c_void,
 ) -> i32;
+pub type PassThruScanForDevicesFn = unsafe extern "stdcall" fn(device_count: *mut u32) -> i32;
+pub type PassThruGetNextDeviceFn = unsafe extern "stdcall" fn(device: *mut SDeviceRaw) -> i32;
+pub type PassThruGetNextCarDAQFn = unsafe extern "stdcall" fn(
+    name: *mut *mut libc::c_char,
+    version: *mut u32,
+    address: *mut *mut libc::c_char,
+) -> i32;
 
 // Much of the descriptions and APIs used here were taken from http://www.drewtech.com/support/passthru.html
 
+/// The size of the data buffer in a [`PassThruMsg`]
+pub const MAX_DATA_SIZE: usize = 4128;
+
 #[derive(Copy, Clone)]
 #[repr(C, packed(1))]
 /// A message sent a received from the device
@@ -255,22 +476,126 @@ pub struct PassThruMsg {
     pub data: [u8; 4128],
 }
 
-#[repr(C, packed(1))]
-struct SConfig {
-    parameter: u32,
-    value: u32,
+// Layout of the SAE J2534 header
+const _: () = assert!(std::mem::size_of::<PassThruMsg>() == 6 * 4 + MAX_DATA_SIZE);
+const _: () = assert!(std::mem::align_of::<PassThruMsg>() == 1);
+
+/// A configuration parameter and its value, the `SCONFIG` structure used by `GET_CONFIG` and
+/// `SET_CONFIG`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct SConfig {
+    pub parameter: u32,
+    pub value: u32,
 }
 
-#[repr(C, packed(1))]
-struct SConfigList {
-    size: u32,
-    config_ptr: *const SConfig,
+/// A list of configuration parameters, the `SCONFIG_LIST` structure used by `GET_CONFIG` and
+/// `SET_CONFIG`. The list borrows the parameters, so they stay alive while it is passed to the
+/// driver.
+#[repr(C)]
+pub struct SConfigList<'a> {
+    num_of_params: u32,
+    config_ptr: *mut SConfig,
+    configs: PhantomData<&'a mut [SConfig]>,
 }
 
-#[repr(C, packed(1))]
-struct SByteArray {
-    size: u32,
-    byte_ptr: *const u8,
+// Layouts of the SAE J2534 header
+const _: () = assert!(std::mem::size_of::<SConfig>() == 8);
+const _: () = assert!(std::mem::align_of::<SConfig>() == 4);
+const _: () = assert!(std::mem::size_of::<SConfigList>() == 2 * std::mem::size_of::<usize>());
+const _: () = assert!(std::mem::align_of::<SConfigList>() == std::mem::align_of::<usize>());
+
+impl SConfig {
+    pub fn new(parameter: ConfigId, value: u32) -> SConfig {
+        SConfig {
+            parameter: parameter as u32,
+            value,
+        }
+    }
+}
+
+impl<'a> SConfigList<'a> {
+    /// Creates a list of `configs`. The driver reads the values for `SET_CONFIG` and writes them
+    /// for `GET_CONFIG`.
+    pub fn new(configs: &'a mut [SConfig]) -> SConfigList<'a> {
+        SConfigList {
+            num_of_params: configs.len() as u32,
+            config_ptr: configs.as_mut_ptr(),
+            configs: PhantomData,
+        }
+    }
+
+    /// Returns the number of parameters in the list
+    pub fn num_of_params(&self) -> u32 {
+        self.num_of_params
+    }
+
+    /// Returns a pointer to the list to pass to `PassThruIoctl`
+    pub fn as_mut_ptr(&mut self) -> *mut libc::c_void {
+        self as *mut SConfigList as *mut libc::c_void
+    }
+}
+
+/// An array of bytes, the `SBYTE_ARRAY` structure used by `FIVE_BAUD_INIT` and the functional
+/// message lookup table ioctls. Created by [`ByteArray`], which owns the bytes.
+#[repr(C)]
+pub struct SByteArray {
+    num_of_bytes: u32,
+    byte_ptr: *mut u8,
+}
+
+// Layouts of the SAE J2534 header
+const _: () = assert!(std::mem::size_of::<SByteArray>() == 2 * std::mem::size_of::<usize>());
+const _: () = assert!(std::mem::align_of::<SByteArray>() == std::mem::align_of::<usize>());
+
+/// Bytes passed to or returned from an ioctl through an [`SByteArray`]
+pub struct ByteArray {
+    bytes: Vec<u8>,
+    raw: SByteArray,
+}
+
+impl ByteArray {
+    /// Creates an array of `bytes` for the driver to read
+    pub fn new(bytes: &[u8]) -> ByteArray {
+        ByteArray::from_vec(bytes.to_vec())
+    }
+
+    /// Creates an array of `capacity` zeroes for the driver to write to
+    pub fn with_capacity(capacity: usize) -> ByteArray {
+        ByteArray::from_vec(vec![0; capacity])
+    }
+
+    fn from_vec(mut bytes: Vec<u8>) -> ByteArray {
+        // The pointer stays valid when the array is moved because the bytes are never reallocated
+        let raw = SByteArray {
+            num_of_bytes: bytes.len() as u32,
+            byte_ptr: bytes.as_mut_ptr(),
+        };
+        ByteArray { bytes, raw }
+    }
+
+    /// Returns a pointer to pass to `PassThruIoctl` as the input
+    pub fn as_input_ptr(&mut self) -> *mut libc::c_void {
+        &mut self.raw as *mut SByteArray as *mut libc::c_void
+    }
+
+    /// Returns a pointer to pass to `PassThruIoctl` as the output. The driver sets the number of
+    /// bytes it wrote.
+    pub fn as_output_ptr(&mut self) -> *mut libc::c_void {
+        &mut self.raw as *mut SByteArray as *mut libc::c_void
+    }
+
+    /// Returns the number of bytes in the array, which the driver may have changed
+    pub fn num_of_bytes(&self) -> u32 {
+        self.raw.num_of_bytes
+    }
+
+    /// Returns the bytes in the array. Never returns more bytes than the array was created with,
+    /// even if the driver reports more.
+    pub fn bytes(&self) -> &[u8] {
+        let len = (self.raw.num_of_bytes as usize).min(self.bytes.len());
+        &self.bytes[..len]
+    }
 }
 
 impl PassThruMsg {
@@ -349,12 +674,10 @@ impl PassThruMsg {
     }
 
     /// Returns the CAN ID and payload. Also use this method for reading ISO-TP messages.
-    /// Returns `None` if the protocol is not CAN or ISO15765 or if the message is too short.
+    /// Returns `None` if the protocol is not CAN, ISO15765 or one of their J2534-2 variants or if
+    /// the message is too short.
     pub fn can_message(&self) -> Option<(u32, &[u8])> {
-        if (self.protocol_id == (Protocol::CAN as u32)
-            || self.protocol_id == (Protocol::ISO15765 as u32))
-            && self.data_size >= 4
-        {
+        if is_can_protocol(self.protocol_id) && self.data_size >= 4 {
             let id = ((self.data[0] as u32) << 24)
                 | ((self.data[1] as u32) << 16)
                 | ((self.data[2] as u32) << 8)
@@ -439,16 +762,39 @@ struct ChannelId(u32);
 struct DeviceId(u32);
 
 /// Periodic message ID
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct MessageId(u32);
 
 /// Message filter ID
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FilterId(u32);
 
 /// A J2534 library
+///
+/// Interfaces, devices and channels can be shared between threads. See
+/// [`Interface::serialize_calls`] for drivers that don't support concurrent calls.
+///
+/// # Example
+/// ```no_run
+/// use j2534::{ConnectFlags, Interface, PassThruMsg, Protocol};
+/// use std::time::Duration;
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+///
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         let mut buf = [PassThruMsg::default(); 16];
+///         let _ = channel.read(&mut buf, Duration::from_millis(100));
+///     });
+///     let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x3E, 0x80]);
+///     channel.write_msg(&msg, Duration::from_millis(100)).unwrap();
+/// });
+/// ```
 pub struct Interface {
-    library: Library,
+    /// Shared with the other interfaces loaded from the same path
+    library: Arc<LoadedLibrary>,
 
     c_pass_thru_open: PassThruOpenFn,
     c_pass_thru_close: PassThruCloseFn,
@@ -464,25 +810,128 @@ pub struct Interface {
     c_pass_thru_stop_periodic_msg: PassThruStopPeriodicMsgFn,
     c_pass_thru_set_programming_voltage: PassThruSetProgrammingVoltageFn,
     c_pass_thru_ioctl: PassThruIoctlFn,
+    /// Only exported by J2534-1 v05.00 drivers, see [`Interface::scan_devices`]
+    c_pass_thru_scan_for_devices: Option<PassThruScanForDevicesFn>,
+    c_pass_thru_get_next_device: Option<PassThruGetNextDeviceFn>,
+    /// A Drew Technologies extension, see [`Interface::enumerate_drewtech_devices`]
+    c_pass_thru_get_next_car_daq: Option<PassThruGetNextCarDAQFn>,
+
+    /// A [`VendorMessages`] stored as its index
+    vendor_messages: AtomicU8,
+    /// The path the library was loaded from
+    path: PathBuf,
+    /// The driver the library was loaded from, see [`Interface::from_driver`]
+    driver: Option<Driver>,
+    /// Set by [`Interface::serialize_calls`]
+    serialize_calls: AtomicBool,
+    /// Held during calls into the library while `serialize_calls` is set
+    serial: Mutex<()>,
+}
+
+// J2534-1 requires drivers to accept calls from multiple threads, including concurrent calls to
+// `PassThruReadMsgs` and `PassThruWriteMsgs` on the same channel. The interface only holds the
+// library and pointers to its functions, which stay valid until the library is unloaded when the
+// interface is dropped. Drivers that don't handle concurrent calls can be serialized with
+// `Interface::serialize_calls`.
+unsafe impl Send for Interface {}
+unsafe impl Sync for Interface {}
+
+thread_local! {
+    /// The code of the last call that failed on this thread and the vendor's description of it,
+    /// if [`Interface::vendor_messages`] asked for one
+    static VENDOR_MESSAGE: RefCell<Option<(i32, Option<String>)>> = const { RefCell::new(None) };
+}
+
+/// The result of a call into the library, see [`Interface::call`]
+pub(crate) trait ReturnCode {
+    /// Returns the code the library returned, 0 on success
+    fn return_code(&self) -> i32;
+}
+
+impl ReturnCode for i32 {
+    fn return_code(&self) -> i32 {
+        *self
+    }
+}
 
-    _marker: PhantomData<*mut ()>,
+/// For calls that return data along with the code
+impl<T> ReturnCode for (T, i32) {
+    fn return_code(&self) -> i32 {
+        self.1
+    }
 }
 
 /// A device created with [`Interface::open`]
 pub struct Device<'a> {
     interface: &'a Interface,
     id: DeviceId,
+    /// Set by [`Device::close`] so that the device isn't closed again when dropped
+    closed: bool,
+    /// Held during device-level calls so that calls from a [`VbattMonitor`] thread don't
+    /// overlap with other device-level calls. Channel calls don't take it, serializing those is
+    /// left to `Interface::serialize_calls`.
+    call_lock: Mutex<()>,
+    /// Set when a call fails with [`Error::DeviceNotConnected`], see [`Device::is_connected`]
+    connection_lost: AtomicBool,
+    /// The channels connected through the device, see [`Device::open_channels`]
+    channels: Mutex<Vec<OpenChannel>>,
+    /// The pins energized with [`Device::set_programming_voltage`]
+    energized: Mutex<EnergizedPins>,
 }
 
 /// A communication channel
 pub struct Channel<'a> {
     device: &'a Device<'a>,
     id: ChannelId,
-    protocol: Protocol,
+    protocol_id: u32,
+    protocol: Option<Protocol>,
+    flags: ConnectFlags,
+    /// The baud rate the channel was created with or that was set with `DATA_RATE`
+    baudrate: AtomicU32,
+    stats: StatsCounters,
+    /// Messages written since the last flush that haven't been confirmed
+    pending_writes: AtomicUsize,
+    /// Set by [`Channel::disconnect`] so that the channel isn't disconnected again when dropped
+    disconnected: bool,
+    tracked: Mutex<Tracked>,
+    echo: Mutex<EchoState>,
+    bus_errors: Mutex<BusErrorState>,
+}
+
+impl Debug for Interface {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Interface")
+            .field("path", &self.path)
+            .field("handle", &self.library.handle())
+            .field("driver", &self.driver.as_ref().map(|driver| &driver.name))
+            .finish()
+    }
+}
+
+impl<'a> Debug for Device<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Device")
+            .field("interface", &self.interface)
+            .field("id", &self.id.0)
+            .finish()
+    }
+}
+
+impl<'a> Debug for Channel<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Channel")
+            .field("device", &self.device)
+            .field("id", &self.id.0)
+            .field("protocol_id", &self.protocol_id)
+            .field("baudrate", &self.baudrate())
+            .finish()
+    }
 }
 
 impl Interface {
-    /// Returns a J2534 library given the path
+    /// Returns a J2534 library given the path. Interfaces created from the same path share the
+    /// loaded library, which is unloaded when the last of them is dropped. Some drivers keep
+    /// global state and misbehave when loaded more than once.
     ///
     /// # Arguments
     ///
@@ -494,8 +943,75 @@ impl Interface {
     /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
     /// ```
     pub fn new<S: AsRef<OsStr>>(path: S) -> Result<Interface, libloading::Error> {
-        let library = Library::new(path)?;
+        let path = PathBuf::from(path.as_ref());
+        let library = library::load_shared(&path)?;
+        Interface::from_library(library, path)
+    }
+
+    /// Like [`Interface::new`], but loads the library for this interface only instead of sharing
+    /// it with other interfaces.
+    ///
+    /// Windows and most other systems count loads of the same file and map it only once per
+    /// process, so the driver's global state may still be shared.
+    pub fn new_unshared<S: AsRef<OsStr>>(path: S) -> Result<Interface, libloading::Error> {
+        let path = PathBuf::from(path.as_ref());
+        let library = LoadedLibrary::new(Library::new(&path)?);
+        Interface::from_library(Arc::new(library), path)
+    }
+
+    /// Wraps a library handle loaded outside of this crate, like an `HMODULE` returned by
+    /// `LoadLibrary`. The library isn't shared with other interfaces and [`Interface::path`]
+    /// returns an empty path.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a valid handle to a loaded J2534 library. The interface takes ownership
+    /// of the handle and unloads the library when dropped, so the caller must not unload it
+    /// unless they get the handle back with [`Interface::into_raw`]. The library is also unloaded
+    /// if it's missing a J2534 function and an error is returned.
+    pub unsafe fn from_raw(handle: *mut libc::c_void) -> Result<Interface, libloading::Error> {
+        let library = LoadedLibrary::from_raw(handle);
+        Interface::from_library(Arc::new(library), PathBuf::new())
+    }
+
+    /// Returns the handle of the library, like the `HMODULE` on Windows. The handle stays owned
+    /// by the interface and becomes invalid when the interface is dropped.
+    pub fn as_raw(&self) -> *const libc::c_void {
+        self.library.handle()
+    }
+
+    /// Returns the handle of the library without unloading it. The caller becomes responsible for
+    /// unloading the library, for example by passing the handle back to [`Interface::from_raw`].
+    ///
+    /// If other interfaces share the library, it's loaded once more so that the returned handle
+    /// holds its own reference, which fails only if the library can't be loaded again.
+    ///
+    /// Devices borrow the interface, so they have to be closed or dropped first.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let handle = interface.into_raw().unwrap();
+    /// // Hand the library to other code and take it back
+    /// let interface = unsafe { Interface::from_raw(handle) }.unwrap();
+    /// ```
+    pub fn into_raw(self) -> Result<*mut libc::c_void, libloading::Error> {
+        match Arc::try_unwrap(self.library) {
+            Ok(library) => Ok(library.into_raw()),
+            // The unload is counted by the system, which keeps the library loaded for the other
+            // interfaces
+            Err(_) => Ok(LoadedLibrary::new(Library::new(&self.path)?).into_raw()),
+        }
+    }
 
+    /// Resolves the functions of a loaded library
+    fn from_library(
+        shared: Arc<LoadedLibrary>,
+        path: PathBuf,
+    ) -> Result<Interface, libloading::Error> {
+        let library = shared.library();
         let interface = unsafe {
             let c_pass_thru_open: Symbol<PassThruOpenFn> = library.get(b"PassThruOpen\0")?;
             let c_pass_thru_close: Symbol<PassThruCloseFn> = library.get(b"PassThruClose\0")?;
@@ -522,6 +1038,12 @@ impl Interface {
             let c_pass_thru_set_programming_voltage: Symbol<PassThruSetProgrammingVoltageFn> =
                 library.get(b"PassThruSetProgrammingVoltage\0")?;
             let c_pass_thru_ioctl: Symbol<PassThruIoctlFn> = library.get(b"PassThruIoctl\0")?;
+            let c_pass_thru_scan_for_devices: Option<Symbol<PassThruScanForDevicesFn>> =
+                library.get(b"PassThruScanForDevices\0").ok();
+            let c_pass_thru_get_next_device: Option<Symbol<PassThruGetNextDeviceFn>> =
+                library.get(b"PassThruGetNextDevice\0").ok();
+            let c_pass_thru_get_next_car_daq: Option<Symbol<PassThruGetNextCarDAQFn>> =
+                library.get(b"PassThruGetNextCarDAQ\0").ok();
             Interface {
                 c_pass_thru_open: *c_pass_thru_open.into_raw(),
                 c_pass_thru_close: *c_pass_thru_close.into_raw(),
@@ -538,9 +1060,19 @@ impl Interface {
                 c_pass_thru_set_programming_voltage: *c_pass_thru_set_programming_voltage
                     .into_raw(),
                 c_pass_thru_ioctl: *c_pass_thru_ioctl.into_raw(),
-                library,
+                c_pass_thru_scan_for_devices: c_pass_thru_scan_for_devices
+                    .map(|symbol| *symbol.into_raw()),
+                c_pass_thru_get_next_device: c_pass_thru_get_next_device
+                    .map(|symbol| *symbol.into_raw()),
+                c_pass_thru_get_next_car_daq: c_pass_thru_get_next_car_daq
+                    .map(|symbol| *symbol.into_raw()),
+                library: shared.clone(),
 
-                _marker: PhantomData,
+                vendor_messages: AtomicU8::new(VendorMessages::default() as u8),
+                path,
+                driver: None,
+                serialize_calls: AtomicBool::new(false),
+                serial: Mutex::new(()),
             }
         };
 
@@ -549,8 +1081,207 @@ impl Interface {
         Ok(interface)
     }
 
-    /// Returns a text description of the most recent error
+    /// Loads the library of an installed driver returned by [`drivers`]. The driver is kept for
+    /// diagnostics and returned by [`Interface::driver`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// let driver = j2534::drivers().unwrap().into_iter().next().unwrap();
+    /// let interface = j2534::Interface::from_driver(&driver).unwrap();
+    /// ```
+    pub fn from_driver(driver: &Driver) -> Result<Interface, libloading::Error> {
+        let mut interface = Interface::new(&driver.path)?;
+        interface.driver = Some(driver.clone());
+        Ok(interface)
+    }
+
+    /// Loads the installed driver whose name or vendor is `name`, ignoring case.
+    ///
+    /// Returns [`Error::DriverNotFound`] if no driver matches and [`Error::AmbiguousDriver`]
+    /// listing the candidates if more than one does.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::open_driver("Tactrix Inc.").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// ```
+    pub fn open_driver(name: &str) -> Result<Interface, Error> {
+        let wanted = name.to_lowercase();
+        let mut candidates: Vec<Driver> = drivers()?
+            .into_iter()
+            .filter(|driver| {
+                driver.name.to_lowercase() == wanted || driver.vendor.to_lowercase() == wanted
+            })
+            .collect();
+        match candidates.len() {
+            0 => Err(Error::DriverNotFound(name.to_string())),
+            1 => Ok(Interface::from_driver(&candidates.remove(0))?),
+            _ => Err(Error::AmbiguousDriver {
+                name: name.to_string(),
+                candidates: candidates
+                    .iter()
+                    .map(|driver| format!("{} ({})", driver.name, driver.vendor))
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Makes calls into the library wait for each other, for drivers that misbehave when called
+    /// from several threads at once. Calls aren't serialized by default, as J2534-1 requires
+    /// drivers to handle concurrent calls. A failing call keeps the lock until its description
+    /// has been read for [`Interface::set_vendor_messages`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// interface.serialize_calls(true);
+    /// ```
+    pub fn serialize_calls(&self, serialize: bool) {
+        self.serialize_calls.store(serialize, Ordering::Relaxed);
+    }
+
+    /// Returns the lock that serializes calls into the library, if they are serialized
+    fn lock_serial(&self) -> Option<MutexGuard<'_, ()>> {
+        if self.serialize_calls.load(Ordering::Relaxed) {
+            Some(self.serial.lock().unwrap())
+        } else {
+            None
+        }
+    }
+
+    /// Calls `f`, which calls into the library, holding the lock if calls are serialized. If the
+    /// call fails, the vendor's description is read before the lock is released, so that no other
+    /// call can replace it first, and kept for [`Interface::error`].
+    pub(crate) fn call<T: ReturnCode>(&self, f: impl FnOnce() -> T) -> T {
+        let _serial = self.lock_serial();
+        let result = f();
+        let code = result.return_code();
+        if code != 0 {
+            let message = if self.captures_message(&Error::from_code(code)) {
+                self.read_last_error().ok()
+            } else {
+                None
+            };
+            VENDOR_MESSAGE.with(|last| *last.borrow_mut() = Some((code, message)));
+        }
+        result
+    }
+
+    /// Returns the path the library was loaded from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the driver the library was loaded from, or `None` if it was loaded with
+    /// [`Interface::new`]
+    pub fn driver(&self) -> Option<&Driver> {
+        self.driver.as_ref()
+    }
+
+    /// Sets which errors returned by the driver get the vendor's description attached as
+    /// [`Error::Vendor`]. The description is read with an extra call to `PassThruGetLastError`
+    /// right after the failing call. Defaults to [`VendorMessages::Never`].
+    ///
+    /// Once descriptions are attached, the wrapped errors no longer match their own variants,
+    /// so match on [`Error::inner`] to handle specific errors.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{Interface, VendorMessages};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// interface.set_vendor_messages(VendorMessages::Always);
+    /// if let Err(err) = interface.open_any() {
+    ///     println!("{}", err);
+    ///     println!("description: {:?}", err.vendor_message());
+    /// };
+    /// ```
+    pub fn set_vendor_messages(&self, policy: VendorMessages) {
+        self.vendor_messages.store(policy as u8, Ordering::Relaxed);
+    }
+
+    /// Returns which errors get the vendor's description attached
+    pub fn vendor_messages(&self) -> VendorMessages {
+        match self.vendor_messages.load(Ordering::Relaxed) {
+            0 => VendorMessages::Never,
+            1 => VendorMessages::OnFailed,
+            _ => VendorMessages::Always,
+        }
+    }
+
+    /// Returns true if [`Interface::vendor_messages`] asks for the description of `error`
+    fn captures_message(&self, error: &Error) -> bool {
+        match (self.vendor_messages(), error) {
+            (VendorMessages::Never, _) => false,
+            (VendorMessages::OnFailed, Error::Failed) => true,
+            (VendorMessages::OnFailed, _) => false,
+            (VendorMessages::Always, Error::Timeout | Error::BufferEmpty) => false,
+            (VendorMessages::Always, _) => true,
+        }
+    }
+
+    /// Converts the code returned by a failing call into an error, attaching the vendor's
+    /// description that [`Interface::call`] read for it on this thread
+    pub(crate) fn error(&self, code: i32) -> Error {
+        let error = Error::from_code(code);
+        let message = VENDOR_MESSAGE.with(|last| match last.borrow_mut().take() {
+            Some((last_code, message)) if last_code == code => message,
+            _ => None,
+        });
+        match message {
+            Some(message) if !message.is_empty() => Error::Vendor {
+                error: Box::new(error),
+                message,
+            },
+            _ => error,
+        }
+    }
+
+    /// Returns a text description of the most recent error. See [`Interface::last_error`].
     pub fn get_last_error(&self) -> Result<CString, Error> {
+        let error = self.last_error_bytes()?;
+        // `last_error_bytes` stops at the first NUL
+        Ok(CString::new(error).unwrap())
+    }
+
+    /// Returns the vendor's description of the most recent error with surrounding whitespace
+    /// removed. Text that isn't valid UTF-8 is converted lossily.
+    ///
+    /// J2534-1 only defines the description for the last call that failed on the calling
+    /// thread, so call this right after the failing call and from the same thread. Later calls,
+    /// including successful ones, may replace or clear it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// if let Err(err) = interface.open_any() {
+    ///     println!("{}: {}", err, interface.last_error().unwrap());
+    /// };
+    /// ```
+    pub fn last_error(&self) -> Result<String, Error> {
+        let error = self.last_error_bytes()?;
+        Ok(String::from_utf8_lossy(&error).trim().to_string())
+    }
+
+    /// Like [`Interface::last_error`], for callers that already hold the serial lock
+    fn read_last_error(&self) -> Result<String, Error> {
+        let error = self.read_last_error_bytes()?;
+        Ok(String::from_utf8_lossy(&error).trim().to_string())
+    }
+
+    /// Calls `PassThruGetLastError` and returns the description up to its NUL terminator
+    fn last_error_bytes(&self) -> Result<Vec<u8>, Error> {
+        let _serial = self.lock_serial();
+        self.read_last_error_bytes()
+    }
+
+    fn read_last_error_bytes(&self) -> Result<Vec<u8>, Error> {
         let mut error: [u8; 80] = [0; 80];
         let res =
             unsafe { (&self.c_pass_thru_get_last_error)(error.as_mut_ptr() as *mut libc::c_char) };
@@ -558,15 +1289,22 @@ impl Interface {
             return Err(Error::from_code(res));
         }
 
-        let c_str = unsafe { CStr::from_ptr(error.as_mut_ptr() as *mut libc::c_char) };
-        Ok(c_str.to_owned())
+        // Some drivers fill the whole buffer without a terminator
+        let len = error.iter().position(|&b| b == 0).unwrap_or(error.len());
+        Ok(error[..len].to_vec())
     }
 
-    /// Creates a `Device` from the PassThru interface connected to the given port
+    /// Opens a device with a vendor-defined name. Depending on the driver this is a port like
+    /// `COM2`, the device name from the registry, a serial number or an IP address. Use
+    /// [`Interface::open_named`] for names that are Rust strings.
+    ///
+    /// The name is passed to `PassThruOpen` byte for byte, so it has to be in the encoding the
+    /// driver expects, which is usually the ANSI code page on Windows. Returns
+    /// [`Error::InvalidArgument`] if it contains a nul byte.
     ///
     /// # Arguments
     ///
-    /// * `port` - The port to search for a J2534 device
+    /// * `name` - The vendor-defined name of the device to open
     ///
     /// # Example
     /// ```no_run
@@ -574,20 +1312,37 @@ impl Interface {
     /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
     /// let device = interface.open("COM2").unwrap();
     /// ```
-    pub fn open<S: Into<Vec<u8>>>(&self, port: S) -> Result<Device, Error> {
-        let s = ffi::CString::new(port).unwrap();
-        let raw = s.as_ptr() as *const libc::c_void;
-        let mut id = 0;
-
-        let res = unsafe { (&self.c_pass_thru_open)(raw, &mut id as *mut u32) };
-        if res != 0 {
-            return Err(Error::from_code(res));
-        }
+    pub fn open<S: Into<Vec<u8>>>(&self, name: S) -> Result<Device<'_>, Error> {
+        let name = ffi::CString::new(name).map_err(|err| {
+            Error::InvalidArgument(format!(
+                "device names can't contain nul bytes, found one at position {}",
+                err.nul_position()
+            ))
+        })?;
+        self.open_raw(name.as_ptr() as *const libc::c_void)
+    }
 
-        Ok(Device {
-            interface: self,
-            id: DeviceId(id),
-        })
+    /// Opens the device with `device_name`, like the `Name` of a device listed in the registry
+    /// by drivers that support several attached devices. On Windows the name is converted to the
+    /// ANSI code page that drivers expect; elsewhere it's passed as UTF-8.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the name contains a nul
+    /// character or a character the ANSI code page can't represent. Use [`Interface::open`]
+    /// with the encoded bytes to open a device with any other name.
+    ///
+    /// On J2534-1 v05.00 drivers, the names of the connected devices are returned by
+    /// [`Interface::scan_devices`], and on Drew Technologies drivers by
+    /// [`Interface::enumerate_drewtech_devices`]. Open those with [`Interface::open`] and their
+    /// raw names, which are already in the driver's encoding.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_named("CarDAQ-Plus 3 1234").unwrap();
+    /// ```
+    pub fn open_named(&self, device_name: &str) -> Result<Device<'_>, Error> {
+        self.open(to_ansi(device_name)?)
     }
 
     /// Creates a `Device` from any connected PassThru devices
@@ -598,17 +1353,26 @@ impl Interface {
     /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
     /// let device = interface.open_any().unwrap();
     /// ```
-    pub fn open_any(&self) -> Result<Device, Error> {
-        let raw = std::ptr::null() as *const libc::c_void;
+    pub fn open_any(&self) -> Result<Device<'_>, Error> {
+        self.open_raw(std::ptr::null())
+    }
+
+    /// Calls `PassThruOpen` with `name`, which is null or points to a nul-terminated string
+    fn open_raw(&self, name: *const libc::c_void) -> Result<Device<'_>, Error> {
         let mut id = 0;
-        let res = unsafe { (&self.c_pass_thru_open)(raw, &mut id as *mut u32) };
+        let res = unsafe { self.call(|| (&self.c_pass_thru_open)(name, &mut id as *mut u32)) };
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.error(res));
         }
 
         Ok(Device {
             interface: self,
             id: DeviceId(id),
+            closed: false,
+            call_lock: Mutex::new(()),
+            connection_lost: AtomicBool::new(false),
+            channels: Mutex::new(Vec::new()),
+            energized: Mutex::new(EnergizedPins::default()),
         })
     }
 
@@ -620,15 +1384,88 @@ impl Interface {
         input: *mut libc::c_void,
         output: *mut libc::c_void,
     ) -> Result<i32, Error> {
-        let res = (&self.c_pass_thru_ioctl)(handle, id as u32, input, output);
+        let res = self.call(|| (&self.c_pass_thru_ioctl)(handle, id as u32, input, output));
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.error(res));
         }
         Ok(res)
     }
 }
 
-#[derive(Copy, Clone, FromPrimitive, ToPrimitive)]
+/// Converts a device name to the ANSI code page, which is what drivers expect on Windows
+#[cfg(windows)]
+fn to_ansi(name: &str) -> Result<Vec<u8>, Error> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn WideCharToMultiByte(
+            code_page: u32,
+            flags: u32,
+            wide: *const u16,
+            wide_len: i32,
+            multi_byte: *mut u8,
+            multi_byte_len: i32,
+            default_char: *const u8,
+            used_default_char: *mut i32,
+        ) -> i32;
+    }
+    const CP_ACP: u32 = 0;
+    // Fail instead of replacing characters with similar looking ones
+    const WC_NO_BEST_FIT_CHARS: u32 = 0x400;
+
+    if name.is_ascii() {
+        return Ok(name.as_bytes().to_vec());
+    }
+    let unrepresentable = || {
+        Error::InvalidArgument(format!(
+            "device name {:?} can't be represented in the ANSI code page",
+            name
+        ))
+    };
+    let wide: Vec<u16> = name.encode_utf16().collect();
+    let wide_len = i32::try_from(wide.len()).map_err(|_| unrepresentable())?;
+    unsafe {
+        let len = WideCharToMultiByte(
+            CP_ACP,
+            WC_NO_BEST_FIT_CHARS,
+            wide.as_ptr(),
+            wide_len,
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null(),
+            std::ptr::null_mut(),
+        );
+        if len <= 0 {
+            return Err(unrepresentable());
+        }
+        let mut ansi = vec![0u8; len as usize];
+        let mut used_default_char = 0;
+        let len = WideCharToMultiByte(
+            CP_ACP,
+            WC_NO_BEST_FIT_CHARS,
+            wide.as_ptr(),
+            wide_len,
+            ansi.as_mut_ptr(),
+            len,
+            std::ptr::null(),
+            &mut used_default_char,
+        );
+        if len <= 0 || used_default_char != 0 {
+            return Err(unrepresentable());
+        }
+        ansi.truncate(len as usize);
+        Ok(ansi)
+    }
+}
+
+/// Other systems have no ANSI code page, their drivers take UTF-8 like the rest of the system
+#[cfg(not(windows))]
+fn to_ansi(name: &str) -> Result<Vec<u8>, Error> {
+    Ok(name.as_bytes().to_vec())
+}
+
+// The variants are named like the constants in the J2534 header
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 pub enum Protocol {
     J1850VPW = 1,
     J1850PWM = 2,
@@ -640,6 +1477,49 @@ pub enum Protocol {
     SCI_A_TRANS = 8,
     SCI_B_ENGINE = 9,
     SCI_B_TRANS = 10,
+    /// J1850VPW on pins selected with `J1962_PINS`, from J2534-2
+    J1850VPW_PS = 0x8000,
+    /// J1850PWM on pins selected with `J1962_PINS`, from J2534-2
+    J1850PWM_PS = 0x8001,
+    /// ISO9141 on pins selected with `J1962_PINS`, from J2534-2
+    ISO9141_PS = 0x8002,
+    /// ISO14230 on pins selected with `J1962_PINS`, from J2534-2
+    ISO14230_PS = 0x8003,
+    /// CAN on pins selected with `J1962_PINS`, from J2534-2
+    CAN_PS = 0x8004,
+    /// ISO15765 on pins selected with `J1962_PINS`, from J2534-2
+    ISO15765_PS = 0x8005,
+    /// Single wire CAN (GMLAN) from J2534-2. Set the pins with `J1962_PINS`.
+    SW_CAN_PS = 0x8008,
+}
+
+impl Protocol {
+    /// Returns the J2534-1 protocol a pin-selected protocol runs, or the protocol itself if it
+    /// isn't pin-selected or has no J2534-1 equivalent.
+    pub fn base(self) -> Protocol {
+        match self {
+            Protocol::J1850VPW_PS => Protocol::J1850VPW,
+            Protocol::J1850PWM_PS => Protocol::J1850PWM,
+            Protocol::ISO9141_PS => Protocol::ISO9141,
+            Protocol::ISO14230_PS => Protocol::ISO14230,
+            Protocol::CAN_PS => Protocol::CAN,
+            Protocol::ISO15765_PS => Protocol::ISO15765,
+            other => other,
+        }
+    }
+
+    /// Returns true if the pins of the protocol are selected with `J1962_PINS`
+    pub fn is_pin_selected(self) -> bool {
+        (self as u32) >= 0x8000
+    }
+}
+
+/// Returns true if messages of the protocol start with a 4-byte CAN ID
+pub(crate) fn is_can_protocol(protocol_id: u32) -> bool {
+    matches!(
+        Protocol::from_u32(protocol_id).map(Protocol::base),
+        Some(Protocol::CAN | Protocol::ISO15765 | Protocol::SW_CAN_PS)
+    )
 }
 
 bitflags! {
@@ -723,10 +1603,14 @@ bitflags! {
     }
 }
 
+/// I/O control IDs. `READ_VBATT` and `READ_PROG_VOLTAGE` take a device ID as the handle, see
+/// [`Device::ioctl_raw`]. The others take a channel ID, see [`Channel::ioctl_raw`]. Passing the
+/// wrong kind of ID usually fails with [`Error::InvalidDeviceId`] or [`Error::InvalidChannelId`].
 #[derive(Copy, Clone)]
 pub enum IoctlId {
     GET_CONFIG = 0x01,
     SET_CONFIG = 0x02,
+    /// Takes a device ID
     READ_VBATT = 0x03,
     FIVE_BAUD_INIT = 0x04,
     FAST_INIT = 0x05,
@@ -738,6 +1622,7 @@ pub enum IoctlId {
     CLEAR_FUNCT_MSG_LOOKUP_TABLE = 0x0B,
     ADD_TO_FUNCT_MSG_LOOKUP_TABLE = 0x0C,
     DELETE_FROM_FUNCT_MSG_LOOKUP_TABLE = 0x0D,
+    /// Takes a device ID
     READ_PROG_VOLTAGE = 0x0E,
 
     SW_CAN_HS = 0x8000,
@@ -746,8 +1631,9 @@ pub enum IoctlId {
     BECOME_MASTER = 0x8003,
 }
 
-#[derive(Copy, Clone)]
-/// Channel configuration parameters. Use with [`Channel::get_config`] and [`Channel::set_config`]
+/// Channel configuration parameters. Use with [`Channel::get_config`] and [`Channel::set_config`].
+/// Vendor-specific parameters that aren't listed can be set with [`Channel::set_configs_unchecked`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ConfigId {
     DATA_RATE = 0x01,
     LOOPBACK = 0x03,
@@ -805,7 +1691,123 @@ pub enum ConfigId {
     INPUT_RANGE_HIGH = 0x8027,
 }
 
-#[derive(Copy, Clone, Debug, FromPrimitive, ToPrimitive)]
+impl ConfigId {
+    /// Every variant, for converting numbers
+    const ALL: [ConfigId; 49] = [
+        ConfigId::DATA_RATE,
+        ConfigId::LOOPBACK,
+        ConfigId::NODE_ADDRESS,
+        ConfigId::NETWORK_LINE,
+        ConfigId::P1_MIN,
+        ConfigId::P1_MAX,
+        ConfigId::P2_MIN,
+        ConfigId::P2_MAX,
+        ConfigId::P3_MIN,
+        ConfigId::P3_MAX,
+        ConfigId::P4_MIN,
+        ConfigId::P4_MAX,
+        ConfigId::W1,
+        ConfigId::W2,
+        ConfigId::W3,
+        ConfigId::W4,
+        ConfigId::W5,
+        ConfigId::TIDLE,
+        ConfigId::TINIL,
+        ConfigId::TWUP,
+        ConfigId::PARITY,
+        ConfigId::BIT_SAMPLE_POINT,
+        ConfigId::SYNC_JUMP_WIDTH,
+        ConfigId::W0,
+        ConfigId::T1_MAX,
+        ConfigId::T2_MAX,
+        ConfigId::T4_MAX,
+        ConfigId::T5_MAX,
+        ConfigId::ISO15765_BS,
+        ConfigId::ISO15765_STMIN,
+        ConfigId::DATA_BITS,
+        ConfigId::FIVE_BAUD_MOD,
+        ConfigId::BS_TX,
+        ConfigId::STMIN_TX,
+        ConfigId::T3_MAX,
+        ConfigId::ISO15765_WFT_MAX,
+        ConfigId::CAN_MIXED_FORMAT,
+        ConfigId::J1962_PINS,
+        ConfigId::SW_CAN_HS_DATA_RATE,
+        ConfigId::SW_CAN_SPEEDCHANGE_ENABLE,
+        ConfigId::SW_CAN_RES_SWITCH,
+        ConfigId::ACTIVE_CHANNELS,
+        ConfigId::SAMPLE_RATE,
+        ConfigId::SAMPLES_PER_READING,
+        ConfigId::READINGS_PER_MSG,
+        ConfigId::AVERAGING_METHOD,
+        ConfigId::SAMPLE_RESOLUTION,
+        ConfigId::INPUT_RANGE_LOW,
+        ConfigId::INPUT_RANGE_HIGH,
+    ];
+
+    /// Returns true if J2534-1 allows setting the parameter on channels using `protocol`.
+    /// Vendor-specific parameters are allowed on every channel.
+    pub fn applies_to(self, protocol: Protocol) -> bool {
+        use ConfigId::*;
+
+        let pin_selected = protocol.is_pin_selected();
+        let protocol = protocol.base();
+        let k_line = matches!(protocol, Protocol::ISO9141 | Protocol::ISO14230);
+        let can = matches!(
+            protocol,
+            Protocol::CAN | Protocol::ISO15765 | Protocol::SW_CAN_PS
+        );
+        let sci = matches!(
+            protocol,
+            Protocol::SCI_A_ENGINE
+                | Protocol::SCI_A_TRANS
+                | Protocol::SCI_B_ENGINE
+                | Protocol::SCI_B_TRANS
+        );
+        match self {
+            DATA_RATE | LOOPBACK => true,
+            NODE_ADDRESS | NETWORK_LINE => protocol == Protocol::J1850PWM,
+            P1_MIN | P1_MAX | P2_MIN | P2_MAX | P3_MIN | P3_MAX | P4_MIN | P4_MAX | W0 | W1
+            | W2 | W3 | W4 | W5 | TIDLE | TINIL | TWUP | PARITY | DATA_BITS | FIVE_BAUD_MOD => {
+                k_line
+            }
+            BIT_SAMPLE_POINT | SYNC_JUMP_WIDTH => can,
+            ISO15765_BS | ISO15765_STMIN | BS_TX | STMIN_TX | ISO15765_WFT_MAX => {
+                protocol == Protocol::ISO15765
+            }
+            T1_MAX | T2_MAX | T3_MAX | T4_MAX | T5_MAX => sci,
+            J1962_PINS => pin_selected,
+            CAN_MIXED_FORMAT => protocol == Protocol::ISO15765,
+            // Extensions aren't checked
+            _ => true,
+        }
+    }
+}
+
+impl TryFrom<u32> for ConfigId {
+    type Error = Error;
+
+    /// Converts a configuration parameter listed in [`ConfigId`]. Returns
+    /// [`Error::InvalidArgument`] for other values.
+    fn try_from(id: u32) -> Result<ConfigId, Error> {
+        let known = ConfigId::ALL
+            .iter()
+            .copied()
+            .find(|&config| config as u32 == id);
+        known.ok_or_else(|| {
+            Error::InvalidArgument(format!("{:#X} is not a known configuration parameter", id))
+        })
+    }
+}
+
+impl fmt::Display for ConfigId {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // The variants are named like the constants in the J2534 header
+        Debug::fmt(self, f)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 pub enum FilterType {
     /// Allows matching messages into the receive queue. This filter type is only valid on non-ISO 15765 channels
     Pass = 1,
@@ -816,6 +1818,28 @@ pub enum FilterType {
     FlowControl = 3,
 }
 
+impl TryFrom<u32> for FilterType {
+    type Error = Error;
+
+    /// Converts a filter type defined by J2534. Returns [`Error::InvalidArgument`] for other values,
+    /// which can be used with [`Channel::start_message_filter_raw`].
+    fn try_from(filter_type: u32) -> Result<FilterType, Error> {
+        FilterType::from_u32(filter_type).ok_or_else(|| {
+            Error::InvalidArgument(format!("{:#X} is not a J2534 filter type", filter_type))
+        })
+    }
+}
+
+impl fmt::Display for FilterType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FilterType::Pass => "PASS_FILTER",
+            FilterType::Block => "BLOCK_FILTER",
+            FilterType::FlowControl => "FLOW_CONTROL_FILTER",
+        })
+    }
+}
+
 #[derive(Debug)]
 /// Information about a device's version.
 pub struct VersionInfo {
@@ -827,22 +1851,179 @@ pub struct VersionInfo {
 pub const SHORT_TO_GROUND: u32 = 0xFFFFFFFE;
 pub const VOLTAGE_OFF: u32 = 0xFFFFFFFF;
 
-impl<'a> Device<'a> {
-    /// Reads the version info of the device
-    pub fn read_version(&self) -> Result<VersionInfo, Error> {
-        let mut firmware_version: [u8; 80] = [0; 80];
+/// The lowest programming voltage J2534-1 allows in millivolts
+pub const MIN_PROGRAMMING_VOLTAGE: u32 = 5000;
+/// The highest programming voltage J2534-1 allows in millivolts
+pub const MAX_PROGRAMMING_VOLTAGE: u32 = 20000;
+
+/// A J1962 connector pin the device can apply a programming voltage to, used with
+/// [`Device::set_programming_voltage`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pin {
+    /// The auxiliary output of the device, which isn't part of the J1962 connector
+    Aux = 0,
+    Pin6 = 6,
+    Pin9 = 9,
+    Pin11 = 11,
+    Pin12 = 12,
+    Pin13 = 13,
+    Pin14 = 14,
+    /// Can only be shorted to ground
+    Pin15 = 15,
+}
+
+/// What [`Device::set_programming_voltage`] applies to a pin
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ProgrammingVoltage {
+    /// Turns the output off (`VOLTAGE_OFF`)
+    Off,
+    /// Shorts the pin to ground (`SHORT_TO_GROUND`). Only valid on [`Pin::Pin15`].
+    ShortToGround,
+    /// Outputs a voltage from [`MIN_PROGRAMMING_VOLTAGE`] to [`MAX_PROGRAMMING_VOLTAGE`] millivolts
+    Millivolts(u32),
+}
+
+impl ProgrammingVoltage {
+    /// Returns the value passed to `PassThruSetProgrammingVoltage`
+    pub fn value(self) -> u32 {
+        match self {
+            ProgrammingVoltage::Off => VOLTAGE_OFF,
+            ProgrammingVoltage::ShortToGround => SHORT_TO_GROUND,
+            ProgrammingVoltage::Millivolts(millivolts) => millivolts,
+        }
+    }
+}
+
+impl<'a> Device<'a> {
+    /// Wraps a device that was opened with `PassThruOpen` outside of this crate.
+    ///
+    /// # Safety
+    ///
+    /// `id` must be a device ID returned by `PassThruOpen` of the library loaded by `interface`
+    /// that hasn't been closed. The returned device takes ownership of it and closes it when
+    /// dropped, so nothing else may close it.
+    pub unsafe fn from_raw(interface: &'a Interface, id: u32) -> Device<'a> {
+        Device {
+            interface,
+            id: DeviceId(id),
+            closed: false,
+            call_lock: Mutex::new(()),
+            connection_lost: AtomicBool::new(false),
+            channels: Mutex::new(Vec::new()),
+            energized: Mutex::new(EnergizedPins::default()),
+        }
+    }
+
+    /// Returns the device ID assigned by the driver
+    pub fn id(&self) -> u32 {
+        self.id.0
+    }
+
+    /// Returns the device ID without closing the device. The caller becomes responsible for
+    /// calling `PassThruClose`, or can wrap the ID again with [`Device::from_raw`].
+    pub fn into_raw(mut self) -> u32 {
+        self.closed = true;
+        self.id.0
+    }
+
+    /// Returns true if the device is still connected. Checks the battery voltage, or reads the
+    /// version if the driver can't measure it.
+    ///
+    /// Once a call fails with [`Error::DeviceNotConnected`], the device remembers it and further
+    /// device-level calls and channel reads and writes fail with
+    /// [`Error::PreviouslyDisconnected`] without calling the driver. This method still calls the
+    /// driver and forgets the failure if the device answers, so it can be polled to wait for the
+    /// hardware to be reconnected. [`Device::clear_connection_lost`] forgets it without a check.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// while !device.is_connected() {
+    ///     println!("reconnect the device");
+    ///     std::thread::sleep(Duration::from_secs(1));
+    /// }
+    /// ```
+    pub fn is_connected(&self) -> bool {
+        let result = match self.read_battery_voltage_unchecked() {
+            Err(err) if matches!(err.inner(), Error::NotSupported | Error::InvalidIoctlId) => {
+                self.read_version_unchecked().map(|_| ())
+            }
+            result => result.map(|_| ()),
+        };
+        let connected = !matches!(result, Err(err) if err.is_disconnect());
+        if connected {
+            self.clear_connection_lost();
+        }
+        connected
+    }
+
+    /// Forgets that a call failed with [`Error::DeviceNotConnected`], so that the driver is
+    /// called again
+    pub fn clear_connection_lost(&self) {
+        self.connection_lost.store(false, Ordering::Relaxed);
+    }
+
+    /// Fails with [`Error::PreviouslyDisconnected`] if an earlier call found the device
+    /// disconnected
+    fn check_connected(&self) -> Result<(), Error> {
+        if self.connection_lost.load(Ordering::Relaxed) {
+            return Err(Error::PreviouslyDisconnected);
+        }
+        Ok(())
+    }
+
+    /// Remembers if `err` says that the device was disconnected
+    fn note_error(&self, err: Error) -> Error {
+        if let Error::DeviceNotConnected = err.inner() {
+            self.connection_lost.store(true, Ordering::Relaxed);
+        }
+        err
+    }
+
+    /// Closes the device. Unlike dropping the device, this reports errors returned by the
+    /// driver, like [`Error::InvalidDeviceId`] if the device was unplugged.
+    ///
+    /// Channels borrow the device, so they have to be disconnected or dropped before it can be
+    /// closed.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.closed = true;
+        let res = unsafe {
+            self.interface
+                .call(|| (self.interface.c_pass_thru_close)(self.id.0))
+        };
+        if res != 0 {
+            return Err(self.interface.error(res));
+        }
+        Ok(())
+    }
+
+    /// Reads the version info of the device
+    pub fn read_version(&self) -> Result<VersionInfo, Error> {
+        self.check_connected()?;
+        self.read_version_unchecked()
+    }
+
+    fn read_version_unchecked(&self) -> Result<VersionInfo, Error> {
+        let _lock = self.call_lock.lock().unwrap();
+        let mut firmware_version: [u8; 80] = [0; 80];
         let mut dll_version: [u8; 80] = [0; 80];
         let mut api_version: [u8; 80] = [0; 80];
         let res = unsafe {
-            (&self.interface.c_pass_thru_read_version)(
-                self.id.0,
-                firmware_version.as_mut_ptr() as *mut libc::c_char,
-                dll_version.as_mut_ptr() as *mut libc::c_char,
-                api_version.as_mut_ptr() as *mut libc::c_char,
-            )
+            self.interface.call(|| {
+                (&self.interface.c_pass_thru_read_version)(
+                    self.id.0,
+                    firmware_version.as_mut_ptr() as *mut libc::c_char,
+                    dll_version.as_mut_ptr() as *mut libc::c_char,
+                    api_version.as_mut_ptr() as *mut libc::c_char,
+                )
+            })
         };
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.note_error(self.interface.error(res)));
         }
         unsafe {
             Ok(VersionInfo {
@@ -858,18 +2039,77 @@ impl<'a> Device<'a> {
 
     /// Outputs a programmable voltage on the specified J1962 connector pin.
     /// Only one pin can have a specified voltage applied at a time. The only exception: it is permissible to program pin 15 for SHORT_TO_GROUND, and another pin to a voltage level.
-    /// When switching pins, the user application must disable the first voltage (VOLTAGE_OFF option) before enabling the second.
+    /// When switching pins, the user application must disable the first voltage ([`ProgrammingVoltage::Off`]) before enabling the second.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the voltage is out of
+    /// range or can't be applied to the pin. Pin 15 can only be shorted to ground, and the other
+    /// pins can't be. Applying a voltage while another pin set through this method still has one
+    /// is rejected as well. See [`Device::pin`] for guards that turn pins off again.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{Interface, Pin, ProgrammingVoltage};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// device.set_programming_voltage(Pin::Pin13, ProgrammingVoltage::Millivolts(18000)).unwrap();
+    /// // ...
+    /// device.set_programming_voltage(Pin::Pin13, ProgrammingVoltage::Off).unwrap();
+    /// ```
+    pub fn set_programming_voltage(
+        &self,
+        pin: Pin,
+        voltage: ProgrammingVoltage,
+    ) -> Result<(), Error> {
+        match (pin, voltage) {
+            (_, ProgrammingVoltage::Off) | (Pin::Pin15, ProgrammingVoltage::ShortToGround) => {}
+            (Pin::Pin15, _) => {
+                return Err(Error::InvalidArgument(
+                    "pin 15 can only be shorted to ground".to_string(),
+                ))
+            }
+            (_, ProgrammingVoltage::ShortToGround) => {
+                return Err(Error::InvalidArgument(format!(
+                    "only pin 15 can be shorted to ground, not {:?}",
+                    pin
+                )))
+            }
+            (_, ProgrammingVoltage::Millivolts(millivolts)) => {
+                if !(MIN_PROGRAMMING_VOLTAGE..=MAX_PROGRAMMING_VOLTAGE).contains(&millivolts) {
+                    return Err(Error::InvalidArgument(format!(
+                        "{} mV is outside the programming voltage range of {} to {} mV",
+                        millivolts, MIN_PROGRAMMING_VOLTAGE, MAX_PROGRAMMING_VOLTAGE
+                    )));
+                }
+            }
+        }
+        let mut energized = self.energized.lock().unwrap();
+        energized.check(pin, voltage)?;
+        self.set_programming_voltage_raw(pin as u32, voltage.value())?;
+        energized.record(pin, voltage);
+        Ok(())
+    }
+
+    /// Like [`Device::set_programming_voltage`], but doesn't check the arguments so that
+    /// vendor-specific pins and voltages can be used.
     ///
     /// # Arguments
     ///
     /// * `pin_number` - The J1962 connector pin to which the PassThru device will apply the specified voltage
-    /// * `voltage` - The voltage value (in millivolts) that will be applied to the specified pin
-    pub fn set_programming_voltage(&self, pin_number: u32, voltage: u32) -> Result<(), Error> {
+    /// * `voltage` - The voltage value (in millivolts) that will be applied to the specified pin,
+    ///   or [`VOLTAGE_OFF`] or [`SHORT_TO_GROUND`]
+    pub fn set_programming_voltage_raw(&self, pin_number: u32, voltage: u32) -> Result<(), Error> {
+        self.check_connected()?;
+        let _lock = self.call_lock.lock().unwrap();
         let res = unsafe {
-            (&self.interface.c_pass_thru_set_programming_voltage)(self.id.0, pin_number, voltage)
+            self.interface.call(|| {
+                (&self.interface.c_pass_thru_set_programming_voltage)(
+                    self.id.0, pin_number, voltage,
+                )
+            })
         };
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.note_error(self.interface.error(res)));
         }
         Ok(())
     }
@@ -881,34 +2121,125 @@ impl<'a> Device<'a> {
     /// * protocol - Protocol to use with the channel
     /// * flags - Protocol-specific flags. This is usually set to zero
     /// * baudrate - Initial baud rate for the channel
+    ///
+    /// Returns [`Error::InvalidArgument`] listing the accepted rates without calling the driver if
+    /// the protocol can't run at the baud rate. CAN and ISO 15765 channels accept 10000 to 1000000
+    /// baud, since devices program rates J2534-1 doesn't list. Use [`Device::connect_unchecked`]
+    /// for devices that support other rates.
+    ///
+    /// Returns [`Error::AlreadyConnected`] without calling the driver if a channel on the same
+    /// bus is already connected through the device, which most drivers reject with
+    /// [`Error::ChannelInUse`]. CAN and ISO 15765 share a bus, as do ISO 9141 and ISO 14230, and
+    /// each pin-selected protocol shares one with its J2534-1 counterpart. Use
+    /// [`Device::connect_raw`] for devices that allow it.
     pub fn connect(
         &self,
         protocol: Protocol,
         flags: ConnectFlags,
         baudrate: u32,
-    ) -> Result<Channel, Error> {
+    ) -> Result<Channel<'_>, Error> {
+        let rates = BaudRates::of(protocol);
+        if !rates.contains(baudrate) {
+            return Err(Error::InvalidArgument(format!(
+                "{} baud is not valid for {:?} channels, the accepted rates are {}",
+                baudrate, protocol, rates
+            )));
+        }
+        self.connect_unchecked(protocol, flags, baudrate)
+    }
+
+    /// Like [`Device::connect`], but doesn't check the baud rate.
+    pub fn connect_unchecked(
+        &self,
+        protocol: Protocol,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) -> Result<Channel<'_>, Error> {
+        self.connect_tracked(protocol as u32, flags, baudrate, true)
+    }
+
+    /// Like [`Device::connect_unchecked`], but takes the protocol ID as a number so that vendor-specific
+    /// protocols can be used. Doesn't check whether the protocol is already connected.
+    pub fn connect_raw(
+        &self,
+        protocol_id: u32,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) -> Result<Channel<'_>, Error> {
+        self.connect_tracked(protocol_id, flags, baudrate, false)
+    }
+
+    /// Connects a channel and registers it, rejecting protocols on a bus that is in use if
+    /// `check_bus` is set
+    fn connect_tracked(
+        &self,
+        protocol_id: u32,
+        flags: ConnectFlags,
+        baudrate: u32,
+        check_bus: bool,
+    ) -> Result<Channel<'_>, Error> {
+        self.check_connected()?;
+        // Held until the channel is registered, so that two threads can't connect the same bus
+        let mut channels = self.channels.lock().unwrap();
+        if check_bus {
+            registry::check_bus_free(&channels, protocol_id)?;
+        }
         let mut id: u32 = 0;
         let res = unsafe {
-            (&self.interface.c_pass_thru_connect)(
-                self.id.0,
-                protocol as u32,
-                flags.bits,
-                baudrate,
-                &mut id as *mut u32,
-            )
+            let _lock = self.call_lock.lock().unwrap();
+            self.interface.call(|| {
+                (&self.interface.c_pass_thru_connect)(
+                    self.id.0,
+                    protocol_id,
+                    flags.bits,
+                    baudrate,
+                    &mut id as *mut u32,
+                )
+            })
         };
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.note_error(self.interface.error(res)));
         }
-        Ok(Channel {
-            device: self,
-            id: ChannelId(id),
-            protocol: protocol as Protocol,
-        })
+        channels.push(OpenChannel::new(id, protocol_id, flags, baudrate));
+        // The channel was just connected and is owned by nothing else
+        Ok(unsafe { Channel::from_raw_unregistered(self, id, protocol_id, flags, baudrate) })
+    }
+
+    /// Calls `PassThruIoctl` with the device ID as the handle, for device-level ioctls such as
+    /// `READ_VBATT` and vendor-specific ones. Channel-level ioctls need [`Channel::ioctl_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `input` and `output` must be valid for the structures the ioctl reads and writes, or null
+    /// if it doesn't use them.
+    pub unsafe fn ioctl_raw(
+        &self,
+        ioctl_id: u32,
+        input: *mut libc::c_void,
+        output: *mut libc::c_void,
+    ) -> Result<(), Error> {
+        self.check_connected()?;
+        let _lock = self.call_lock.lock().unwrap();
+        let res = self
+            .interface
+            .call(|| (self.interface.c_pass_thru_ioctl)(self.id.0, ioctl_id, input, output));
+        if res != 0 {
+            return Err(self.note_error(self.interface.error(res)));
+        }
+        Ok(())
     }
 
     /// Returns the battery voltage in millivolts read from Pin 16 on the J1962 connector.
+    ///
+    /// Some drivers can't measure the voltage before a channel is connected and return
+    /// [`Error::NotSupported`] until then.
     pub fn read_battery_voltage(&self) -> Result<u32, Error> {
+        self.check_connected()?;
+        self.read_battery_voltage_unchecked()
+    }
+
+    fn read_battery_voltage_unchecked(&self) -> Result<u32, Error> {
+        let _lock = self.call_lock.lock().unwrap();
         let mut voltage: u32 = 0;
         unsafe {
             self.interface.ioctl(
@@ -917,12 +2248,36 @@ impl<'a> Device<'a> {
                 std::ptr::null_mut::<libc::c_void>(),
                 (&mut voltage) as *mut _ as *mut libc::c_void,
             )
-        }?;
+        }
+        .map_err(|err| self.note_error(err))?;
         Ok(voltage)
     }
 
-    /// Returns the current output voltage for ECU reprogramming in millivolts.
+    /// Like [`Device::read_battery_voltage`], but returns volts
+    pub fn read_battery_voltage_volts(&self) -> Result<f32, Error> {
+        Ok(self.read_battery_voltage()? as f32 / 1000.0)
+    }
+
+    /// Returns the current output voltage for ECU reprogramming in millivolts. Returns
+    /// [`Error::NotSupported`] if the device can't measure it.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{Interface, Pin, ProgrammingVoltage};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    ///
+    /// // Apply 18V to pin 13 and wait until it is within 0.5V
+    /// device.set_programming_voltage(Pin::Pin13, ProgrammingVoltage::Millivolts(18000)).unwrap();
+    /// while (device.read_programming_voltage().unwrap() as i64 - 18000).abs() > 500 {
+    ///     std::thread::sleep(Duration::from_millis(10));
+    /// }
+    /// ```
     pub fn read_programming_voltage(&self) -> Result<u32, Error> {
+        self.check_connected()?;
+        let _lock = self.call_lock.lock().unwrap();
         let mut voltage: u32 = 0;
         unsafe {
             self.interface.ioctl(
@@ -931,145 +2286,1164 @@ impl<'a> Device<'a> {
                 std::ptr::null_mut::<libc::c_void>(),
                 (&mut voltage) as *mut _ as *mut libc::c_void,
             )
-        }?;
+        }
+        .map_err(|err| self.note_error(err))?;
         Ok(voltage)
     }
 }
 
 impl<'a> Drop for Device<'a> {
     fn drop(&mut self) {
-        unsafe { (&self.interface.c_pass_thru_close)(self.id.0) };
+        if !self.closed {
+            unsafe {
+                self.interface
+                    .call(|| (&self.interface.c_pass_thru_close)(self.id.0))
+            };
+        }
     }
 }
 
-impl<'a> Channel<'a> {
-    /// Fills `msgs` with messages until timing out or until `msgs` is filled. Returns the slice of messages read.
-    ///
-    /// # Arguments
-    ///
-    /// * `msgs` - The array of messages to fill.
-    /// * `timeout` - The amount of time in milliseconds to wait. If set to zero, reads buffered messages and returns immediately
-    ///
-    /// Returns the amount of messages read.
-    pub fn read(&self, buf: &mut [PassThruMsg], timeout: u32) -> Result<usize, Error> {
-        for msg in buf.iter_mut() {
-            msg.protocol_id = self.protocol as u32;
+/// The baud rates a protocol can run at
+enum BaudRates {
+    Fixed(&'static [u32]),
+    Range(u32, u32),
+}
+
+impl BaudRates {
+    fn of(protocol: Protocol) -> BaudRates {
+        match protocol {
+            Protocol::J1850VPW | Protocol::J1850VPW_PS => BaudRates::Fixed(&[10400, 41600]),
+            Protocol::J1850PWM | Protocol::J1850PWM_PS => BaudRates::Fixed(&[41600, 83300]),
+            Protocol::ISO9141
+            | Protocol::ISO9141_PS
+            | Protocol::ISO14230
+            | Protocol::ISO14230_PS => BaudRates::Range(4800, 10400),
+            // J2534-1 only requires 125000, 250000 and 500000, but devices program any rate the
+            // CAN controller can produce
+            Protocol::CAN | Protocol::CAN_PS | Protocol::ISO15765 | Protocol::ISO15765_PS => {
+                BaudRates::Range(10000, 1000000)
+            }
+            // Normal and high speed mode
+            Protocol::SW_CAN_PS => BaudRates::Fixed(&[33333, 83333]),
+            Protocol::SCI_A_ENGINE
+            | Protocol::SCI_A_TRANS
+            | Protocol::SCI_B_ENGINE
+            | Protocol::SCI_B_TRANS => BaudRates::Fixed(&[7812, 62500]),
         }
-        let mut num_msgs: u32 = buf.len() as u32;
-        let res = unsafe {
-            (&self.device.interface.c_pass_thru_read_msgs)(
-                self.id.0,
-                buf.as_mut_ptr(),
-                &mut num_msgs as *mut u32,
-                timeout,
-            )
-        };
-        if res != 0 {
-            return Err(Error::from_code(res));
+    }
+
+    fn contains(&self, baudrate: u32) -> bool {
+        match *self {
+            BaudRates::Fixed(rates) => rates.contains(&baudrate),
+            BaudRates::Range(min, max) => (min..=max).contains(&baudrate),
         }
-        Ok(num_msgs as usize)
     }
+}
 
-    /// Reads a single message
-    pub fn read_once(&self, timeout: u32) -> Result<PassThruMsg, Error> {
-        let mut msg = PassThruMsg::new(self.protocol);
+impl fmt::Display for BaudRates {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            BaudRates::Fixed(rates) => {
+                let rates: Vec<String> = rates.iter().map(|rate| rate.to_string()).collect();
+                write!(f, "{}", rates.join(", "))
+            }
+            BaudRates::Range(min, max) => write!(f, "{} to {}", min, max),
+        }
+    }
+}
 
-        let mut num_msgs = 1 as u32;
-        let res = unsafe {
-            (&self.device.interface.c_pass_thru_read_msgs)(
-                self.id.0,
-                &mut msg as *mut PassThruMsg,
-                &mut num_msgs as *mut u32,
-                timeout,
-            )
-        };
-        if num_msgs == 0 {
-            // This should never happen, but just in case...
-            Err(Error::BufferEmpty)
-        } else if res != 0 {
-            Err(Error::from_code(res))
-        } else {
-            Ok(msg)
+/// Checks a configuration parameter value against the range J2534 allows for it
+fn check_config(protocol: Option<Protocol>, id: ConfigId, value: u32) -> Result<(), Error> {
+    let max = match id {
+        ConfigId::LOOPBACK => 1,
+        ConfigId::ISO15765_BS | ConfigId::ISO15765_STMIN => 0xFF,
+        ConfigId::CAN_MIXED_FORMAT => MixedFormat::AllFrames as u32,
+        ConfigId::DATA_RATE => {
+            if let Some(protocol) = protocol {
+                let rates = BaudRates::of(protocol);
+                if !rates.contains(value) {
+                    return Err(Error::InvalidArgument(format!(
+                        "{} baud is not valid for {:?} channels, the accepted rates are {}",
+                        value, protocol, rates
+                    )));
+                }
+            }
+            return Ok(());
         }
+        _ => return Ok(()),
+    };
+    if value > max {
+        return Err(Error::InvalidArgument(format!(
+            "{:#X} is not a valid value for configuration parameter {:#X}, the maximum is {:#X}",
+            value, id as u32, max
+        )));
     }
+    Ok(())
+}
 
-    /// Writes `msgs` to the device until all messages have been written or until the timeout has been reached. Returns the amount of message written.
+/// Converts a timeout to milliseconds. Sub-millisecond timeouts are rounded up so that they never
+/// become a zero (non-blocking) timeout, and long timeouts saturate at `u32::MAX`.
+fn timeout_millis(timeout: Duration) -> u32 {
+    let millis = timeout.as_nanos().div_ceil(1_000_000);
+    millis.min(u32::MAX as u128) as u32
+}
+
+/// Returns the deadline for a timeout starting now
+fn deadline_after(timeout: Duration) -> Instant {
+    // Clamp to the longest timeout the driver accepts so that the addition can't overflow
+    Instant::now() + timeout.min(Duration::from_millis(u32::MAX as u64))
+}
+
+/// Calls `read` with the time remaining until `deadline` until it returns something other than a
+/// timeout or the deadline passes. `read` is called at least once.
+fn retry_until<T>(
+    deadline: Instant,
+    mut read: impl FnMut(Duration) -> Result<T, Error>,
+) -> Result<T, Error> {
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match read(timeout) {
+            // The driver may return slightly before the timeout expires
+            Err(Error::Timeout) | Err(Error::BufferEmpty) if !timeout.is_zero() => {}
+            Err(Error::BufferEmpty) => return Err(Error::Timeout),
+            other => return other,
+        }
+    }
+}
+
+/// The longest time a cancelable read waits before checking its [`CancelToken`]
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Cancels blocking reads from another thread. Clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Creates a token that has not been cancelled
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels all reads using this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if [`CancelToken::cancel`] has been called
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the cancellation so the token can be reused
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A message confirmed by [`Channel::write_confirmed`]
+#[derive(Debug)]
+pub struct Confirmation {
+    /// The hardware timestamp of the echoed message in microseconds
+    pub timestamp: u32,
+    /// The echoed message
+    pub echo: PassThruMsg,
+    /// Other messages received while waiting for the echo
+    pub received: Vec<PassThruMsg>,
+}
+
+/// Checks the messages of a filter before it is passed to the driver
+fn check_filter_msgs(
+    filter_type: FilterType,
+    mask_msg: Option<&PassThruMsg>,
+    pattern_msg: Option<&PassThruMsg>,
+    flow_control_msg: Option<&PassThruMsg>,
+) -> Result<(), Error> {
+    if let (Some(mask), Some(pattern)) = (mask_msg, pattern_msg) {
+        let (mask_size, pattern_size) = (mask.data_size, pattern.data_size);
+        if mask_size != pattern_size {
+            return Err(Error::InvalidArgument(format!(
+                "filter mask is {} bytes long but the pattern is {} bytes long",
+                mask_size, pattern_size
+            )));
+        }
+    }
+    match (filter_type, flow_control_msg) {
+        (FilterType::FlowControl, None) => Err(Error::InvalidArgument(
+            "flow control filters need a flow control message".to_string(),
+        )),
+        (FilterType::Pass, Some(_)) | (FilterType::Block, Some(_)) => {
+            Err(Error::InvalidArgument(format!(
+                "{:?} filters don't take a flow control message",
+                filter_type
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The intervals in milliseconds allowed for periodic messages
+const PERIODIC_INTERVAL_RANGE: std::ops::RangeInclusive<u32> = 5..=65535;
+
+/// The amount of messages read per call to `PassThruReadMsgs` by helpers that read in batches
+const READ_BATCH_SIZE: usize = 16;
+
+impl<'a> Channel<'a> {
+    /// Wraps a channel that was connected with `PassThruConnect` outside of this crate.
+    /// `protocol_id`, `flags` and `baudrate` must be the arguments it was connected with.
     ///
-    /// # Arguments
+    /// # Safety
     ///
-    /// * msgs - The array of messages to send.
-    /// * timeout - The amount of time in milliseconds to wait. If set to zero, queues as many messages as possible and returns immediately.
-    pub fn write(&self, msgs: &mut [PassThruMsg], timeout: u32) -> Result<usize, Error> {
-        let mut num_msgs: u32 = msgs.len() as u32;
-        let res = unsafe {
-            (&self.device.interface.c_pass_thru_write_msgs)(
-                self.id.0,
-                msgs.as_mut_ptr(),
-                &mut num_msgs as *mut u32,
-                timeout,
-            )
-        };
-        if res != 0 {
-            return Err(Error::from_code(res));
+    /// `id` must be a channel ID returned by `PassThruConnect` for `device` that hasn't been
+    /// disconnected. The returned channel takes ownership of it and disconnects it when dropped,
+    /// so nothing else may disconnect it.
+    pub unsafe fn from_raw(
+        device: &'a Device<'a>,
+        id: u32,
+        protocol_id: u32,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) -> Channel<'a> {
+        device.register_channel(id, protocol_id, flags, baudrate);
+        Channel::from_raw_unregistered(device, id, protocol_id, flags, baudrate)
+    }
+
+    /// Like [`Channel::from_raw`], but leaves registering the channel with the device to the
+    /// caller
+    unsafe fn from_raw_unregistered(
+        device: &'a Device<'a>,
+        id: u32,
+        protocol_id: u32,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) -> Channel<'a> {
+        Channel {
+            device,
+            id: ChannelId(id),
+            protocol_id,
+            protocol: Protocol::from_u32(protocol_id),
+            flags,
+            baudrate: AtomicU32::new(baudrate),
+            stats: StatsCounters::new(),
+            pending_writes: AtomicUsize::new(0),
+            disconnected: false,
+            tracked: Mutex::new(Tracked::default()),
+            echo: Mutex::new(EchoState::default()),
+            bus_errors: Mutex::new(BusErrorState::default()),
         }
-        Ok(num_msgs as usize)
     }
 
-    /// Sets up a network protocol filter to filter messages received by the PassThru device. There is a limit of ten filters per network layer protocol.
-    /// The device blocks all receive frames by default when no filters are defined.
+    /// Returns the channel ID assigned by the driver
+    pub fn id(&self) -> u32 {
+        self.id.0
+    }
+
+    /// Returns the channel ID without disconnecting the channel. The caller becomes responsible
+    /// for calling `PassThruDisconnect`, or can wrap the ID again with [`Channel::from_raw`].
     ///
-    /// Returns filter ID
-    /// http://www.drewtech.com/support/passthru/startmsgfilter.html
-    pub fn start_message_filter(
-        &self,
-        filter_type: FilterType,
-        mask_msg: Option<&PassThruMsg>,
-        pattern_msg: Option<&PassThruMsg>,
-        flow_control_msg: Option<&PassThruMsg>,
-    ) -> Result<FilterId, Error> {
-        let mut msg_id: u32 = 0;
+    /// The filters and periodic messages started through the channel keep running and are no
+    /// longer stopped by this crate.
+    pub fn into_raw(mut self) -> u32 {
+        self.disconnected = true;
+        self.device.unregister_channel(self.id.0);
+        self.id.0
+    }
 
-        let mask_ptr = match mask_msg {
-            Some(msg) => msg as *const PassThruMsg,
-            None => std::ptr::null() as *const PassThruMsg,
-        };
+    /// Returns the protocol the channel was created with, or `None` if the protocol ID isn't
+    /// a standard one. See [`Channel::protocol_id`].
+    pub fn protocol(&self) -> Option<Protocol> {
+        self.protocol
+    }
 
-        let pattern_ptr = match pattern_msg {
-            Some(msg) => msg as *const PassThruMsg,
-            None => std::ptr::null() as *const PassThruMsg,
-        };
+    /// Returns the protocol ID the channel was created with
+    pub fn protocol_id(&self) -> u32 {
+        self.protocol_id
+    }
 
-        let flow_control_ptr = match flow_control_msg {
-            Some(msg) => msg as *const PassThruMsg,
-            None => std::ptr::null() as *const PassThruMsg,
-        };
+    /// Returns the flags the channel was created with
+    pub fn flags(&self) -> ConnectFlags {
+        self.flags
+    }
 
+    /// Returns the baud rate the channel was created with, or the last one set with
+    /// [`Channel::set_data_rate`] or the `DATA_RATE` parameter
+    pub fn baudrate(&self) -> u32 {
+        self.baudrate.load(Ordering::Relaxed)
+    }
+
+    /// Disconnects the channel. Unlike dropping the channel, this reports errors returned by
+    /// the driver.
+    ///
+    /// Like when dropping the channel, the filters and periodic messages started through the
+    /// channel are stopped first unless [`Channel::clear_tracked`] was called.
+    pub fn disconnect(mut self) -> Result<(), Error> {
+        self.disconnected = true;
+        self.stop_tracked();
         let res = unsafe {
-            (&self.device.interface.c_pass_thru_start_msg_filter)(
-                self.id.0,
-                filter_type as u32,
-                mask_ptr,
-                pattern_ptr,
-                flow_control_ptr,
-                &mut msg_id as *mut u32,
-            )
+            self.device
+                .interface
+                .call(|| (self.device.interface.c_pass_thru_disconnect)(self.id.0))
         };
+        self.device.unregister_channel(self.id.0);
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.device.interface.error(res));
         }
-        Ok(FilterId(msg_id))
+        Ok(())
     }
 
-    /// Removes a message filter started with `Channel::start_msg_filter`
+    /// Returns an empty message using the channel's protocol
+    fn new_msg(&self) -> PassThruMsg {
+        PassThruMsg {
+            protocol_id: self.protocol_id,
+            ..PassThruMsg::default()
+        }
+    }
+
+    /// Fills `buf` with messages until timing out or until `buf` is filled. The buffer can be reused
+    /// between calls to avoid allocating messages on every read.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - The array of messages to fill. Must not be empty.
+    /// * `timeout` - The amount of time to wait, rounded up to whole milliseconds. If set to zero, reads buffered messages and returns immediately
+    ///
+    /// Returns the amount of messages read. If the timeout expires after some, but not all, messages
+    /// have been read, the messages that were read are still returned. [`Error::Timeout`] is only
+    /// returned if no messages were read, and [`Error::InvalidArgument`] without calling the driver
+    /// if `buf` is empty.
+    pub fn read(&self, buf: &mut [PassThruMsg], timeout: Duration) -> Result<usize, Error> {
+        self.read_raw(buf, timeout_millis(timeout))
+    }
+
+    /// Like [`Channel::read`], but takes the timeout in milliseconds like `PassThruReadMsgs`.
+    pub fn read_raw(&self, buf: &mut [PassThruMsg], timeout: u32) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Err(Error::InvalidArgument("read buffer is empty".to_string()));
+        }
+        self.device.check_connected()?;
+        for msg in buf.iter_mut() {
+            msg.protocol_id = self.protocol_id;
+        }
+        let mut num_msgs: u32 = buf.len() as u32;
+        let res = unsafe {
+            self.device.interface.call(|| {
+                (&self.device.interface.c_pass_thru_read_msgs)(
+                    self.id.0,
+                    buf.as_mut_ptr(),
+                    &mut num_msgs as *mut u32,
+                    timeout,
+                )
+            })
+        };
+        // Don't trust the driver to stay within the buffer
+        let count = (num_msgs as usize).min(buf.len());
+        self.stats.record_read(&buf[..count], res);
+        if res != 0 {
+            match self.device.interface.error(res) {
+                // The timeout expired before `buf` was filled
+                Error::Timeout if count > 0 => {}
+                err => return Err(self.device.note_error(err)),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Reads up to `max` messages until timing out. Fewer than `max` messages may be returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - The maximum amount of messages to read.
+    /// * `timeout` - The amount of time to wait. If set to zero, reads buffered messages and returns immediately
+    ///
+    /// Returns [`Error::Timeout`] if no messages were received before the timeout expired.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, FilterType, Interface, PassThruMsg, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// // Allow all messages to be received
+    /// let filter = PassThruMsg::new_can(0, &[]);
+    /// channel
+    ///     .start_message_filter(FilterType::Pass, Some(&filter), Some(&filter), None)
+    ///     .unwrap();
+    ///
+    /// loop {
+    ///     match channel.read_msgs(32, Duration::from_secs(1)) {
+    ///         Ok(msgs) => {
+    ///             for msg in &msgs {
+    ///                 println!("{:?}", msg.can_message());
+    ///             }
+    ///         }
+    ///         Err(j2534::Error::Timeout) => continue,
+    ///         Err(err) => panic!("{}", err),
+    ///     }
+    /// }
+    /// ```
+    pub fn read_msgs(&self, max: usize, timeout: Duration) -> Result<Vec<PassThruMsg>, Error> {
+        let mut msgs = vec![self.new_msg(); max];
+        let count = self.read(&mut msgs, timeout)?;
+        msgs.truncate(count);
+        Ok(msgs)
+    }
+
+    /// Reads all messages currently buffered by the device without blocking.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum amount of messages to read. Reading stops once this many messages have been
+    ///   read, even if more are buffered, so a busy bus can't keep this method from returning.
+    pub fn drain(&self, limit: usize) -> Result<Vec<PassThruMsg>, Error> {
+        let mut msgs = Vec::new();
+        let mut buf = [self.new_msg(); READ_BATCH_SIZE];
+        while msgs.len() < limit {
+            let batch = &mut buf[..READ_BATCH_SIZE.min(limit - msgs.len())];
+            let count = match self.read(batch, Duration::ZERO) {
+                Ok(count) => count,
+                Err(Error::BufferEmpty) | Err(Error::Timeout) => break,
+                Err(err) => return Err(err),
+            };
+            msgs.extend_from_slice(&batch[..count]);
+            if count < batch.len() {
+                // The receive queue is empty
+                break;
+            }
+        }
+        Ok(msgs)
+    }
+
+    /// Reads a single buffered message without blocking. Returns `None` if no message is buffered.
+    pub fn try_read_msg(&self) -> Result<Option<PassThruMsg>, Error> {
+        match self.read_once(Duration::ZERO) {
+            Ok(msg) => Ok(Some(msg)),
+            Err(Error::BufferEmpty) | Err(Error::Timeout) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads a single message, waiting until `deadline` at the latest. If the deadline has already passed,
+    /// buffered messages are polled once before giving up.
+    ///
+    /// Returns [`Error::Timeout`] if no message was received before the deadline.
+    pub fn read_msg_deadline(&self, deadline: Instant) -> Result<PassThruMsg, Error> {
+        retry_until(deadline, |timeout| self.read_once(timeout))
+    }
+
+    /// Reads up to `max` messages, waiting until `deadline` at the latest. See [`Channel::read_msgs`].
+    ///
+    /// Returns [`Error::Timeout`] if no messages were received before the deadline.
+    pub fn read_msgs_deadline(
+        &self,
+        max: usize,
+        deadline: Instant,
+    ) -> Result<Vec<PassThruMsg>, Error> {
+        retry_until(deadline, |timeout| self.read_msgs(max, timeout))
+    }
+
+    /// Like [`Channel::read_msgs`], but can be cancelled from another thread with `token`. The driver
+    /// is called with short timeouts so that the token can be checked in between.
+    ///
+    /// Returns [`Error::Cancelled`] if the token was cancelled before any messages were received.
+    /// The channel can still be used after cancelling.
+    pub fn read_msgs_cancelable(
+        &self,
+        max: usize,
+        timeout: Duration,
+        token: &CancelToken,
+    ) -> Result<Vec<PassThruMsg>, Error> {
+        let deadline = deadline_after(timeout);
+        loop {
+            if token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            let timeout = deadline
+                .saturating_duration_since(Instant::now())
+                .min(CANCEL_POLL_INTERVAL);
+            match self.read_msgs(max, timeout) {
+                Err(Error::Timeout) | Err(Error::BufferEmpty) if !timeout.is_zero() => {}
+                Err(Error::BufferEmpty) => return Err(Error::Timeout),
+                other => return other,
+            }
+        }
+    }
+
+    /// Reads messages until one matches `pred` and returns it. Messages that don't match are discarded.
+    /// Messages are read one at a time, so messages received after the match stay queued.
+    ///
+    /// Returns [`Error::Timeout`] if no matching message was received before the timeout expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `pred` - Returns true for the message to wait for.
+    /// * `timeout` - The total amount of time to wait.
+    pub fn read_until<F>(&self, pred: F, timeout: Duration) -> Result<PassThruMsg, Error>
+    where
+        F: FnMut(&PassThruMsg) -> bool,
+    {
+        self.read_until_inner(pred, timeout, |_| {})
+    }
+
+    /// Like [`Channel::read_until`], but messages that don't match are appended to `skipped`.
+    /// Skipped messages are kept even if the read fails.
+    pub fn read_until_collect<F>(
+        &self,
+        pred: F,
+        timeout: Duration,
+        skipped: &mut Vec<PassThruMsg>,
+    ) -> Result<PassThruMsg, Error>
+    where
+        F: FnMut(&PassThruMsg) -> bool,
+    {
+        self.read_until_inner(pred, timeout, |msg| skipped.push(msg))
+    }
+
+    fn read_until_inner<F, S>(
+        &self,
+        mut pred: F,
+        timeout: Duration,
+        mut skip: S,
+    ) -> Result<PassThruMsg, Error>
+    where
+        F: FnMut(&PassThruMsg) -> bool,
+        S: FnMut(PassThruMsg),
+    {
+        let deadline = deadline_after(timeout);
+        loop {
+            let msg = self.read_msg_deadline(deadline)?;
+            if pred(&msg) {
+                return Ok(msg);
+            }
+            skip(msg);
+            if Instant::now() >= deadline {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+
+    /// Returns a blocking iterator over received messages. Messages are read in batches and yielded one at a time.
+    ///
+    /// Reads that time out are retried, so the iterator only ends after yielding a hard error, such as
+    /// [`Error::DeviceNotConnected`] or [`Error::InvalidChannelId`] if the channel was disconnected.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The amount of time to wait for each batch of messages.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// for msg in channel.messages(Duration::from_millis(100)) {
+    ///     println!("{:?}", msg.unwrap());
+    /// }
+    /// ```
+    pub fn messages(&self, timeout: Duration) -> Messages<'_> {
+        Messages {
+            channel: self,
+            timeout,
+            batch: ReadBatch::new(self.new_msg()),
+            done: false,
+        }
+    }
+
+    /// Returns a non-blocking iterator over messages already buffered by the device. The iterator
+    /// returns `None` as soon as the receive queue is empty, which makes it suitable for polling from an
+    /// event loop. Messages left over from a batch are kept for the next call to `next`, so the same
+    /// iterator can be kept and polled again later.
+    pub fn try_iter(&self) -> TryIter<'_> {
+        TryIter {
+            channel: self,
+            batch: ReadBatch::new(self.new_msg()),
+        }
+    }
+
+    /// Reads a single message. Echoes of transmitted messages are handled according to the
+    /// [`EchoPolicy`] and bus errors are routed if enabled with [`Channel::set_route_bus_errors`].
+    /// Other messages are returned as-is, even if they are transmit indications or
+    /// ISO 15765 first frame indications.
+    ///
+    /// Returns [`Error::Timeout`] if no message was received before the timeout expired, or
+    /// [`Error::BufferEmpty`] if `timeout` is zero and no message was buffered.
+    pub fn read_once(&self, timeout: Duration) -> Result<PassThruMsg, Error> {
+        let deadline = deadline_after(timeout);
+        let mut remaining = timeout;
+        loop {
+            match self.read_verbatim(remaining) {
+                Ok(msg) => {
+                    if let Some(msg) = self.filter_received(msg) {
+                        return Ok(msg);
+                    }
+                }
+                // Only echoes were received before the timeout expired
+                Err(Error::BufferEmpty) if !timeout.is_zero() => return Err(Error::Timeout),
+                Err(err) => return Err(err),
+            }
+            remaining = deadline.saturating_duration_since(Instant::now());
+        }
+    }
+
+    /// Reads a single message, ignoring the echo policy
+    fn read_verbatim(&self, timeout: Duration) -> Result<PassThruMsg, Error> {
+        let mut msg = [self.new_msg()];
+        match self.read(&mut msg, timeout)? {
+            // Some drivers report success without reading anything
+            0 if timeout.is_zero() => Err(Error::BufferEmpty),
+            0 => Err(Error::Timeout),
+            _ => Ok(msg[0]),
+        }
+    }
+
+    /// Writes `msgs` to the device until all messages have been written or until the timeout has been reached. Returns the amount of message written.
+    /// If the timeout expires before all messages have been written, the amount of messages that were written is still returned.
+    ///
+    /// Returns [`Error::Timeout`] if the timeout expired before any message was written, [`Error::InvalidMessage`] without calling the driver if any message has a `data_size` larger than the data buffer and
+    /// [`Error::BufferFull`] if the transmit queue is full.
+    ///
+    /// # Arguments
+    ///
+    /// * msgs - The array of messages to send.
+    /// * timeout - The amount of time to wait, rounded up to whole milliseconds. If set to zero, queues as many messages as possible and returns immediately.
+    pub fn write(&self, msgs: &mut [PassThruMsg], timeout: Duration) -> Result<usize, Error> {
+        self.write_raw(msgs, timeout_millis(timeout))
+    }
+
+    /// Like [`Channel::write`], but takes the timeout in milliseconds like `PassThruWriteMsgs`.
+    pub fn write_raw(&self, msgs: &mut [PassThruMsg], timeout: u32) -> Result<usize, Error> {
+        if msgs
+            .iter()
+            .any(|msg| msg.data_size as usize > MAX_DATA_SIZE)
+        {
+            return Err(Error::InvalidMessage);
+        }
+        self.device.check_connected()?;
+
+        let mut num_msgs: u32 = msgs.len() as u32;
+        let res = unsafe {
+            self.device.interface.call(|| {
+                (&self.device.interface.c_pass_thru_write_msgs)(
+                    self.id.0,
+                    msgs.as_mut_ptr(),
+                    &mut num_msgs as *mut u32,
+                    timeout,
+                )
+            })
+        };
+        let count = (num_msgs as usize).min(msgs.len());
+        self.stats.record_write(&msgs[..count], res);
+        self.pending_writes.fetch_add(count, Ordering::Relaxed);
+        if res != 0 {
+            match self.device.interface.error(res) {
+                // The timeout expired after some messages were written
+                Error::Timeout if count > 0 => {}
+                err => return Err(self.device.note_error(err)),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Writes a single message to the device.
+    ///
+    /// Returns [`Error::Timeout`] if the message could not be written before the timeout expired.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, PassThruMsg, Protocol, TxFlags};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// // Send a tester present request
+    /// let msg = PassThruMsg::new_isotp(0x7E0, &[0x3E, 0x00]).tx_flags(TxFlags::ISO15765_FRAME_PAD);
+    /// channel.write_msg(&msg, Duration::from_secs(1)).unwrap();
+    /// ```
+    pub fn write_msg(&self, msg: &PassThruMsg, timeout: Duration) -> Result<(), Error> {
+        // The driver may modify the message, so write a copy
+        let mut msgs = [*msg];
+        match self.write(&mut msgs, timeout)? {
+            0 => Err(Error::Timeout),
+            _ => Ok(()),
+        }
+    }
+
+    /// Creates a message for each payload using the channel's protocol and writes them to the device.
+    /// Returns the amount of messages written. See [`Channel::write`].
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if any payload is longer than [`MAX_DATA_SIZE`].
+    ///
+    /// # Arguments
+    ///
+    /// * payloads - The data of each message to send.
+    /// * tx_flags - The transmit flags to set on every message.
+    /// * timeout - The amount of time to wait.
+    pub fn write_payloads(
+        &self,
+        payloads: &[&[u8]],
+        tx_flags: TxFlags,
+        timeout: Duration,
+    ) -> Result<usize, Error> {
+        let mut msgs = Vec::with_capacity(payloads.len());
+        for (i, payload) in payloads.iter().enumerate() {
+            if payload.len() > MAX_DATA_SIZE {
+                return Err(Error::InvalidArgument(format!(
+                    "payload {} is {} bytes long, the maximum is {} bytes",
+                    i,
+                    payload.len(),
+                    MAX_DATA_SIZE
+                )));
+            }
+            let mut msg = self.new_msg().tx_flags(tx_flags);
+            msg.data[..payload.len()].copy_from_slice(payload);
+            msg.data_size = payload.len() as u32;
+            msgs.push(msg);
+        }
+        self.write(&mut msgs, timeout)
+    }
+
+    /// Writes an ISO 15765 message with the ID `tx_id`. The frames are padded, and `extended_id`
+    /// sends the message with a 29-bit ID.
+    ///
+    /// Returns [`Error::MessageProtocolId`] if the channel isn't an ISO 15765 channel and
+    /// [`Error::InvalidArgument`] if the payload is longer than [`MAX_ISOTP_PAYLOAD`], without
+    /// calling the driver.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, FilterType, Interface, PassThruMsg, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let mask = PassThruMsg::new_isotp(0xFFFFFFFF, &[]);
+    /// let pattern = PassThruMsg::new_isotp(0x7E8, &[]);
+    /// let flow_control = PassThruMsg::new_isotp(0x7E0, &[]);
+    /// channel
+    ///     .start_message_filter(FilterType::FlowControl, Some(&mask), Some(&pattern), Some(&flow_control))
+    ///     .unwrap();
+    ///
+    /// // Start a diagnostic session
+    /// channel.write_iso15765(0x7E0, &[0x10, 0x03], false, Duration::from_secs(1)).unwrap();
+    /// ```
+    pub fn write_iso15765(
+        &self,
+        tx_id: u32,
+        payload: &[u8],
+        extended_id: bool,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        if self.protocol.map(Protocol::base) != Some(Protocol::ISO15765) {
+            return Err(Error::MessageProtocolId);
+        }
+        if payload.len() > MAX_ISOTP_PAYLOAD {
+            return Err(Error::InvalidArgument(format!(
+                "ISO-TP payload is {} bytes long, the maximum is {} bytes",
+                payload.len(),
+                MAX_ISOTP_PAYLOAD
+            )));
+        }
+        let mut tx_flags = TxFlags::ISO15765_FRAME_PAD;
+        if extended_id {
+            tx_flags |= TxFlags::CAN_29BIT_ID;
+        }
+        let mut msg = PassThruMsg::new_isotp(tx_id, payload).tx_flags(tx_flags);
+        // ISO15765_PS channels need their own protocol ID on every message
+        msg.protocol_id = self.protocol_id;
+        self.write_msg(&msg, timeout)
+    }
+
+    /// Writes all of `msgs` to the device, retrying the remaining messages when the transmit queue
+    /// is full until everything has been written or the timeout has been reached.
+    ///
+    /// Returns [`Error::PartialWrite`] with the amount of messages that were written if the timeout
+    /// expired before all messages were written.
+    ///
+    /// # Arguments
+    ///
+    /// * msgs - The array of messages to send.
+    /// * timeout - The total amount of time to wait.
+    pub fn write_all(&self, msgs: &mut [PassThruMsg], timeout: Duration) -> Result<(), Error> {
+        let deadline = deadline_after(timeout);
+        let mut written = 0;
+        while written < msgs.len() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let count = match self.write(&mut msgs[written..], timeout) {
+                Ok(count) => count,
+                Err(err) => match err.inner() {
+                    Error::BufferFull => {
+                        // Give the device some time to empty the queue
+                        std::thread::sleep(Duration::from_millis(1));
+                        0
+                    }
+                    Error::Timeout => 0,
+                    _ => return Err(err),
+                },
+            };
+            written += count;
+            if count == 0 && timeout.is_zero() {
+                return Err(Error::PartialWrite { written });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the amount of messages written since the last flush that haven't been confirmed
+    pub fn pending_writes(&self) -> usize {
+        self.pending_writes.load(Ordering::Relaxed)
+    }
+
+    /// Waits until every message written since the last flush has been transmitted by the device.
+    ///
+    /// If loopback is enabled, or the channel uses ISO 15765 which reports TX_DONE indications,
+    /// messages are read until a confirmation has been received for each pending write. Otherwise
+    /// the device doesn't report transmissions, and the transmit queue is probed by writing zero
+    /// messages until the driver reports that it is empty.
+    ///
+    /// Returns the messages that were received while waiting that weren't confirmations, or
+    /// [`Error::FlushTimeout`] with those messages if the timeout expired first. Writes confirmed
+    /// before the timeout expired are no longer pending.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, PassThruMsg, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let msg = PassThruMsg::new_isotp(0x7E0, &[0x34, 0x00, 0x44, 0x00, 0x00, 0x00, 0x00]);
+    /// channel.write_msg(&msg, Duration::from_secs(1)).unwrap();
+    /// let received = channel.flush(Duration::from_secs(1)).unwrap();
+    /// ```
+    pub fn flush(&self, timeout: Duration) -> Result<Vec<PassThruMsg>, Error> {
+        let deadline = deadline_after(timeout);
+        let mut received = Vec::new();
+        if self.pending_writes() == 0 {
+            return Ok(received);
+        }
+
+        let indications = self.protocol.map(Protocol::base) == Some(Protocol::ISO15765);
+        let loopback = matches!(self.loopback(), Ok(true));
+        if !indications && !loopback {
+            return match self.wait_for_write_queue(deadline) {
+                Ok(()) => {
+                    self.pending_writes.store(0, Ordering::Relaxed);
+                    Ok(received)
+                }
+                Err(Error::Timeout) => Err(Error::FlushTimeout {
+                    pending: self.pending_writes(),
+                    received,
+                }),
+                Err(err) => Err(err),
+            };
+        }
+
+        while self.pending_writes() > 0 {
+            let msg = match retry_until(deadline, |timeout| self.read_verbatim(timeout)) {
+                Ok(msg) => msg,
+                Err(Error::Timeout) => {
+                    return Err(Error::FlushTimeout {
+                        pending: self.pending_writes(),
+                        received,
+                    })
+                }
+                Err(err) => return Err(err),
+            };
+            let rx_status = RxStatus::from_bits_truncate(msg.rx_status);
+            // ISO 15765 channels report each transmission with a TX_DONE indication, and
+            // additionally echo the message if loopback is enabled
+            let confirmation = if indications {
+                rx_status.contains(RxStatus::TX_DONE)
+            } else {
+                rx_status.contains(RxStatus::TX_MSG_TYPE)
+            };
+            if confirmation {
+                self.confirm_write();
+            } else {
+                received.push(msg);
+            }
+        }
+        Ok(received)
+    }
+
+    /// Writes zero messages with the remaining timeout until the driver no longer reports a timeout
+    /// or a full queue, which means every queued message was transmitted
+    fn wait_for_write_queue(&self, deadline: Instant) -> Result<(), Error> {
+        // Some drivers reject a null message array even when no messages are written
+        let mut probe = [self.new_msg()];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut num_msgs: u32 = 0;
+            let res = unsafe {
+                self.device.interface.call(|| {
+                    (self.device.interface.c_pass_thru_write_msgs)(
+                        self.id.0,
+                        probe.as_mut_ptr(),
+                        &mut num_msgs as *mut u32,
+                        timeout_millis(remaining),
+                    )
+                })
+            };
+            if res == 0 {
+                return Ok(());
+            }
+            let err = self.device.interface.error(res);
+            let queued = matches!(err.inner(), Error::Timeout | Error::BufferFull);
+            if !queued {
+                return Err(self.device.note_error(err));
+            }
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+            // The driver may return before the timeout if it doesn't block on empty writes
+            std::thread::sleep(Duration::from_millis(1).min(remaining));
+        }
+    }
+
+    /// Marks one pending write as confirmed
+    fn confirm_write(&self) {
+        let _ = self
+            .pending_writes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    /// Writes a single message and waits until the device echoes it back, confirming it was
+    /// transmitted. Loopback is enabled while waiting if it isn't already, and the previous setting
+    /// is restored afterwards.
+    ///
+    /// The echo is matched on the protocol and data of the message, which include the ID bytes.
+    ///
+    /// Returns [`Error::NotConfirmed`] if no echo was received before the timeout expired. Its
+    /// `written` field tells whether the message was written to the device.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, PassThruMsg, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let msg = PassThruMsg::new_can(0x7E0, &[0x02, 0x10, 0x02]);
+    /// let confirmation = channel.write_confirmed(&msg, Duration::from_secs(1)).unwrap();
+    /// println!("transmitted at {}us", confirmation.timestamp);
+    /// ```
+    pub fn write_confirmed(
+        &self,
+        msg: &PassThruMsg,
+        timeout: Duration,
+    ) -> Result<Confirmation, Error> {
+        let loopback = self.loopback()?;
+        if !loopback {
+            self.set_loopback(true)?;
+        }
+        let result = self.write_and_wait_for_echo(msg, deadline_after(timeout));
+        if !loopback {
+            let restored = self.set_loopback(false);
+            // Report a failure to restore the setting only if nothing else went wrong
+            if result.is_ok() {
+                restored?;
+            }
+        }
+        result
+    }
+
+    fn write_and_wait_for_echo(
+        &self,
+        msg: &PassThruMsg,
+        deadline: Instant,
+    ) -> Result<Confirmation, Error> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match self.write_msg(msg, timeout) {
+            Ok(()) => {}
+            Err(Error::Timeout) => return Err(Error::NotConfirmed { written: false }),
+            Err(err) => return Err(err),
+        }
+
+        let size = msg.data_size as usize;
+        let mut received = Vec::new();
+        loop {
+            let echo = match retry_until(deadline, |timeout| self.read_verbatim(timeout)) {
+                Ok(echo) => echo,
+                Err(Error::Timeout) => return Err(Error::NotConfirmed { written: true }),
+                Err(err) => return Err(err),
+            };
+            if echo.transmitted()
+                && echo.protocol_id == msg.protocol_id
+                && echo.data_size as usize == size
+                && echo.data[..size] == msg.data[..size]
+            {
+                self.confirm_write();
+                return Ok(Confirmation {
+                    timestamp: echo.timestamp,
+                    echo,
+                    received,
+                });
+            }
+            received.push(echo);
+        }
+    }
+
+    /// Sets up a network protocol filter to filter messages received by the PassThru device. There is a limit of ten filters per network layer protocol.
+    /// The device blocks all receive frames by default when no filters are defined.
+    ///
+    /// Returns filter ID
+    /// http://www.drewtech.com/support/passthru/startmsgfilter.html
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the mask and pattern have
+    /// different lengths, if a flow control message is missing for a flow control filter or if one
+    /// is given for a pass or block filter, and if a flow control filter is started on a channel
+    /// that doesn't use ISO 15765.
+    pub fn start_message_filter(
+        &self,
+        filter_type: FilterType,
+        mask_msg: Option<&PassThruMsg>,
+        pattern_msg: Option<&PassThruMsg>,
+        flow_control_msg: Option<&PassThruMsg>,
+    ) -> Result<FilterId, Error> {
+        if filter_type == FilterType::FlowControl
+            && self.protocol.map(Protocol::base) != Some(Protocol::ISO15765)
+        {
+            return Err(Error::InvalidArgument(
+                "flow control filters are only valid on ISO15765 channels".to_string(),
+            ));
+        }
+        check_filter_msgs(filter_type, mask_msg, pattern_msg, flow_control_msg)?;
+        self.start_filter_unchecked(filter_type as u32, mask_msg, pattern_msg, flow_control_msg)
+    }
+
+    /// Like [`Channel::start_message_filter`], but takes the filter type as a number so that
+    /// vendor-specific filter types can be used. Filter types defined by J2534 are checked like
+    /// in [`Channel::start_message_filter`]; others are passed to the driver unchecked.
+    pub fn start_message_filter_raw(
+        &self,
+        filter_type: u32,
+        mask_msg: Option<&PassThruMsg>,
+        pattern_msg: Option<&PassThruMsg>,
+        flow_control_msg: Option<&PassThruMsg>,
+    ) -> Result<FilterId, Error> {
+        match FilterType::try_from(filter_type) {
+            Ok(filter_type) => {
+                self.start_message_filter(filter_type, mask_msg, pattern_msg, flow_control_msg)
+            }
+            Err(_) => {
+                self.start_filter_unchecked(filter_type, mask_msg, pattern_msg, flow_control_msg)
+            }
+        }
+    }
+
+    /// Starts a filter and registers it
+    fn start_filter_unchecked(
+        &self,
+        filter_type: u32,
+        mask_msg: Option<&PassThruMsg>,
+        pattern_msg: Option<&PassThruMsg>,
+        flow_control_msg: Option<&PassThruMsg>,
+    ) -> Result<FilterId, Error> {
+        let driver_id =
+            self.driver_start_filter(filter_type, mask_msg, pattern_msg, flow_control_msg)?;
+        let mut tracked = self.tracked.lock().unwrap();
+        let handle = FilterId(tracked.next_handle());
+        tracked.filters.push(TrackedFilter {
+            handle,
+            driver_id,
+            filter_type,
+            mask: mask_msg.copied(),
+            pattern: pattern_msg.copied(),
+            flow_control: flow_control_msg.copied(),
+        });
+        Ok(handle)
+    }
+
+    /// Calls `PassThruStartMsgFilter` and returns the filter ID assigned by the driver
+    fn driver_start_filter(
+        &self,
+        filter_type: u32,
+        mask_msg: Option<&PassThruMsg>,
+        pattern_msg: Option<&PassThruMsg>,
+        flow_control_msg: Option<&PassThruMsg>,
+    ) -> Result<u32, Error> {
+        let mut msg_id: u32 = 0;
+
+        let mask_ptr = match mask_msg {
+            Some(msg) => msg as *const PassThruMsg,
+            None => std::ptr::null() as *const PassThruMsg,
+        };
+
+        let pattern_ptr = match pattern_msg {
+            Some(msg) => msg as *const PassThruMsg,
+            None => std::ptr::null() as *const PassThruMsg,
+        };
+
+        let flow_control_ptr = match flow_control_msg {
+            Some(msg) => msg as *const PassThruMsg,
+            None => std::ptr::null() as *const PassThruMsg,
+        };
+
+        let res = unsafe {
+            self.device.interface.call(|| {
+                (&self.device.interface.c_pass_thru_start_msg_filter)(
+                    self.id.0,
+                    filter_type,
+                    mask_ptr,
+                    pattern_ptr,
+                    flow_control_ptr,
+                    &mut msg_id as *mut u32,
+                )
+            })
+        };
+        if res != 0 {
+            return Err(self.device.interface.error(res));
+        }
+        Ok(msg_id)
+    }
+
+    /// Removes a message filter started with `Channel::start_msg_filter`
     ///
     /// # Arguments
     ///
     /// * `msg_id` - The id of the message returned from `Channel::start_msg_filter`
     pub fn stop_message_filter(&self, filter_id: FilterId) -> Result<(), Error> {
-        let res =
-            unsafe { (&self.device.interface.c_pass_thru_stop_msg_filter)(self.id.0, filter_id.0) };
+        let mut tracked = self.tracked.lock().unwrap();
+        let driver_id = tracked.filter_id(filter_id).ok_or(Error::InvalidFilterId)?;
+        self.driver_stop_filter(driver_id)?;
+        tracked.filters.retain(|filter| filter.handle != filter_id);
+        Ok(())
+    }
+
+    /// Like [`Channel::stop_message_filter`], but takes the filter ID assigned by the driver, like
+    /// one started through [`Channel::id`] outside of this crate. A filter started by this channel
+    /// with that ID is no longer restored by [`Channel::reconnect`].
+    pub fn stop_message_filter_raw(&self, driver_id: u32) -> Result<(), Error> {
+        let mut tracked = self.tracked.lock().unwrap();
+        self.driver_stop_filter(driver_id)?;
+        tracked
+            .filters
+            .retain(|filter| filter.driver_id != driver_id);
+        Ok(())
+    }
+
+    /// Calls `PassThruStopMsgFilter` with a filter ID assigned by the driver
+    fn driver_stop_filter(&self, driver_id: u32) -> Result<(), Error> {
+        let res = unsafe {
+            self.device
+                .interface
+                .call(|| (&self.device.interface.c_pass_thru_stop_msg_filter)(self.id.0, driver_id))
+        };
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.device.interface.error(res));
         }
         Ok(())
     }
@@ -1080,25 +3454,106 @@ impl<'a> Channel<'a> {
     /// # Arguments
     ///
     /// * `msg` - The message to send
-    /// * `time_interval` - The time in milliseconds to wait between sending messages. The acceptable range is between 5 and 65,535 milliseconds.
+    /// * `time_interval` - The time to wait between sending messages. The acceptable range is between 5 and 65,535 milliseconds.
+    ///
+    /// Returns [`Error::InvalidTimeInterval`] without calling the driver if the interval is out of range, and
+    /// [`Error::ExceededLimit`] if the device can't start any more periodic messages.
     pub fn start_periodic_message(
+        &self,
+        msg: &PassThruMsg,
+        time_interval: Duration,
+    ) -> Result<MessageId, Error> {
+        self.start_periodic_message_raw(msg, timeout_millis(time_interval))
+    }
+
+    /// Like [`Channel::start_periodic_message`], but takes the interval in milliseconds like `PassThruStartPeriodicMsg`.
+    pub fn start_periodic_message_raw(
         &self,
         msg: &PassThruMsg,
         time_interval: u32,
     ) -> Result<MessageId, Error> {
+        if !PERIODIC_INTERVAL_RANGE.contains(&time_interval) {
+            return Err(Error::InvalidTimeInterval);
+        }
+        let driver_id = self.driver_start_periodic(msg, time_interval)?;
+        let mut tracked = self.tracked.lock().unwrap();
+        let handle = MessageId(tracked.next_handle());
+        tracked.periodic.push(TrackedPeriodic {
+            handle,
+            driver_id,
+            msg: *msg,
+            interval: time_interval,
+            created: Instant::now(),
+        });
+        Ok(handle)
+    }
+
+    /// Calls `PassThruStartPeriodicMsg` and returns the message ID assigned by the driver
+    fn driver_start_periodic(&self, msg: &PassThruMsg, time_interval: u32) -> Result<u32, Error> {
         let mut msg_id = 0;
         let res = unsafe {
-            (&self.device.interface.c_pass_thru_start_periodic_msg)(
-                self.id.0,
-                msg as *const PassThruMsg,
-                &mut msg_id as *mut u32,
-                time_interval,
-            )
+            self.device.interface.call(|| {
+                (&self.device.interface.c_pass_thru_start_periodic_msg)(
+                    self.id.0,
+                    msg as *const PassThruMsg,
+                    &mut msg_id as *mut u32,
+                    time_interval,
+                )
+            })
         };
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.device.interface.error(res));
         }
-        Ok(MessageId(msg_id))
+        Ok(msg_id)
+    }
+
+    /// Replaces the message and/or the interval of a periodic message, keeping its handle. See
+    /// [`PeriodicMsg::update`].
+    pub fn update_periodic_message(
+        &self,
+        msg_id: MessageId,
+        new_msg: Option<&PassThruMsg>,
+        new_interval: Option<Duration>,
+    ) -> Result<(), Error> {
+        let mut tracked = self.tracked.lock().unwrap();
+        let periodic = tracked
+            .periodic
+            .iter_mut()
+            .find(|periodic| periodic.handle == msg_id)
+            .ok_or(Error::InvalidMessageId)?;
+        let msg = new_msg.copied().unwrap_or(periodic.msg);
+        let interval = new_interval.map_or(periodic.interval, timeout_millis);
+        if !PERIODIC_INTERVAL_RANGE.contains(&interval) {
+            return Err(Error::InvalidTimeInterval);
+        }
+
+        let driver_id = match self.driver_start_periodic(&msg, interval) {
+            Ok(driver_id) => {
+                // Keep the old message if it can't be stopped, so that both aren't sent
+                if let Err(err) = self.driver_stop_periodic(periodic.driver_id) {
+                    let _ = self.driver_stop_periodic(driver_id);
+                    return Err(err);
+                }
+                driver_id
+            }
+            Err(err) if matches!(err.inner(), Error::ExceededLimit) => {
+                self.driver_stop_periodic(periodic.driver_id)?;
+                match self.driver_start_periodic(&msg, interval) {
+                    Ok(driver_id) => driver_id,
+                    Err(err) => {
+                        tracked
+                            .periodic
+                            .retain(|periodic| periodic.handle != msg_id);
+                        return Err(err);
+                    }
+                }
+            }
+            Err(err) => return Err(err),
+        };
+        periodic.driver_id = driver_id;
+        periodic.msg = msg;
+        periodic.interval = interval;
+        Ok(())
     }
 
     /// Stops a periodic mesage started with `Channel::start_periodic_msg`
@@ -1107,46 +3562,302 @@ impl<'a> Channel<'a> {
     ///
     /// * msg_id = the id of the periodic message returned from `Channel::start_periodiC_msg`
     pub fn stop_periodic_message(&self, msg_id: MessageId) -> Result<(), Error> {
-        let res =
-            unsafe { (&self.device.interface.c_pass_thru_stop_periodic_msg)(self.id.0, msg_id.0) };
+        let mut tracked = self.tracked.lock().unwrap();
+        let driver_id = tracked.periodic_id(msg_id).ok_or(Error::InvalidMessageId)?;
+        self.driver_stop_periodic(driver_id)?;
+        tracked
+            .periodic
+            .retain(|periodic| periodic.handle != msg_id);
+        Ok(())
+    }
+
+    /// Like [`Channel::stop_periodic_message`], but takes the message ID assigned by the driver,
+    /// like one started through [`Channel::id`] outside of this crate. A periodic message started
+    /// by this channel with that ID is no longer restored by [`Channel::reconnect`].
+    pub fn stop_periodic_message_raw(&self, driver_id: u32) -> Result<(), Error> {
+        let mut tracked = self.tracked.lock().unwrap();
+        self.driver_stop_periodic(driver_id)?;
+        tracked
+            .periodic
+            .retain(|periodic| periodic.driver_id != driver_id);
+        Ok(())
+    }
+
+    /// Calls `PassThruStopPeriodicMsg` with a message ID assigned by the driver
+    fn driver_stop_periodic(&self, driver_id: u32) -> Result<(), Error> {
+        let res = unsafe {
+            self.device.interface.call(|| {
+                (&self.device.interface.c_pass_thru_stop_periodic_msg)(self.id.0, driver_id)
+            })
+        };
         if res != 0 {
-            return Err(Error::from_code(res));
+            return Err(self.device.interface.error(res));
         }
         Ok(())
     }
 
+    /// Splits the channel into a reader and a writer that can be used from separate threads.
+    /// The channel is disconnected once both halves are dropped, or can be restored with [`ChannelReader::reunite`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, PassThruMsg, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    /// let (reader, writer) = channel.split();
+    /// let timeout = Duration::from_secs(1);
+    ///
+    /// std::thread::scope(|s| {
+    ///     s.spawn(move || {
+    ///         for msg in reader.read_msgs(16, timeout).unwrap() {
+    ///             println!("{:?}", msg);
+    ///         }
+    ///     });
+    ///     s.spawn(move || writer.write_msg(&PassThruMsg::new_can(8, &[0, 1, 2, 3]), timeout));
+    /// });
+    /// ```
+    pub fn split(self) -> (ChannelReader<'a>, ChannelWriter<'a>) {
+        // The halves implement `Send` themselves
+        #[allow(clippy::arc_with_non_send_sync)]
+        let channel = Arc::new(self);
+        (
+            ChannelReader {
+                channel: channel.clone(),
+            },
+            ChannelWriter { channel },
+        )
+    }
+
     /// Clear transmit message queue
     pub fn clear_transmit_buffer(&self) -> Result<(), Error> {
         unsafe {
             self.device.interface.ioctl(
                 self.id.0,
-                IoctlId::CLEAR_TX_BUFFER,
-                std::ptr::null_mut::<libc::c_void>(),
+                IoctlId::CLEAR_TX_BUFFER,
+                std::ptr::null_mut::<libc::c_void>(),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        }?;
+        Ok(())
+    }
+
+    /// Halt continuous messages
+    ///
+    /// This stops every periodic message the driver has on the channel, including ones that
+    /// weren't started through this crate. The handles of messages started through this channel
+    /// are forgotten, so stopping them afterwards, including by dropping a [`PeriodicMsg`], returns
+    /// [`Error::InvalidMessageId`] without calling the driver.
+    pub fn clear_periodic_messages(&self) -> Result<(), Error> {
+        unsafe {
+            self.device.interface.ioctl(
+                self.id.0,
+                IoctlId::CLEAR_PERIODIC_MSGS,
+                std::ptr::null_mut::<libc::c_void>(),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        }?;
+        self.tracked.lock().unwrap().periodic.clear();
+        Ok(())
+    }
+
+    /// Clear receive message queue
+    pub fn clear_receive_buffer(&self) -> Result<(), Error> {
+        unsafe {
+            self.device.interface.ioctl(
+                self.id.0,
+                IoctlId::CLEAR_RX_BUFFER,
+                std::ptr::null_mut::<libc::c_void>(),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        }?;
+        Ok(())
+    }
+
+    /// Removes all message filters
+    ///
+    /// The handles of filters started through this channel are forgotten, and handles aren't
+    /// reused. Stopping them afterwards, including by dropping a [`MessageFilter`], returns
+    /// [`Error::InvalidFilterId`] without calling the driver, so a filter the driver assigns the
+    /// same ID later isn't stopped by mistake.
+    pub fn clear_message_filters(&self) -> Result<(), Error> {
+        unsafe {
+            self.device.interface.ioctl(
+                self.id.0,
+                IoctlId::CLEAR_MSG_FILTERS,
+                std::ptr::null_mut::<libc::c_void>(),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        }?;
+        self.tracked.lock().unwrap().filters.clear();
+        Ok(())
+    }
+
+    /// Gets a single configuration parameter. See [`Channel::get_configs`] to get several.
+    pub fn get_config(&self, id: ConfigId) -> Result<u32, Error> {
+        let mut items = [SConfig::new(id, 0)];
+        let mut input = SConfigList::new(&mut items);
+        unsafe {
+            self.device.interface.ioctl(
+                self.id.0,
+                IoctlId::GET_CONFIG,
+                input.as_mut_ptr(),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        }?;
+        Ok(items[0].value)
+    }
+
+    /// Gets several configuration parameters with one `GET_CONFIG` call. Returns each parameter
+    /// with its value, in the order they were requested.
+    ///
+    /// Drivers differ in how they handle a list with a parameter they don't support: most fail
+    /// the whole call. If that happens, the parameters are read one at a time so that the error
+    /// is reported only for the parameters that caused it.
+    pub fn get_configs(&self, ids: &[ConfigId]) -> Vec<(ConfigId, Result<u32, Error>)> {
+        if ids.is_empty() {
+            return Vec::new();
+        }
+        let mut items: Vec<SConfig> = ids.iter().map(|&id| SConfig::new(id, 0)).collect();
+        let mut input = SConfigList::new(&mut items);
+        let res = unsafe {
+            self.device.interface.ioctl(
+                self.id.0,
+                IoctlId::GET_CONFIG,
+                input.as_mut_ptr(),
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        match res {
+            Ok(_) => ids
+                .iter()
+                .zip(items)
+                .map(|(&id, item)| (id, Ok(item.value)))
+                .collect(),
+            Err(err) if ids.len() == 1 => vec![(ids[0], Err(err))],
+            Err(_) => ids.iter().map(|&id| (id, self.get_config(id))).collect(),
+        }
+    }
+
+    /// Changes the baud rate of the connected channel, for example after an ECU switched to a
+    /// faster rate. The rate is checked like in [`Device::connect`].
+    pub fn set_data_rate(&self, baudrate: u32) -> Result<(), Error> {
+        self.set_config(ConfigId::DATA_RATE, baudrate)
+    }
+
+    /// Reads the baud rate from the driver. Unlike [`Channel::baudrate`], this shows whether the
+    /// driver used a different rate than the one that was set.
+    pub fn data_rate(&self) -> Result<u32, Error> {
+        self.get_config(ConfigId::DATA_RATE)
+    }
+
+    /// Returns true if transmitted messages are echoed back as received messages
+    pub fn loopback(&self) -> Result<bool, Error> {
+        Ok(self.get_config(ConfigId::LOOPBACK)? != 0)
+    }
+
+    /// Enables or disables echoing of transmitted messages
+    pub fn set_loopback(&self, enabled: bool) -> Result<(), Error> {
+        self.set_config(ConfigId::LOOPBACK, enabled as u32)
+    }
+
+    /// Sets a single configuration parameter. See [`Channel::set_configs`] to set several.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if the value is outside the
+    /// range J2534 allows for the parameter.
+    pub fn set_config(&self, id: ConfigId, value: u32) -> Result<(), Error> {
+        self.set_configs(&[(id, value)])
+    }
+
+    /// Sets several configuration parameters with one `SET_CONFIG` call. Some drivers only apply
+    /// related parameters together when they are set in the same call.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if a value is outside the
+    /// range J2534 allows for the parameter: `LOOPBACK` must be 0 or 1, the ISO 15765 block size
+    /// and separation time must fit in a byte and `DATA_RATE` must be a baud rate the protocol
+    /// accepts.
+    pub fn set_configs(&self, configs: &[(ConfigId, u32)]) -> Result<(), Error> {
+        for &(id, value) in configs {
+            check_config(self.protocol, id, value)?;
+        }
+        let configs: Vec<SConfig> = configs
+            .iter()
+            .map(|&(id, value)| SConfig::new(id, value))
+            .collect();
+        self.set_configs_unchecked(&configs)
+    }
+
+    /// Like [`Channel::set_configs`], but doesn't check the values and takes the parameters as
+    /// numbers so that vendor-specific parameters can be set.
+    pub fn set_configs_unchecked(&self, configs: &[SConfig]) -> Result<(), Error> {
+        let mut items = configs.to_vec();
+        let mut input = SConfigList::new(&mut items);
+        unsafe {
+            self.device.interface.ioctl(
+                self.id.0,
+                IoctlId::SET_CONFIG,
+                input.as_mut_ptr(),
                 std::ptr::null_mut::<libc::c_void>(),
             )
         }?;
+        if let Some(config) = configs
+            .iter()
+            .rev()
+            .find(|config| config.parameter == ConfigId::DATA_RATE as u32)
+        {
+            self.baudrate.store(config.value, Ordering::Relaxed);
+            self.device.update_channel_baudrate(self.id.0, config.value);
+        }
         Ok(())
     }
 
-    /// Halt continuous messages
-    pub fn clear_periodic_messages(&self) -> Result<(), Error> {
-        unsafe {
-            self.device.interface.ioctl(
-                self.id.0,
-                IoctlId::CLEAR_PERIODIC_MSGS,
-                std::ptr::null_mut::<libc::c_void>(),
-                std::ptr::null_mut::<libc::c_void>(),
-            )
-        }?;
+    /// Calls `PassThruIoctl` with the channel ID as the handle, for channel-level ioctls that
+    /// have no wrapper such as vendor-specific ones. Device-level ioctls like `READ_VBATT` need
+    /// [`Device::ioctl_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `input` and `output` must be valid for the structures the ioctl reads and writes, or null
+    /// if it doesn't use them.
+    pub unsafe fn ioctl_raw(
+        &self,
+        ioctl_id: u32,
+        input: *mut libc::c_void,
+        output: *mut libc::c_void,
+    ) -> Result<(), Error> {
+        let res = self
+            .device
+            .interface
+            .call(|| (self.device.interface.c_pass_thru_ioctl)(self.id.0, ioctl_id, input, output));
+        if res != 0 {
+            return Err(self.device.interface.error(res));
+        }
         Ok(())
     }
 
-    /// Clear receive message queue
-    pub fn clear_receive_buffer(&self) -> Result<(), Error> {
+    /// Adds functional addresses to the functional message lookup table, so that messages sent to
+    /// them are received. Used on J1850PWM channels.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if `addrs` is empty.
+    pub fn add_functional_addresses(&self, addrs: &[u8]) -> Result<(), Error> {
+        self.functional_lookup_ioctl(IoctlId::ADD_TO_FUNCT_MSG_LOOKUP_TABLE, addrs)
+    }
+
+    /// Removes functional addresses from the functional message lookup table.
+    ///
+    /// Returns [`Error::InvalidArgument`] without calling the driver if `addrs` is empty.
+    pub fn remove_functional_addresses(&self, addrs: &[u8]) -> Result<(), Error> {
+        self.functional_lookup_ioctl(IoctlId::DELETE_FROM_FUNCT_MSG_LOOKUP_TABLE, addrs)
+    }
+
+    /// Removes all functional addresses from the functional message lookup table
+    pub fn clear_functional_addresses(&self) -> Result<(), Error> {
         unsafe {
             self.device.interface.ioctl(
                 self.id.0,
-                IoctlId::CLEAR_RX_BUFFER,
+                IoctlId::CLEAR_FUNCT_MSG_LOOKUP_TABLE,
                 std::ptr::null_mut::<libc::c_void>(),
                 std::ptr::null_mut::<libc::c_void>(),
             )
@@ -1154,55 +3865,87 @@ impl<'a> Channel<'a> {
         Ok(())
     }
 
-    /// Removes all message filters
-    pub fn clear_message_filters(&self) -> Result<(), Error> {
-        unsafe {
+    /// Performs a five baud initialization of the ECU at `target_address` on an ISO 9141 or
+    /// ISO 14230 channel. Returns the two key bytes sent by the ECU.
+    ///
+    /// Returns [`Error::NoInitResponse`] if no ECU responded.
+    pub fn five_baud_init(&self, target_address: u8) -> Result<[u8; 2], Error> {
+        let mut input = ByteArray::new(&[target_address]);
+        let mut output = ByteArray::with_capacity(2);
+        let res = unsafe {
             self.device.interface.ioctl(
                 self.id.0,
-                IoctlId::CLEAR_MSG_FILTERS,
-                std::ptr::null_mut::<libc::c_void>(),
-                std::ptr::null_mut::<libc::c_void>(),
+                IoctlId::FIVE_BAUD_INIT,
+                input.as_input_ptr(),
+                output.as_output_ptr(),
             )
-        }?;
-        Ok(())
+        };
+        match res {
+            Ok(_) => {}
+            Err(Error::Timeout) => return Err(Error::NoInitResponse),
+            Err(err) => return Err(err),
+        }
+        // The driver sets the number of bytes it wrote
+        match *output.bytes() {
+            [key_byte_1, key_byte_2] if output.num_of_bytes() == 2 => Ok([key_byte_1, key_byte_2]),
+            _ => Err(Error::Failed),
+        }
     }
 
-    /// Gets a single configuration parameter.
-    pub fn get_config(&self, id: ConfigId) -> Result<u32, Error> {
-        let mut item = SConfig {
-            parameter: id as u32,
-            value: 0,
-        };
-        let mut input = SConfigList {
-            size: 1,
-            config_ptr: &mut item as *mut SConfig,
-        };
-        unsafe {
+    /// Performs a fast initialization on an ISO 14230 channel by sending `request`, usually a
+    /// StartCommunication request. Returns the response of the ECU.
+    ///
+    /// Returns [`Error::NoInitResponse`] if no ECU responded.
+    pub fn fast_init(&self, request: &PassThruMsg) -> Result<PassThruMsg, Error> {
+        let mut response = self.new_msg();
+        let res = unsafe {
             self.device.interface.ioctl(
                 self.id.0,
-                IoctlId::GET_CONFIG,
-                &mut input as *mut _ as *mut libc::c_void,
-                std::ptr::null_mut::<libc::c_void>(),
+                IoctlId::FAST_INIT,
+                request as *const PassThruMsg as *mut libc::c_void,
+                &mut response as *mut _ as *mut libc::c_void,
             )
-        }?;
-        Ok(item.value)
+        };
+        match res {
+            Ok(_) => {}
+            Err(Error::Timeout) => return Err(Error::NoInitResponse),
+            Err(err) => return Err(err),
+        }
+        if response.data_size as usize > response.data.len() {
+            return Err(Error::Failed);
+        }
+        Ok(response)
     }
 
-    /// Sets a single configuration parameter.
-    pub fn set_config(&self, id: ConfigId, value: u32) -> Result<(), Error> {
-        let mut item = SConfig {
-            parameter: id as u32,
-            value,
-        };
-        let mut input = SConfigList {
-            size: 1,
-            config_ptr: &mut item as *mut SConfig,
-        };
+    /// Performs a fast initialization by sending a StartCommunication request (`81`) from the
+    /// tester at `source_address` to the ECU at `target_address`. See [`Channel::fast_init`].
+    pub fn fast_init_start_communication(
+        &self,
+        target_address: u8,
+        source_address: u8,
+    ) -> Result<PassThruMsg, Error> {
+        // A format byte for physical addressing and a length of one, the addresses and the
+        // service. The device adds the checksum.
+        let data = [0xC1, target_address, source_address, 0x81];
+        let mut request = self.new_msg();
+        request.data[..data.len()].copy_from_slice(&data);
+        request.data_size = data.len() as u32;
+        self.fast_init(&request)
+    }
+
+    /// Passes `addrs` to an ioctl that edits the functional message lookup table
+    fn functional_lookup_ioctl(&self, id: IoctlId, addrs: &[u8]) -> Result<(), Error> {
+        if addrs.is_empty() {
+            return Err(Error::InvalidArgument(
+                "no functional addresses were given".to_string(),
+            ));
+        }
+        let mut input = ByteArray::new(addrs);
         unsafe {
             self.device.interface.ioctl(
                 self.id.0,
-                IoctlId::SET_CONFIG,
-                &mut input as *mut _ as *mut libc::c_void,
+                id,
+                input.as_input_ptr(),
                 std::ptr::null_mut::<libc::c_void>(),
             )
         }?;
@@ -1212,12 +3955,191 @@ impl<'a> Channel<'a> {
 
 impl<'a> Drop for Channel<'a> {
     fn drop(&mut self) {
-        unsafe { (&self.device.interface.c_pass_thru_disconnect)(self.id.0) };
+        if !self.disconnected {
+            self.stop_tracked();
+            unsafe {
+                self.device
+                    .interface
+                    .call(|| (&self.device.interface.c_pass_thru_disconnect)(self.id.0))
+            };
+            self.device.unregister_channel(self.id.0);
+        }
+    }
+}
+
+/// The receiving half of a channel created by [`Channel::split`]
+pub struct ChannelReader<'a> {
+    channel: Arc<Channel<'a>>,
+}
+
+/// The transmitting half of a channel created by [`Channel::split`]
+pub struct ChannelWriter<'a> {
+    channel: Arc<Channel<'a>>,
+}
+
+impl<'a> ChannelReader<'a> {
+    /// See [`Channel::read`]
+    pub fn read(&self, buf: &mut [PassThruMsg], timeout: Duration) -> Result<usize, Error> {
+        self.channel.read(buf, timeout)
+    }
+
+    /// See [`Channel::read_msgs`]
+    pub fn read_msgs(&self, max: usize, timeout: Duration) -> Result<Vec<PassThruMsg>, Error> {
+        self.channel.read_msgs(max, timeout)
+    }
+
+    /// See [`Channel::read_once`]
+    pub fn read_once(&self, timeout: Duration) -> Result<PassThruMsg, Error> {
+        self.channel.read_once(timeout)
+    }
+
+    /// See [`Channel::try_read_msg`]
+    pub fn try_read_msg(&self) -> Result<Option<PassThruMsg>, Error> {
+        self.channel.try_read_msg()
+    }
+
+    /// See [`Channel::read_msg_deadline`]
+    pub fn read_msg_deadline(&self, deadline: Instant) -> Result<PassThruMsg, Error> {
+        self.channel.read_msg_deadline(deadline)
+    }
+
+    /// Joins the two halves of a channel. Returns both halves if they don't belong to the same channel.
+    pub fn reunite(
+        self,
+        writer: ChannelWriter<'a>,
+    ) -> Result<Channel<'a>, (ChannelReader<'a>, ChannelWriter<'a>)> {
+        if !Arc::ptr_eq(&self.channel, &writer.channel) {
+            return Err((self, writer));
+        }
+        drop(writer);
+        match Arc::try_unwrap(self.channel) {
+            Ok(channel) => Ok(channel),
+            Err(_) => unreachable!("a channel is only shared by its reader and writer"),
+        }
+    }
+}
+
+impl<'a> ChannelWriter<'a> {
+    /// See [`Channel::write`]
+    pub fn write(&self, msgs: &mut [PassThruMsg], timeout: Duration) -> Result<usize, Error> {
+        self.channel.write(msgs, timeout)
+    }
+
+    /// See [`Channel::write_msg`]
+    pub fn write_msg(&self, msg: &PassThruMsg, timeout: Duration) -> Result<(), Error> {
+        self.channel.write_msg(msg, timeout)
+    }
+
+    /// See [`Channel::write_all`]
+    pub fn write_all(&self, msgs: &mut [PassThruMsg], timeout: Duration) -> Result<(), Error> {
+        self.channel.write_all(msgs, timeout)
+    }
+
+    /// See [`Channel::write_payloads`]
+    pub fn write_payloads(
+        &self,
+        payloads: &[&[u8]],
+        tx_flags: TxFlags,
+        timeout: Duration,
+    ) -> Result<usize, Error> {
+        self.channel.write_payloads(payloads, tx_flags, timeout)
+    }
+}
+
+/// Messages read in a batch that have not been yielded yet
+struct ReadBatch {
+    buf: Vec<PassThruMsg>,
+    pos: usize,
+    len: usize,
+}
+
+impl ReadBatch {
+    fn new(msg: PassThruMsg) -> ReadBatch {
+        ReadBatch {
+            buf: vec![msg; READ_BATCH_SIZE],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the next buffered message
+    fn pop(&mut self) -> Option<PassThruMsg> {
+        if self.pos < self.len {
+            self.pos += 1;
+            Some(self.buf[self.pos - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Reads the next batch of messages. Must only be called once all buffered messages have been popped.
+    fn fill(&mut self, channel: &Channel, timeout: Duration) -> Result<usize, Error> {
+        self.pos = 0;
+        self.len = 0;
+        self.len = channel.read(&mut self.buf, timeout)?;
+        Ok(self.len)
+    }
+}
+
+/// A blocking iterator over messages received by a channel. Created by [`Channel::messages`]
+pub struct Messages<'a> {
+    channel: &'a Channel<'a>,
+    timeout: Duration,
+    batch: ReadBatch,
+    done: bool,
+}
+
+impl<'a> Iterator for Messages<'a> {
+    type Item = Result<PassThruMsg, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            if let Some(msg) = self.batch.pop() {
+                match self.channel.filter_received(msg) {
+                    Some(msg) => return Some(Ok(msg)),
+                    None => continue,
+                }
+            }
+            match self.batch.fill(self.channel, self.timeout) {
+                Ok(_) | Err(Error::Timeout) | Err(Error::BufferEmpty) => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A non-blocking iterator over messages buffered by the device. Created by [`Channel::try_iter`]
+pub struct TryIter<'a> {
+    channel: &'a Channel<'a>,
+    batch: ReadBatch,
+}
+
+impl<'a> Iterator for TryIter<'a> {
+    type Item = Result<PassThruMsg, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(msg) = self.batch.pop() {
+                match self.channel.filter_received(msg) {
+                    Some(msg) => return Some(Ok(msg)),
+                    None => continue,
+                }
+            }
+            match self.batch.fill(self.channel, Duration::ZERO) {
+                Ok(0) | Err(Error::Timeout) | Err(Error::BufferEmpty) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
     }
 }
 
 /// Information about an installed PassThru driver
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Driver {
     pub name: String,
     pub vendor: String,
@@ -1260,3 +4182,691 @@ pub fn drivers() -> io::Result<Vec<Driver>> {
 pub fn drivers() -> io::Result<Vec<Driver>> {
     Ok(Vec::new())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn write_timeout_without_progress() {
+        let channel = mock::can_channel();
+        let mut msgs = [PassThruMsg::new_can(0x7E0, &[1, 2]); 2];
+
+        mock::with_channel(channel.id(), |channel| {
+            channel.write_result = Some((0, 0x09))
+        });
+        assert!(matches!(
+            channel.write(&mut msgs, Duration::from_millis(10)),
+            Err(Error::Timeout)
+        ));
+
+        mock::with_channel(channel.id(), |channel| {
+            channel.write_result = Some((1, 0x09))
+        });
+        assert_eq!(
+            channel.write(&mut msgs, Duration::from_millis(10)).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn read_empty_buffer() {
+        let channel = mock::can_channel();
+        mock::push_rx(channel.id(), PassThruMsg::new_can(0x7E8, &[1]));
+
+        // Empty buffers are rejected without calling the driver
+        assert!(matches!(
+            channel.read(&mut [], Duration::from_millis(10)),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert_eq!(mock::channel(channel.id()).reads, 0);
+        assert_eq!(mock::channel(channel.id()).rx.len(), 1);
+    }
+
+    #[test]
+    fn try_read_msg_empty_queue() {
+        let channel = mock::can_channel();
+        assert!(channel.try_read_msg().unwrap().is_none());
+
+        mock::push_rx(channel.id(), PassThruMsg::new_can(0x7E8, &[1, 2]));
+        let msg = channel.try_read_msg().unwrap().unwrap();
+        assert_eq!(msg.can_message(), Some((0x7E8, &[1, 2][..])));
+        assert!(channel.try_read_msg().unwrap().is_none());
+
+        mock::with_channel(channel.id(), |channel| channel.read_error = Some(0x08));
+        assert!(matches!(
+            channel.try_read_msg(),
+            Err(Error::DeviceNotConnected)
+        ));
+    }
+
+    #[test]
+    fn error_from_primitive() {
+        assert!(matches!(Error::from_i64(0x10), Some(Error::BufferEmpty)));
+        assert!(matches!(Error::from_u64(0x99), Some(Error::Unknown(0x99))));
+        assert!(Error::from_i64(1 << 40).is_none());
+    }
+
+    #[test]
+    fn flush_keeps_received_messages() {
+        let channel = mock::can_channel();
+        let msg = PassThruMsg::new_can(0x7E0, &[1]);
+
+        // Without loopback the device doesn't confirm transmissions, so the queue is probed
+        channel.write_msg(&msg, Duration::from_millis(10)).unwrap();
+        mock::with_channel(channel.id(), |channel| {
+            channel.write_result = Some((0, 0x09))
+        });
+        match channel.flush(Duration::from_millis(10)) {
+            Err(Error::FlushTimeout { pending, received }) => {
+                assert_eq!(pending, 1);
+                assert!(received.is_empty());
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        let written = mock::channel(channel.id()).written.len();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(20));
+                mock::with_channel(channel.id(), |channel| channel.write_result = None);
+            });
+            assert!(channel.flush(Duration::from_secs(5)).unwrap().is_empty());
+        });
+        assert_eq!(channel.pending_writes(), 0);
+        // The probes didn't write anything
+        assert_eq!(mock::channel(channel.id()).written.len(), written);
+
+        channel.write_msg(&msg, Duration::from_millis(10)).unwrap();
+        channel.set_loopback(true).unwrap();
+        mock::push_rx(channel.id(), PassThruMsg::new_can(0x7E8, &[2]));
+        // The write before enabling loopback was never echoed
+        match channel.flush(Duration::from_millis(10)) {
+            Err(Error::FlushTimeout { pending, received }) => {
+                assert_eq!(pending, 1);
+                assert_eq!(received.len(), 1);
+                assert_eq!(received[0].can_message(), Some((0x7E8, &[2][..])));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // A late echo of the first write
+        let mut echo = msg;
+        echo.rx_status = RxStatus::TX_MSG_TYPE.bits();
+        mock::push_rx(channel.id(), echo);
+        mock::push_rx(channel.id(), PassThruMsg::new_can(0x7E8, &[3]));
+        channel.write_msg(&msg, Duration::from_millis(10)).unwrap();
+        let received = channel.flush(Duration::from_millis(10)).unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(channel.pending_writes(), 0);
+    }
+
+    #[test]
+    fn write_iso15765_uses_channel_protocol() {
+        for protocol in [Protocol::ISO15765, Protocol::ISO15765_PS] {
+            let channel = mock::connect(protocol, ConnectFlags::empty(), 500000);
+            channel
+                .write_iso15765(0x18DA10F1, &[0x3E, 0x00], true, Duration::ZERO)
+                .unwrap();
+            let written = mock::channel(channel.id()).written;
+            let (protocol_id, tx_flags) = (written[0].protocol_id, written[0].tx_flags);
+            assert_eq!(protocol_id, protocol as u32);
+            assert_eq!(
+                tx_flags,
+                (TxFlags::ISO15765_FRAME_PAD | TxFlags::CAN_29BIT_ID).bits()
+            );
+            assert_eq!(
+                written[0].isotp_message(),
+                Some((0x18DA10F1, &[0x3E, 0x00][..]))
+            );
+        }
+
+        let channel = mock::can_channel();
+        assert!(matches!(
+            channel.write_iso15765(0x7E0, &[0x3E, 0x00], false, Duration::ZERO),
+            Err(Error::MessageProtocolId)
+        ));
+        assert!(mock::channel(channel.id()).written.is_empty());
+    }
+
+    #[test]
+    fn baud_rates() {
+        assert!(BaudRates::of(Protocol::CAN).contains(500000));
+        // Programmed rates J2534-1 doesn't list
+        assert!(BaudRates::of(Protocol::ISO15765).contains(1000000));
+        assert!(BaudRates::of(Protocol::ISO15765_PS).contains(83333));
+        assert!(!BaudRates::of(Protocol::CAN).contains(2000000));
+        assert!(BaudRates::of(Protocol::ISO9141).contains(10400));
+        assert!(!BaudRates::of(Protocol::ISO14230).contains(41600));
+        assert!(BaudRates::of(Protocol::J1850PWM_PS).contains(41600));
+        assert!(!BaudRates::of(Protocol::J1850VPW).contains(83300));
+        assert!(BaudRates::of(Protocol::SW_CAN_PS).contains(33333));
+        assert_eq!(
+            BaudRates::of(Protocol::SCI_A_ENGINE).to_string(),
+            "7812, 62500"
+        );
+        assert_eq!(BaudRates::of(Protocol::CAN).to_string(), "10000 to 1000000");
+    }
+
+    #[test]
+    fn connect_checks_baud_rate() {
+        let device = mock::open();
+        assert!(matches!(
+            device.connect(Protocol::CAN, ConnectFlags::empty(), 5000000),
+            Err(Error::InvalidArgument(_))
+        ));
+        device
+            .connect(Protocol::CAN, ConnectFlags::empty(), 33333)
+            .unwrap();
+        device
+            .connect_unchecked(Protocol::J1850VPW, ConnectFlags::empty(), 1)
+            .unwrap();
+    }
+
+    #[test]
+    fn clear_periodic_messages_forgets_handles() {
+        let channel = mock::can_channel();
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x3E, 0x80]);
+        let id = channel
+            .start_periodic_message(&msg, Duration::from_millis(100))
+            .unwrap();
+        let periodic = channel
+            .periodic_msg(&msg, Duration::from_millis(100))
+            .unwrap();
+
+        channel.clear_periodic_messages().unwrap();
+        assert!(mock::channel(channel.id()).periodic.is_empty());
+        assert!(channel.active_periodic_msgs().is_empty());
+        assert!(matches!(
+            channel.stop_periodic_message(id),
+            Err(Error::InvalidMessageId)
+        ));
+        assert!(matches!(periodic.stop(), Err(Error::InvalidMessageId)));
+    }
+
+    #[test]
+    fn pass_thru_msg_layout() {
+        assert_eq!(std::mem::size_of::<PassThruMsg>(), 4152);
+        assert_eq!(std::mem::offset_of!(PassThruMsg, protocol_id), 0);
+        assert_eq!(std::mem::offset_of!(PassThruMsg, rx_status), 4);
+        assert_eq!(std::mem::offset_of!(PassThruMsg, tx_flags), 8);
+        assert_eq!(std::mem::offset_of!(PassThruMsg, timestamp), 12);
+        assert_eq!(std::mem::offset_of!(PassThruMsg, data_size), 16);
+        assert_eq!(std::mem::offset_of!(PassThruMsg, extra_data_index), 20);
+        assert_eq!(std::mem::offset_of!(PassThruMsg, data), 24);
+    }
+
+    #[test]
+    fn byte_array_layout() {
+        assert_eq!(
+            std::mem::size_of::<SByteArray>(),
+            2 * std::mem::size_of::<usize>()
+        );
+        assert_eq!(std::mem::offset_of!(SByteArray, num_of_bytes), 0);
+        assert_eq!(
+            std::mem::offset_of!(SByteArray, byte_ptr),
+            std::mem::size_of::<usize>()
+        );
+    }
+
+    #[test]
+    fn functional_lookup_table() {
+        let channel = mock::connect(Protocol::J1850PWM, ConnectFlags::empty(), 41600);
+        channel.add_functional_addresses(&[0x6A, 0x6B]).unwrap();
+        channel.add_functional_addresses(&[0x6C]).unwrap();
+        channel.remove_functional_addresses(&[0x6B]).unwrap();
+        assert_eq!(mock::channel(channel.id()).functional, [0x6A, 0x6C]);
+        channel.clear_functional_addresses().unwrap();
+        assert!(mock::channel(channel.id()).functional.is_empty());
+        assert!(matches!(
+            channel.add_functional_addresses(&[]),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn config_list_layout() {
+        let word = std::mem::size_of::<usize>();
+        assert_eq!(std::mem::offset_of!(SConfig, parameter), 0);
+        assert_eq!(std::mem::offset_of!(SConfig, value), 4);
+        assert_eq!(std::mem::offset_of!(SConfigList, num_of_params), 0);
+        assert_eq!(std::mem::offset_of!(SConfigList, config_ptr), word);
+
+        let mut items = [SConfig::new(ConfigId::LOOPBACK, 1); 3];
+        let list = SConfigList::new(&mut items);
+        assert_eq!(list.num_of_params(), 3);
+        assert_eq!(list.config_ptr, items.as_mut_ptr());
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let channel = mock::connect(Protocol::ISO15765, ConnectFlags::empty(), 500000);
+        channel
+            .set_configs(&[(ConfigId::ISO15765_BS, 8), (ConfigId::ISO15765_STMIN, 20)])
+            .unwrap();
+        channel.set_loopback(true).unwrap();
+        assert!(channel.loopback().unwrap());
+
+        let values = channel.get_configs(&[ConfigId::ISO15765_BS, ConfigId::ISO15765_STMIN]);
+        assert!(matches!(values[0], (ConfigId::ISO15765_BS, Ok(8))));
+        assert!(matches!(values[1], (ConfigId::ISO15765_STMIN, Ok(20))));
+        assert!(channel.get_configs(&[]).is_empty());
+
+        // Vendor-specific parameters aren't checked
+        channel
+            .set_configs_unchecked(&[SConfig {
+                parameter: 0x10001,
+                value: 0x1234,
+            }])
+            .unwrap();
+        assert_eq!(mock::channel(channel.id()).configs[&0x10001], 0x1234);
+    }
+
+    #[test]
+    fn config_checks() {
+        let channel = mock::connect(Protocol::ISO15765, ConnectFlags::empty(), 500000);
+        for (id, value) in [
+            (ConfigId::LOOPBACK, 2),
+            (ConfigId::ISO15765_BS, 0x100),
+            (ConfigId::DATA_RATE, 2000000),
+        ] {
+            assert!(matches!(
+                channel.set_config(id, value),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert!(mock::channel(channel.id()).configs.is_empty());
+        channel.set_data_rate(250000).unwrap();
+        assert_eq!(channel.data_rate().unwrap(), 250000);
+    }
+
+    #[test]
+    fn config_applies_to() {
+        assert!(ConfigId::LOOPBACK.applies_to(Protocol::SCI_A_ENGINE));
+        assert!(ConfigId::NODE_ADDRESS.applies_to(Protocol::J1850PWM));
+        assert!(ConfigId::NODE_ADDRESS.applies_to(Protocol::J1850PWM_PS));
+        assert!(!ConfigId::NODE_ADDRESS.applies_to(Protocol::J1850VPW));
+        assert!(ConfigId::P2_MAX.applies_to(Protocol::ISO14230));
+        assert!(!ConfigId::P2_MAX.applies_to(Protocol::CAN));
+        assert!(ConfigId::SYNC_JUMP_WIDTH.applies_to(Protocol::SW_CAN_PS));
+        assert!(ConfigId::ISO15765_STMIN.applies_to(Protocol::ISO15765_PS));
+        assert!(!ConfigId::ISO15765_STMIN.applies_to(Protocol::CAN));
+        assert!(ConfigId::T1_MAX.applies_to(Protocol::SCI_B_TRANS));
+        assert!(ConfigId::J1962_PINS.applies_to(Protocol::CAN_PS));
+        assert!(!ConfigId::J1962_PINS.applies_to(Protocol::CAN));
+        assert!(ConfigId::CAN_MIXED_FORMAT.applies_to(Protocol::ISO15765));
+        assert!(!ConfigId::CAN_MIXED_FORMAT.applies_to(Protocol::CAN));
+
+        assert_eq!(ConfigId::try_from(0x03).unwrap(), ConfigId::LOOPBACK);
+        assert!(ConfigId::try_from(0x02).is_err());
+        for &id in ConfigId::ALL.iter() {
+            assert_eq!(ConfigId::try_from(id as u32).unwrap(), id);
+        }
+        assert_eq!(
+            ConfigId::try_from(0x8027).unwrap(),
+            ConfigId::INPUT_RANGE_HIGH
+        );
+        assert_eq!(ConfigId::ISO15765_BS.to_string(), "ISO15765_BS");
+    }
+
+    #[test]
+    fn battery_voltage() {
+        let device = mock::open();
+        assert_eq!(device.read_battery_voltage().unwrap(), 12600);
+        mock::with_device(device.id(), |device| device.vbatt = 13800);
+        assert_eq!(device.read_battery_voltage_volts().unwrap(), 13.8);
+
+        mock::with_device(device.id(), |device| device.fail = Some(0x01));
+        assert!(matches!(
+            device.read_battery_voltage(),
+            Err(Error::NotSupported)
+        ));
+    }
+
+    #[test]
+    fn loopback_echoes_writes() {
+        let channel = mock::can_channel();
+        assert!(!channel.loopback().unwrap());
+        channel.set_loopback(true).unwrap();
+        assert_eq!(
+            mock::channel(channel.id()).configs[&(ConfigId::LOOPBACK as u32)],
+            1
+        );
+
+        let msg = PassThruMsg::new_can(0x7E0, &[0x02, 0x10, 0x03]);
+        channel.write_msg(&msg, Duration::from_millis(10)).unwrap();
+        let echo = channel.read_verbatim(Duration::from_millis(10)).unwrap();
+        assert!(echo.transmitted());
+        assert_eq!(echo.can_message(), msg.can_message());
+
+        channel.set_loopback(false).unwrap();
+        assert!(!channel.loopback().unwrap());
+    }
+
+    #[test]
+    fn byte_array_bounds() {
+        let mut array = ByteArray::new(&[1, 2, 3]);
+        assert_eq!(array.num_of_bytes(), 3);
+        assert_eq!(array.bytes(), [1, 2, 3]);
+        let raw = array.as_output_ptr() as *mut SByteArray;
+
+        // A driver reporting more bytes than it was given
+        unsafe { (*raw).num_of_bytes = 10 };
+        assert_eq!(array.bytes(), [1, 2, 3]);
+        unsafe { (*raw).num_of_bytes = 1 };
+        assert_eq!(array.bytes(), [1]);
+
+        let array = ByteArray::with_capacity(4);
+        assert_eq!(array.bytes(), [0; 4]);
+    }
+
+    #[test]
+    fn five_baud_init_keys() {
+        let channel = mock::connect(Protocol::ISO9141, ConnectFlags::empty(), 10400);
+        assert!(matches!(
+            channel.five_baud_init(0x33),
+            Err(Error::NoInitResponse)
+        ));
+
+        mock::with_channel(channel.id(), |channel| {
+            channel.init_keys = Some(vec![0x08, 0x08])
+        });
+        assert_eq!(channel.five_baud_init(0x33).unwrap(), [0x08, 0x08]);
+        assert_eq!(mock::channel(channel.id()).init_addresses, [0x33, 0x33]);
+
+        mock::with_channel(channel.id(), |channel| channel.init_keys = Some(vec![0x08]));
+        assert!(matches!(channel.five_baud_init(0x33), Err(Error::Failed)));
+    }
+
+    #[test]
+    fn device_raw_round_trip() {
+        let interface = mock::interface();
+        let device = interface.open_any().unwrap();
+        let id = device.id();
+        assert_eq!(device.into_raw(), id);
+        assert_eq!(mock::device(id).closes, 0);
+
+        let device = unsafe { Device::from_raw(&interface, id) };
+        assert_eq!(device.id(), id);
+        assert!(device.read_battery_voltage().is_ok());
+        drop(device);
+        assert_eq!(mock::device(id).closes, 1);
+    }
+
+    #[test]
+    fn channel_raw_round_trip() {
+        let device = mock::open();
+        let channel = device
+            .connect(Protocol::ISO15765, ConnectFlags::CAN_29_BIT_ID, 500000)
+            .unwrap();
+        let id = channel.into_raw();
+        assert!(mock::channel(id).connected);
+
+        let channel = unsafe {
+            Channel::from_raw(
+                device,
+                id,
+                Protocol::ISO15765 as u32,
+                ConnectFlags::CAN_29_BIT_ID,
+                500000,
+            )
+        };
+        assert_eq!(channel.id(), id);
+        assert_eq!(channel.protocol(), Some(Protocol::ISO15765));
+        assert_eq!(channel.flags(), ConnectFlags::CAN_29_BIT_ID);
+        channel.clear_receive_buffer().unwrap();
+        drop(channel);
+        assert!(!mock::channel(id).connected);
+    }
+
+    #[test]
+    fn open_named_encodes_name() {
+        let interface = mock::interface();
+        let device = interface.open_named("J2534 Gerät 1").unwrap();
+        assert_eq!(
+            mock::device(device.id()).name.as_deref(),
+            Some("J2534 Gerät 1".as_bytes())
+        );
+        assert!(matches!(
+            interface.open_named("J2534\0"),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn is_connected_clears_connection_lost() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.fail = Some(0x08));
+        assert!(matches!(
+            device.read_battery_voltage(),
+            Err(Error::DeviceNotConnected)
+        ));
+        assert!(matches!(
+            device.read_battery_voltage(),
+            Err(Error::PreviouslyDisconnected)
+        ));
+        assert!(!device.is_connected());
+
+        // The hardware was plugged back in
+        mock::with_device(device.id(), |device| device.fail = None);
+        assert!(device.is_connected());
+        assert_eq!(device.read_battery_voltage().unwrap(), 12600);
+    }
+
+    #[test]
+    fn vendor_messages_policy() {
+        let interface = mock::interface();
+        assert_eq!(interface.vendor_messages(), VendorMessages::Never);
+        let device = interface.open_any().unwrap();
+        mock::with_device(device.id(), |device| device.fail = Some(0x07));
+        assert!(matches!(device.read_version(), Err(Error::Failed)));
+
+        interface.set_vendor_messages(VendorMessages::OnFailed);
+        let err = device.read_version().unwrap_err();
+        assert!(matches!(err.inner(), Error::Failed));
+        assert_eq!(err.vendor_message(), Some("mock error 0x7"));
+        assert_eq!(err.as_code(), Some(0x07));
+
+        mock::with_device(device.id(), |device| device.fail = Some(0x01));
+        assert!(matches!(device.read_version(), Err(Error::NotSupported)));
+        interface.set_vendor_messages(VendorMessages::Always);
+        let err = device.read_version().unwrap_err();
+        assert!(matches!(err.inner(), Error::NotSupported));
+        assert!(err.vendor_message().is_some());
+    }
+
+    #[test]
+    fn vendor_messages_keep_error_handling() {
+        let interface = mock::interface();
+        interface.set_vendor_messages(VendorMessages::Always);
+        let device = interface.open_any().unwrap();
+        let channel = device
+            .connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+            .unwrap();
+
+        // A full queue is still retried until the timeout
+        mock::with_channel(channel.id(), |channel| {
+            channel.write_result = Some((0, 0x11))
+        });
+        let mut msgs = [PassThruMsg::new_can(0x7E0, &[0x01, 0x00])];
+        assert!(matches!(
+            channel.write_all(&mut msgs, Duration::from_millis(20)),
+            Err(Error::PartialWrite { written: 0 })
+        ));
+        assert!(mock::channel(channel.id()).written.is_empty());
+
+        // The old periodic message is still stopped to make room for its replacement
+        mock::with_channel(channel.id(), |channel| channel.write_result = None);
+        let mut periodic = channel
+            .periodic_msg(&msgs[0], Duration::from_millis(100))
+            .unwrap();
+        mock::with_channel(channel.id(), |channel| channel.periodic_limit = Some(1));
+        periodic
+            .update(None, Some(Duration::from_millis(50)))
+            .unwrap();
+        assert_eq!(mock::channel(channel.id()).periodic_msgs.len(), 1);
+    }
+
+    #[test]
+    fn handles_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Interface>();
+        assert_send_sync::<Device>();
+        assert_send_sync::<Channel>();
+        assert_send_sync::<ChannelReader>();
+        assert_send_sync::<ChannelWriter>();
+    }
+
+    #[test]
+    fn serialized_calls_keep_vendor_messages() {
+        let interface = mock::interface();
+        interface.serialize_calls(true);
+        interface.set_vendor_messages(VendorMessages::OnFailed);
+        let device = interface.open_any().unwrap();
+        mock::with_device(device.id(), |device| device.fail = Some(0x07));
+        let err = device.read_version().unwrap_err();
+        assert_eq!(err.vendor_message(), Some("mock error 0x7"));
+        assert_eq!(interface.last_error().unwrap(), "mock error 0x7");
+
+        // A description read for one call isn't attached to the next
+        mock::with_device(device.id(), |device| device.fail = Some(0x01));
+        interface.set_vendor_messages(VendorMessages::Never);
+        let err = device.read_version().unwrap_err();
+        assert!(matches!(err, Error::NotSupported));
+    }
+
+    #[test]
+    fn serialized_calls_dont_overlap() {
+        const COUNT: usize = 50;
+
+        /// Writes from one thread while reading the echoes from another, returning the most
+        /// calls that were in progress at once
+        fn max_in_flight(serialize: bool) -> usize {
+            let interface = mock::interface();
+            interface.serialize_calls(serialize);
+            let device = interface.open_any().unwrap();
+            let channel = device
+                .connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+                .unwrap();
+            channel.set_loopback(true).unwrap();
+            mock::with_channel(channel.id(), |channel| {
+                channel.read_delay = Duration::from_millis(1);
+                channel.write_delay = Duration::from_millis(1);
+            });
+
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    for i in 0..COUNT {
+                        let mut msgs = [PassThruMsg::new_can(0x7E0, &[i as u8])];
+                        channel.write(&mut msgs, Duration::ZERO).unwrap();
+                    }
+                });
+                let mut received = Vec::new();
+                while received.len() < COUNT {
+                    match channel.read_msgs(8, Duration::ZERO) {
+                        Ok(msgs) => received.extend(msgs.iter().map(|msg| msg.data[4])),
+                        Err(err) if matches!(err.inner(), Error::BufferEmpty) => {}
+                        Err(err) => panic!("{}", err),
+                    }
+                }
+                assert!(received.iter().copied().eq(0..COUNT as u8));
+            });
+            mock::device(device.id()).max_in_flight
+        }
+
+        // The reader and the writer overlap unless their calls are serialized
+        assert!(max_in_flight(false) > 1);
+        assert_eq!(max_in_flight(true), 1);
+    }
+
+    #[test]
+    fn devices_close_once() {
+        let interface = mock::interface();
+        let dropped = interface.open_any().unwrap();
+        let id = dropped.id();
+        drop(dropped);
+        assert_eq!(mock::device(id).closes, 1);
+
+        let closed = interface.open_any().unwrap();
+        let id = closed.id();
+        closed.close().unwrap();
+        assert_eq!(mock::device(id).closes, 1);
+
+        let raw = interface.open_any().unwrap().into_raw();
+        assert_eq!(mock::device(raw).closes, 0);
+        unsafe { Device::from_raw(&interface, raw) }
+            .close()
+            .unwrap();
+        assert_eq!(mock::device(raw).closes, 1);
+    }
+
+    #[test]
+    fn channels_disconnect_once() {
+        let interface = mock::interface();
+        let device = interface.open_any().unwrap();
+        let connect = |protocol| {
+            device
+                .connect(protocol, ConnectFlags::empty(), 500000)
+                .unwrap()
+        };
+
+        let dropped = connect(Protocol::CAN);
+        let id = dropped.id();
+        drop(dropped);
+        assert_eq!(mock::channel(id).disconnects, 1);
+
+        let disconnected = connect(Protocol::CAN);
+        let id = disconnected.id();
+        disconnected.disconnect().unwrap();
+        assert_eq!(mock::channel(id).disconnects, 1);
+
+        // The last half to be dropped disconnects the channel
+        let (reader, writer) = connect(Protocol::CAN).split();
+        let id = reader.channel.id();
+        drop(reader);
+        assert_eq!(mock::channel(id).disconnects, 0);
+        drop(writer);
+        assert_eq!(mock::channel(id).disconnects, 1);
+
+        let (reader, writer) = connect(Protocol::CAN).split();
+        let reunited = reader.reunite(writer).ok().unwrap();
+        let id = reunited.id();
+        drop(reunited);
+        assert_eq!(mock::channel(id).disconnects, 1);
+
+        let raw = connect(Protocol::CAN).into_raw();
+        assert_eq!(mock::channel(raw).disconnects, 0);
+        let channel = unsafe {
+            Channel::from_raw(
+                &device,
+                raw,
+                Protocol::CAN as u32,
+                ConnectFlags::empty(),
+                500000,
+            )
+        };
+        drop(channel);
+        assert_eq!(mock::channel(raw).disconnects, 1);
+
+        // Closing the device doesn't disconnect the channels again
+        let id = connect(Protocol::CAN).id();
+        let device_id = device.id();
+        drop(device);
+        assert_eq!(mock::channel(id).disconnects, 1);
+        assert_eq!(mock::device(device_id).closes, 1);
+    }
+
+    #[test]
+    fn error_codes() {
+        for code in 0..=0x1A {
+            assert_eq!(Error::from_code(code).as_code(), Some(code as u32));
+        }
+        assert_eq!(Error::from_code(0x10000).as_code(), Some(0x10000));
+
+        // Errors raised by the crate don't pretend to be driver results
+        assert_eq!(Error::InvalidArgument(String::new()).as_code(), None);
+        assert_eq!(Error::PartialWrite { written: 1 }.as_code(), None);
+        assert_eq!(Error::PreviouslyDisconnected.as_code(), None);
+        assert_eq!(Error::Cancelled.as_code(), None);
+    }
+}
@@ -0,0 +1,136 @@
+//! Loaded libraries, shared between interfaces created from the same path
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+#[cfg(unix)]
+use libloading::os::unix::Library as RawLibrary;
+#[cfg(windows)]
+use libloading::os::windows::Library as RawLibrary;
+use libloading::Library;
+
+/// The libraries loaded with [`Interface::new`](crate::Interface::new), keyed by their
+/// canonical path. Entries whose interfaces were all dropped are removed on the next load.
+static LIBRARIES: Mutex<Vec<(PathBuf, Weak<LoadedLibrary>)>> = Mutex::new(Vec::new());
+
+/// A loaded library and its handle
+pub(crate) struct LoadedLibrary {
+    library: Library,
+    handle: *mut libc::c_void,
+}
+
+// The handle is only read to pass it to the caller, the library itself can be used from any
+// thread
+unsafe impl Send for LoadedLibrary {}
+unsafe impl Sync for LoadedLibrary {}
+
+impl LoadedLibrary {
+    pub(crate) fn new(library: Library) -> LoadedLibrary {
+        // Take the handle apart and put it back together to learn its value
+        let raw = RawLibrary::from(library).into_raw();
+        let library = unsafe { RawLibrary::from_raw(raw) }.into();
+        LoadedLibrary {
+            library,
+            handle: raw.cast(),
+        }
+    }
+
+    /// Takes ownership of a handle loaded outside of this crate
+    pub(crate) unsafe fn from_raw(handle: *mut libc::c_void) -> LoadedLibrary {
+        LoadedLibrary {
+            library: RawLibrary::from_raw(handle.cast()).into(),
+            handle,
+        }
+    }
+
+    pub(crate) fn library(&self) -> &Library {
+        &self.library
+    }
+
+    pub(crate) fn handle(&self) -> *mut libc::c_void {
+        self.handle
+    }
+
+    /// Returns the handle without unloading the library
+    pub(crate) fn into_raw(self) -> *mut libc::c_void {
+        RawLibrary::from(self.library).into_raw().cast()
+    }
+}
+
+/// Returns the library at `path`, loading it unless an interface already did
+pub(crate) fn load_shared(path: &Path) -> Result<Arc<LoadedLibrary>, libloading::Error> {
+    // Paths that can't be canonicalized are left to the loader's search order
+    let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    let mut libraries = LIBRARIES.lock().unwrap();
+    libraries.retain(|(_, library)| library.strong_count() > 0);
+    if let Some(library) = libraries
+        .iter()
+        .find(|(path, _)| *path == key)
+        .and_then(|(_, library)| library.upgrade())
+    {
+        return Ok(library);
+    }
+
+    let library = Arc::new(LoadedLibrary::new(Library::new(path)?));
+    libraries.push((key, Arc::downgrade(&library)));
+    Ok(library)
+}
+
+#[cfg(all(test, any(windows, target_os = "linux")))]
+mod tests {
+    use super::*;
+
+    /// A library that every process of the system can load
+    #[cfg(windows)]
+    const SYSTEM_LIBRARY: &str = "kernel32.dll";
+    #[cfg(target_os = "linux")]
+    const SYSTEM_LIBRARY: &str = "libc.so.6";
+
+    #[test]
+    fn handle_round_trip() {
+        let library = LoadedLibrary::new(Library::new(SYSTEM_LIBRARY).unwrap());
+        let handle = library.handle();
+        assert!(!handle.is_null());
+
+        let raw = library.into_raw();
+        assert_eq!(raw, handle);
+        let library = unsafe { LoadedLibrary::from_raw(raw) };
+        assert_eq!(library.handle(), handle);
+        assert_eq!(library.into_raw(), handle);
+
+        // Unload the library the handle still holds
+        drop(unsafe { LoadedLibrary::from_raw(handle) });
+    }
+
+    fn registered(key: &Path) -> Vec<usize> {
+        LIBRARIES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(path, _)| path == key)
+            .map(|(_, library)| library.strong_count())
+            .collect()
+    }
+
+    #[test]
+    fn shared_until_dropped() {
+        let path = Path::new(SYSTEM_LIBRARY);
+        let first = load_shared(path).unwrap();
+        let second = load_shared(path).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(registered(path), [2]);
+
+        drop(first);
+        drop(second);
+        // The entry is pruned by the next load, which loads the library again
+        assert_eq!(registered(path), [0]);
+        let third = load_shared(path).unwrap();
+        assert_eq!(registered(path), [1]);
+
+        // A library loaded for one interface only isn't registered
+        let unshared = Arc::new(LoadedLibrary::new(Library::new(path).unwrap()));
+        assert!(!Arc::ptr_eq(&third, &unshared));
+        assert_eq!(registered(path), [1]);
+    }
+}
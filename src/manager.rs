@@ -0,0 +1,187 @@
+//! Loading installed drivers on demand with [`DriverManager`]
+
+use std::sync::OnceLock;
+
+use crate::{drivers, Device, Driver, Error, Interface};
+
+/// An installed driver known to a [`DriverManager`] and its library, once loaded
+#[derive(Debug)]
+pub struct DriverEntry {
+    driver: Driver,
+    interface: OnceLock<Interface>,
+}
+
+impl DriverEntry {
+    /// Returns the name, vendor and library path of the driver
+    pub fn driver(&self) -> &Driver {
+        &self.driver
+    }
+
+    /// Returns the library if it was loaded
+    pub fn interface(&self) -> Option<&Interface> {
+        self.interface.get()
+    }
+
+    /// Returns true if the library was loaded
+    pub fn is_loaded(&self) -> bool {
+        self.interface.get().is_some()
+    }
+}
+
+/// Why a driver couldn't open a device in [`DriverManager::open_first_available`]
+#[derive(Debug)]
+pub struct DriverFailure {
+    /// The driver that failed, as listed by the manager
+    pub driver: Driver,
+    /// The error from loading the library or opening the device
+    pub error: Error,
+}
+
+/// Describes the failures of [`Error::NoDeviceAvailable`]
+pub(crate) fn describe_failures(failures: &[DriverFailure]) -> String {
+    if failures.is_empty() {
+        return "no drivers are installed".to_string();
+    }
+    failures
+        .iter()
+        .map(|failure| format!("{}: {}", failure.driver.name, failure.error))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The installed drivers, whose libraries are loaded when first used.
+///
+/// # Example
+/// ```no_run
+/// use j2534::DriverManager;
+///
+/// let manager = DriverManager::discover().unwrap();
+/// for entry in manager.drivers() {
+///     println!("{} ({})", entry.driver().name, entry.driver().vendor);
+/// }
+/// let (device, entry) = manager.open_first_available().unwrap();
+/// println!("opened a device of {}", entry.driver().name);
+/// ```
+#[derive(Debug)]
+pub struct DriverManager {
+    entries: Vec<DriverEntry>,
+}
+
+impl DriverManager {
+    /// Returns a manager of the drivers returned by [`drivers`]. No library is loaded yet.
+    pub fn discover() -> Result<DriverManager, Error> {
+        Ok(DriverManager::from_drivers(drivers()?))
+    }
+
+    /// Returns a manager of `drivers`, like a list filtered by the application
+    pub fn from_drivers(drivers: Vec<Driver>) -> DriverManager {
+        DriverManager {
+            entries: drivers
+                .into_iter()
+                .map(|driver| DriverEntry {
+                    driver,
+                    interface: OnceLock::new(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the drivers in the order they were discovered
+    pub fn drivers(&self) -> &[DriverEntry] {
+        &self.entries
+    }
+
+    /// Returns the library of the driver at `index`, loading it the first time.
+    ///
+    /// Returns [`Error::InvalidArgument`] if `index` is out of range and [`Error::Library`] if
+    /// the library can't be loaded, in which case the next call tries again.
+    pub fn load(&self, index: usize) -> Result<&Interface, Error> {
+        let entry = self.entries.get(index).ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "driver index {} is out of range, there are {} drivers",
+                index,
+                self.entries.len()
+            ))
+        })?;
+        if let Some(interface) = entry.interface.get() {
+            return Ok(interface);
+        }
+        // If another thread loaded the library first, this interface shares its library and is
+        // dropped
+        let _ = entry.interface.set(Interface::from_driver(&entry.driver)?);
+        Ok(entry.interface.get().unwrap())
+    }
+
+    /// Opens any device of the first driver that has one, trying the drivers in order.
+    ///
+    /// Returns [`Error::NoDeviceAvailable`] with the error of every driver if none could open a
+    /// device.
+    pub fn open_first_available(&self) -> Result<(Device<'_>, &DriverEntry), Error> {
+        let mut failures = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            match self.load(index).and_then(|interface| interface.open_any()) {
+                Ok(device) => return Ok((device, entry)),
+                Err(error) => failures.push(DriverFailure {
+                    driver: entry.driver.clone(),
+                    error,
+                }),
+            }
+        }
+        Err(Error::NoDeviceAvailable(failures))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing_driver(name: &str) -> Driver {
+        Driver {
+            name: name.to_string(),
+            vendor: "Nobody".to_string(),
+            path: "/nonexistent/j2534.dll".to_string(),
+        }
+    }
+
+    #[test]
+    fn no_device_available() {
+        let manager =
+            DriverManager::from_drivers(vec![missing_driver("First"), missing_driver("Second")]);
+        let failures = match manager.open_first_available() {
+            Err(Error::NoDeviceAvailable(failures)) => failures,
+            other => panic!("{:?}", other.map(|(device, _)| device.id())),
+        };
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].driver.name, "First");
+        assert_eq!(failures[1].driver.name, "Second");
+        assert!(failures
+            .iter()
+            .all(|failure| matches!(failure.error, Error::Library(_))));
+        assert!(manager.drivers().iter().all(|entry| !entry.is_loaded()));
+
+        let description = describe_failures(&failures);
+        assert!(description.starts_with("First: "));
+        assert!(description.contains("; Second: "));
+    }
+
+    #[test]
+    fn no_drivers_installed() {
+        let manager = DriverManager::from_drivers(Vec::new());
+        match manager.open_first_available() {
+            Err(Error::NoDeviceAvailable(failures)) => assert!(failures.is_empty()),
+            other => panic!("{:?}", other.map(|(device, _)| device.id())),
+        };
+        assert_eq!(describe_failures(&[]), "no drivers are installed");
+    }
+
+    #[test]
+    fn load_retries_after_failure() {
+        let manager = DriverManager::from_drivers(vec![missing_driver("First")]);
+        assert!(matches!(manager.load(0), Err(Error::Library(_))));
+        // Nothing is cached, so the next call tries to load the library again
+        assert!(matches!(manager.load(0), Err(Error::Library(_))));
+        assert!(manager.drivers()[0].interface().is_none());
+
+        assert!(matches!(manager.load(1), Err(Error::InvalidArgument(_))));
+    }
+}
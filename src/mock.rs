@@ -0,0 +1,661 @@
+//! An in-process J2534 driver for the unit tests
+//!
+//! [`interface`] returns an [`Interface`] whose functions are implemented here instead of in a
+//! vendor library. Devices and channels get IDs that are unique across all tests, so tests can
+//! run in parallel and inspect the state of their own devices and channels.
+
+// Not every test uses every part of the driver
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::CStr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[cfg(unix)]
+use libloading::os::unix::Library as RawLibrary;
+#[cfg(windows)]
+use libloading::os::windows::Library as RawLibrary;
+
+use crate::library::LoadedLibrary;
+use crate::{
+    Channel, ConnectFlags, Device, Interface, IoctlId, PassThruMsg, Protocol, RxStatus, SConfig,
+    VendorMessages,
+};
+
+const STATUS_NOERROR: i32 = 0x00;
+const ERR_INVALID_CHANNEL_ID: i32 = 0x02;
+const ERR_INVALID_PROTOCOL_ID: i32 = 0x03;
+const ERR_INVALID_IOCTL_VALUE: i32 = 0x05;
+const ERR_FAILED: i32 = 0x07;
+const ERR_TIMEOUT: i32 = 0x09;
+const ERR_EXCEEDED_LIMIT: i32 = 0x0C;
+const ERR_INVALID_MSG_ID: i32 = 0x0D;
+const ERR_BUFFER_EMPTY: i32 = 0x10;
+const ERR_INVALID_FILTER_ID: i32 = 0x16;
+const ERR_INVALID_DEVICE_ID: i32 = 0x1A;
+
+/// The state of a device opened through the mock driver
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MockDevice {
+    /// The name passed to `PassThruOpen`
+    pub name: Option<Vec<u8>>,
+    /// The number of times `PassThruClose` was called with the device's ID
+    pub closes: usize,
+    /// The battery voltage returned by `READ_VBATT`
+    pub vbatt: u32,
+    /// When set, `READ_VBATT` fails with this code
+    pub vbatt_error: Option<i32>,
+    /// The firmware, library and API versions, or `None` for `1.2.3`, `04.04.1` and `04.04`
+    pub versions: Option<[&'static str; 3]>,
+    /// When set, every device-level call fails with this code
+    pub fail: Option<i32>,
+    /// The number of device-level calls that reached the driver
+    pub calls: usize,
+    /// Protocols `PassThruConnect` refuses with `ERR_INVALID_PROTOCOL_ID`
+    pub refused_protocols: Vec<u32>,
+    /// The state channels connected to the device start with
+    pub new_channel: MockChannel,
+    /// The pin and voltage of each `PassThruSetProgrammingVoltage` call, oldest first
+    pub voltages: Vec<(u32, u32)>,
+    /// The reads and writes on the device's channels that are in progress
+    pub in_flight: usize,
+    /// The most reads and writes on the device's channels that were in progress at once
+    pub max_in_flight: usize,
+}
+
+/// A filter started through the mock driver
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct MockFilter {
+    pub filter_type: u32,
+    pub mask: Option<PassThruMsg>,
+    pub pattern: Option<PassThruMsg>,
+    pub flow_control: Option<PassThruMsg>,
+}
+
+/// The state of a channel connected through the mock driver
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MockChannel {
+    pub device: u32,
+    pub protocol_id: u32,
+    pub connected: bool,
+    /// The number of times `PassThruDisconnect` was called with the channel's ID
+    pub disconnects: usize,
+    /// Messages returned by `PassThruReadMsgs`, oldest first
+    pub rx: VecDeque<PassThruMsg>,
+    /// Messages accepted by `PassThruWriteMsgs`
+    pub written: Vec<PassThruMsg>,
+    /// When set, `PassThruWriteMsgs` accepts this many messages and returns the code
+    pub write_result: Option<(usize, i32)>,
+    /// The time `PassThruWriteMsgs` takes before accepting messages
+    pub write_delay: Duration,
+    /// The time `PassThruReadMsgs` takes before reading messages
+    pub read_delay: Duration,
+    /// When set, `PassThruReadMsgs` reads nothing and returns the code
+    pub read_error: Option<i32>,
+    /// The number of calls to `PassThruReadMsgs`
+    pub reads: usize,
+    pub filters: Vec<u32>,
+    /// When set, `PassThruStartMsgFilter` fails once this many filters are running
+    pub filter_limit: Option<usize>,
+    /// The messages of each running filter
+    pub filter_msgs: BTreeMap<u32, MockFilter>,
+    pub periodic: Vec<u32>,
+    /// When set, `PassThruStartPeriodicMsg` fails once this many messages are running
+    pub periodic_limit: Option<usize>,
+    /// The message and interval of each running periodic message
+    pub periodic_msgs: BTreeMap<u32, (PassThruMsg, u32)>,
+    /// Periodic messages that fail to stop with `ERR_FAILED`
+    pub stuck_periodic: Vec<u32>,
+    pub configs: BTreeMap<u32, u32>,
+    /// Whether writes aren't echoed even with loopback enabled
+    pub drop_echoes: bool,
+    /// Parameters `GET_CONFIG` and `SET_CONFIG` fail on with `ERR_INVALID_IOCTL_VALUE`
+    pub unknown_configs: Vec<u32>,
+    /// The functional message lookup table
+    pub functional: Vec<u8>,
+    /// The bytes `FIVE_BAUD_INIT` returns, or `None` if the ECU doesn't respond
+    pub init_keys: Option<Vec<u8>>,
+    /// The target addresses passed to `FIVE_BAUD_INIT`
+    pub init_addresses: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    devices: BTreeMap<u32, MockDevice>,
+    channels: BTreeMap<u32, MockChannel>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    devices: BTreeMap::new(),
+    channels: BTreeMap::new(),
+});
+
+/// The source of device, channel, filter and periodic message IDs
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+thread_local! {
+    /// The description returned by `PassThruGetLastError`
+    static LAST_ERROR: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    /// The state of the next device the thread opens, for code that opens devices itself
+    static NEXT_DEVICE: RefCell<Option<MockDevice>> = const { RefCell::new(None) };
+}
+
+fn next_id() -> u32 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn fail(code: i32) -> i32 {
+    set_last_error(&format!("mock error {:#x}", code));
+    code
+}
+
+/// Sets the state of the next device opened by this thread
+pub(crate) fn set_next_device(device: MockDevice) {
+    NEXT_DEVICE.with(|next| *next.borrow_mut() = Some(device));
+}
+
+/// Returns the state of a device
+pub(crate) fn device(id: u32) -> MockDevice {
+    STATE.lock().unwrap().devices[&id].clone()
+}
+
+/// Changes the state of a device
+pub(crate) fn with_device<T>(id: u32, f: impl FnOnce(&mut MockDevice) -> T) -> T {
+    f(STATE.lock().unwrap().devices.get_mut(&id).unwrap())
+}
+
+/// Returns the state of a channel
+pub(crate) fn channel(id: u32) -> MockChannel {
+    STATE.lock().unwrap().channels[&id].clone()
+}
+
+/// Changes the state of a channel
+pub(crate) fn with_channel<T>(id: u32, f: impl FnOnce(&mut MockChannel) -> T) -> T {
+    f(STATE.lock().unwrap().channels.get_mut(&id).unwrap())
+}
+
+/// Queues a message to be read from a channel
+pub(crate) fn push_rx(channel: u32, msg: PassThruMsg) {
+    with_channel(channel, |channel| channel.rx.push_back(msg));
+}
+
+/// Sets the description returned by `PassThruGetLastError` on the calling thread
+pub(crate) fn set_last_error(description: &str) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = description.as_bytes().to_vec());
+}
+
+/// Returns an interface backed by the mock driver
+pub(crate) fn interface() -> Interface {
+    #[cfg(unix)]
+    let this = RawLibrary::this();
+    #[cfg(windows)]
+    let this = RawLibrary::this().unwrap();
+    Interface {
+        library: Arc::new(LoadedLibrary::new(this.into())),
+        c_pass_thru_open: pass_thru_open,
+        c_pass_thru_close: pass_thru_close,
+        c_pass_thru_connect: pass_thru_connect,
+        c_pass_thru_disconnect: pass_thru_disconnect,
+        c_pass_thru_read_version: pass_thru_read_version,
+        c_pass_thru_get_last_error: pass_thru_get_last_error,
+        c_pass_thru_read_msgs: pass_thru_read_msgs,
+        c_pass_thru_start_msg_filter: pass_thru_start_msg_filter,
+        c_pass_thru_stop_msg_filter: pass_thru_stop_msg_filter,
+        c_pass_thru_write_msgs: pass_thru_write_msgs,
+        c_pass_thru_start_periodic_msg: pass_thru_start_periodic_msg,
+        c_pass_thru_stop_periodic_msg: pass_thru_stop_periodic_msg,
+        c_pass_thru_set_programming_voltage: pass_thru_set_programming_voltage,
+        c_pass_thru_ioctl: pass_thru_ioctl,
+        c_pass_thru_scan_for_devices: None,
+        c_pass_thru_get_next_device: None,
+        c_pass_thru_get_next_car_daq: None,
+        vendor_messages: AtomicU8::new(VendorMessages::default() as u8),
+        path: PathBuf::from("mock"),
+        driver: None,
+        serialize_calls: AtomicBool::new(false),
+        serial: Mutex::new(()),
+    }
+}
+
+/// Returns a new mock device. The interface and the device are leaked so that the device can be
+/// returned, use [`interface`] for tests that close them.
+pub(crate) fn open() -> &'static Device<'static> {
+    let interface: &'static Interface = Box::leak(Box::new(interface()));
+    Box::leak(Box::new(interface.open_any().unwrap()))
+}
+
+/// Returns a channel connected through a new mock device
+pub(crate) fn connect(protocol: Protocol, flags: ConnectFlags, baudrate: u32) -> Channel<'static> {
+    open().connect(protocol, flags, baudrate).unwrap()
+}
+
+/// Returns a 500 kbit/s CAN channel connected through a new mock device
+pub(crate) fn can_channel() -> Channel<'static> {
+    connect(Protocol::CAN, ConnectFlags::empty(), 500000)
+}
+
+/// Runs a device-level call, failing it if the device was told to fail
+fn device_call(id: u32, f: impl FnOnce(&mut MockDevice) -> i32) -> i32 {
+    let mut state = STATE.lock().unwrap();
+    let device = match state.devices.get_mut(&id) {
+        Some(device) if device.closes == 0 => device,
+        _ => return fail(ERR_INVALID_DEVICE_ID),
+    };
+    device.calls += 1;
+    if let Some(code) = device.fail {
+        return fail(code);
+    }
+    f(device)
+}
+
+/// Runs a read or write on a channel, counting it as in progress on the channel's device while
+/// `f` runs. The state isn't locked in between, so that calls can overlap.
+fn in_flight(channel_id: u32, f: impl FnOnce() -> i32) -> i32 {
+    let device_id = {
+        let mut state = STATE.lock().unwrap();
+        let device_id = match state.channels.get(&channel_id) {
+            Some(channel) => channel.device,
+            None => return f(),
+        };
+        if let Some(device) = state.devices.get_mut(&device_id) {
+            device.in_flight += 1;
+            device.max_in_flight = device.max_in_flight.max(device.in_flight);
+        }
+        device_id
+    };
+    let res = f();
+    if let Some(device) = STATE.lock().unwrap().devices.get_mut(&device_id) {
+        device.in_flight -= 1;
+    }
+    res
+}
+
+/// Runs a channel-level call
+fn channel_call(id: u32, f: impl FnOnce(&mut MockChannel) -> i32) -> i32 {
+    let mut state = STATE.lock().unwrap();
+    match state.channels.get_mut(&id) {
+        Some(channel) if channel.connected => f(channel),
+        _ => fail(ERR_INVALID_CHANNEL_ID),
+    }
+}
+
+unsafe extern "stdcall" fn pass_thru_open(name: *const libc::c_void, device_id: *mut u32) -> i32 {
+    let id = next_id();
+    let name = if name.is_null() {
+        None
+    } else {
+        Some(
+            CStr::from_ptr(name as *const libc::c_char)
+                .to_bytes()
+                .to_vec(),
+        )
+    };
+    let device = NEXT_DEVICE.with(|next| next.borrow_mut().take());
+    STATE.lock().unwrap().devices.insert(
+        id,
+        MockDevice {
+            name,
+            ..device.unwrap_or(MockDevice {
+                vbatt: 12600,
+                ..MockDevice::default()
+            })
+        },
+    );
+    *device_id = id;
+    STATUS_NOERROR
+}
+
+unsafe extern "stdcall" fn pass_thru_close(device_id: u32) -> i32 {
+    match STATE.lock().unwrap().devices.get_mut(&device_id) {
+        Some(device) => {
+            device.closes += 1;
+            STATUS_NOERROR
+        }
+        None => fail(ERR_INVALID_DEVICE_ID),
+    }
+}
+
+unsafe extern "stdcall" fn pass_thru_connect(
+    device_id: u32,
+    protocol_id: u32,
+    _flags: u32,
+    _baudrate: u32,
+    channel_id: *mut u32,
+) -> i32 {
+    let mut channel = MockChannel::default();
+    let res = device_call(device_id, |device| {
+        if device.refused_protocols.contains(&protocol_id) {
+            return fail(ERR_INVALID_PROTOCOL_ID);
+        }
+        channel = device.new_channel.clone();
+        STATUS_NOERROR
+    });
+    if res != STATUS_NOERROR {
+        return res;
+    }
+    let id = next_id();
+    STATE.lock().unwrap().channels.insert(
+        id,
+        MockChannel {
+            device: device_id,
+            protocol_id,
+            connected: true,
+            ..channel
+        },
+    );
+    *channel_id = id;
+    STATUS_NOERROR
+}
+
+unsafe extern "stdcall" fn pass_thru_disconnect(channel_id: u32) -> i32 {
+    let mut state = STATE.lock().unwrap();
+    match state.channels.get_mut(&channel_id) {
+        // Counted even if the channel was disconnected already
+        Some(channel) => {
+            channel.disconnects += 1;
+            if !std::mem::replace(&mut channel.connected, false) {
+                return fail(ERR_INVALID_CHANNEL_ID);
+            }
+            STATUS_NOERROR
+        }
+        None => fail(ERR_INVALID_CHANNEL_ID),
+    }
+}
+
+unsafe extern "stdcall" fn pass_thru_read_version(
+    device_id: u32,
+    firmware_version: *mut libc::c_char,
+    dll_version: *mut libc::c_char,
+    api_version: *mut libc::c_char,
+) -> i32 {
+    device_call(device_id, |device| {
+        let versions = device.versions.unwrap_or(["1.2.3", "04.04.1", "04.04"]);
+        for (&dst, src) in [firmware_version, dll_version, api_version]
+            .iter()
+            .zip(versions.iter())
+        {
+            std::ptr::copy_nonoverlapping(src.as_ptr() as *const libc::c_char, dst, src.len());
+            *dst.add(src.len()) = 0;
+        }
+        STATUS_NOERROR
+    })
+}
+
+unsafe extern "stdcall" fn pass_thru_get_last_error(error_description: *mut libc::c_char) -> i32 {
+    LAST_ERROR.with(|last| {
+        let last = last.borrow();
+        let len = last.len().min(79);
+        std::ptr::copy_nonoverlapping(last.as_ptr() as *const libc::c_char, error_description, len);
+        *error_description.add(len) = 0;
+    });
+    STATUS_NOERROR
+}
+
+unsafe extern "stdcall" fn pass_thru_read_msgs(
+    channel_id: u32,
+    msgs: *mut PassThruMsg,
+    num_msgs: *mut u32,
+    timeout: u32,
+) -> i32 {
+    let wanted = *num_msgs as usize;
+    *num_msgs = 0;
+    in_flight(channel_id, || {
+        // Sleep without holding the state, so that other calls aren't delayed
+        let delay = STATE
+            .lock()
+            .unwrap()
+            .channels
+            .get(&channel_id)
+            .map_or(Duration::ZERO, |channel| channel.read_delay);
+        std::thread::sleep(delay);
+        channel_call(channel_id, |channel| {
+            channel.reads += 1;
+            if let Some(code) = channel.read_error {
+                return fail(code);
+            }
+            let mut count = 0;
+            while count < wanted {
+                match channel.rx.pop_front() {
+                    Some(msg) => *msgs.add(count) = msg,
+                    None => break,
+                }
+                count += 1;
+            }
+            *num_msgs = count as u32;
+            match (count, timeout) {
+                (0, 0) => ERR_BUFFER_EMPTY,
+                (n, t) if n < wanted && t > 0 => ERR_TIMEOUT,
+                _ => STATUS_NOERROR,
+            }
+        })
+    })
+}
+
+unsafe extern "stdcall" fn pass_thru_write_msgs(
+    channel_id: u32,
+    msgs: *mut PassThruMsg,
+    num_msgs: *mut u32,
+    _timeout: u32,
+) -> i32 {
+    let wanted = *num_msgs as usize;
+    in_flight(channel_id, || {
+        // Sleep without holding the state, so that other calls aren't delayed
+        let delay = STATE
+            .lock()
+            .unwrap()
+            .channels
+            .get(&channel_id)
+            .map_or(Duration::ZERO, |channel| channel.write_delay);
+        std::thread::sleep(delay);
+        channel_call(channel_id, |channel| {
+            let (accepted, res) = channel.write_result.unwrap_or((wanted, STATUS_NOERROR));
+            let accepted = accepted.min(wanted);
+            let loopback =
+                channel.configs.get(&0x03).copied().unwrap_or(0) != 0 && !channel.drop_echoes;
+            for i in 0..accepted {
+                let msg = *msgs.add(i);
+                channel.written.push(msg);
+                if loopback {
+                    let mut echo = msg;
+                    echo.rx_status = RxStatus::TX_MSG_TYPE.bits();
+                    channel.rx.push_back(echo);
+                }
+            }
+            *num_msgs = accepted as u32;
+            if res != STATUS_NOERROR {
+                fail(res)
+            } else {
+                res
+            }
+        })
+    })
+}
+
+unsafe extern "stdcall" fn pass_thru_start_periodic_msg(
+    channel_id: u32,
+    msg: *const PassThruMsg,
+    msg_id: *mut u32,
+    time_interval: u32,
+) -> i32 {
+    channel_call(channel_id, |channel| {
+        if channel
+            .periodic_limit
+            .is_some_and(|limit| channel.periodic.len() >= limit)
+        {
+            return fail(ERR_EXCEEDED_LIMIT);
+        }
+        let id = next_id();
+        channel.periodic.push(id);
+        channel.periodic_msgs.insert(id, (*msg, time_interval));
+        *msg_id = id;
+        STATUS_NOERROR
+    })
+}
+
+unsafe extern "stdcall" fn pass_thru_stop_periodic_msg(channel_id: u32, msg_id: u32) -> i32 {
+    channel_call(channel_id, |channel| {
+        if channel.stuck_periodic.contains(&msg_id) {
+            return fail(ERR_FAILED);
+        }
+        let len = channel.periodic.len();
+        channel.periodic.retain(|&id| id != msg_id);
+        channel.periodic_msgs.remove(&msg_id);
+        if channel.periodic.len() == len {
+            return fail(ERR_INVALID_MSG_ID);
+        }
+        STATUS_NOERROR
+    })
+}
+
+unsafe extern "stdcall" fn pass_thru_start_msg_filter(
+    channel_id: u32,
+    filter_type: u32,
+    mask_msg: *const PassThruMsg,
+    pattern_msg: *const PassThruMsg,
+    flow_control_msg: *const PassThruMsg,
+    filter_id: *mut u32,
+) -> i32 {
+    channel_call(channel_id, |channel| {
+        if channel.filter_limit == Some(channel.filters.len()) {
+            return fail(ERR_EXCEEDED_LIMIT);
+        }
+        let id = next_id();
+        channel.filters.push(id);
+        channel.filter_msgs.insert(
+            id,
+            MockFilter {
+                filter_type,
+                mask: mask_msg.as_ref().copied(),
+                pattern: pattern_msg.as_ref().copied(),
+                flow_control: flow_control_msg.as_ref().copied(),
+            },
+        );
+        *filter_id = id;
+        STATUS_NOERROR
+    })
+}
+
+unsafe extern "stdcall" fn pass_thru_stop_msg_filter(channel_id: u32, filter_id: u32) -> i32 {
+    channel_call(channel_id, |channel| {
+        let len = channel.filters.len();
+        channel.filters.retain(|&id| id != filter_id);
+        channel.filter_msgs.remove(&filter_id);
+        if channel.filters.len() == len {
+            return fail(ERR_INVALID_FILTER_ID);
+        }
+        STATUS_NOERROR
+    })
+}
+
+unsafe extern "stdcall" fn pass_thru_set_programming_voltage(
+    device_id: u32,
+    pin_number: u32,
+    voltage: u32,
+) -> i32 {
+    device_call(device_id, |device| {
+        device.voltages.push((pin_number, voltage));
+        STATUS_NOERROR
+    })
+}
+
+/// The `SCONFIG_LIST` structure as the driver sees it
+#[repr(C)]
+struct RawConfigList {
+    num_of_params: u32,
+    config_ptr: *mut SConfig,
+}
+
+/// The `SBYTE_ARRAY` structure as the driver sees it
+#[repr(C)]
+struct RawByteArray {
+    num_of_bytes: u32,
+    byte_ptr: *mut u8,
+}
+
+unsafe fn input_bytes<'a>(input: *mut libc::c_void) -> &'a [u8] {
+    let array = &*(input as *const RawByteArray);
+    std::slice::from_raw_parts(array.byte_ptr, array.num_of_bytes as usize)
+}
+
+unsafe extern "stdcall" fn pass_thru_ioctl(
+    handle_id: u32,
+    ioctl_id: u32,
+    input: *mut libc::c_void,
+    output: *mut libc::c_void,
+) -> i32 {
+    if ioctl_id == IoctlId::READ_VBATT as u32 {
+        return device_call(handle_id, |device| {
+            if let Some(code) = device.vbatt_error {
+                return fail(code);
+            }
+            *(output as *mut u32) = device.vbatt;
+            STATUS_NOERROR
+        });
+    }
+    if ioctl_id == IoctlId::READ_PROG_VOLTAGE as u32 {
+        return device_call(handle_id, |_| {
+            *(output as *mut u32) = 0;
+            STATUS_NOERROR
+        });
+    }
+    channel_call(handle_id, |channel| {
+        match ioctl_id {
+            id if id == IoctlId::GET_CONFIG as u32 || id == IoctlId::SET_CONFIG as u32 => {
+                let list = &*(input as *const RawConfigList);
+                let configs =
+                    std::slice::from_raw_parts_mut(list.config_ptr, list.num_of_params as usize);
+                if configs
+                    .iter()
+                    .any(|config| channel.unknown_configs.contains(&config.parameter))
+                {
+                    return fail(ERR_INVALID_IOCTL_VALUE);
+                }
+                for config in configs {
+                    if id == IoctlId::SET_CONFIG as u32 {
+                        channel.configs.insert(config.parameter, config.value);
+                    } else {
+                        config.value = channel.configs.get(&config.parameter).copied().unwrap_or(0);
+                    }
+                }
+            }
+            id if id == IoctlId::CLEAR_RX_BUFFER as u32 => channel.rx.clear(),
+            id if id == IoctlId::CLEAR_PERIODIC_MSGS as u32 => {
+                channel.periodic.clear();
+                channel.periodic_msgs.clear();
+            }
+            id if id == IoctlId::FIVE_BAUD_INIT as u32 => {
+                channel.init_addresses.extend_from_slice(input_bytes(input));
+                let keys = match &channel.init_keys {
+                    Some(keys) => keys,
+                    None => return fail(ERR_TIMEOUT),
+                };
+                let output = &mut *(output as *mut RawByteArray);
+                let len = keys.len().min(output.num_of_bytes as usize);
+                std::ptr::copy_nonoverlapping(keys.as_ptr(), output.byte_ptr, len);
+                output.num_of_bytes = len as u32;
+            }
+            id if id == IoctlId::ADD_TO_FUNCT_MSG_LOOKUP_TABLE as u32 => {
+                for &addr in input_bytes(input) {
+                    if !channel.functional.contains(&addr) {
+                        channel.functional.push(addr);
+                    }
+                }
+            }
+            id if id == IoctlId::DELETE_FROM_FUNCT_MSG_LOOKUP_TABLE as u32 => {
+                let addrs = input_bytes(input);
+                channel.functional.retain(|addr| !addrs.contains(addr));
+            }
+            id if id == IoctlId::CLEAR_FUNCT_MSG_LOOKUP_TABLE as u32 => channel.functional.clear(),
+            id if id == IoctlId::CLEAR_MSG_FILTERS as u32 => {
+                channel.filters.clear();
+                channel.filter_msgs.clear();
+            }
+            _ => {}
+        }
+        STATUS_NOERROR
+    })
+}
@@ -0,0 +1,171 @@
+//! Rate-limited transmission
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{deadline_after, Channel, Error, PassThruMsg};
+
+/// Writes messages to a channel with a minimum gap between them, so that other nodes on a slow
+/// bus get a chance to transmit.
+///
+/// The gap is measured from the start of one write to the start of the next, so time spent in the
+/// driver counts towards it.
+///
+/// # Example
+/// ```no_run
+/// use j2534::{ConnectFlags, Interface, PacedWriter, PassThruMsg, Protocol};
+/// use std::time::Duration;
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+/// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 125000).unwrap();
+///
+/// let mut writer = PacedWriter::new(&channel, Duration::from_millis(5));
+/// let msgs: Vec<_> = (0..10u8).map(|i| PassThruMsg::new_can(0x100, &[i])).collect();
+/// writer.write_msgs(&msgs, Duration::from_secs(1)).unwrap();
+/// println!("{:?} messages per second", writer.transmit_rate());
+/// ```
+pub struct PacedWriter<'a> {
+    channel: &'a Channel<'a>,
+    gap: Duration,
+    /// The time the last write started
+    last_write: Option<Instant>,
+    /// The time the first write started
+    first_write: Option<Instant>,
+    written: u64,
+}
+
+impl<'a> PacedWriter<'a> {
+    /// Creates a writer that leaves at least `gap` between the start of each write
+    pub fn new(channel: &'a Channel<'a>, gap: Duration) -> PacedWriter<'a> {
+        PacedWriter {
+            channel,
+            gap,
+            last_write: None,
+            first_write: None,
+            written: 0,
+        }
+    }
+
+    /// Returns the minimum gap between writes
+    pub fn gap(&self) -> Duration {
+        self.gap
+    }
+
+    /// Sets the minimum gap between writes
+    pub fn set_gap(&mut self, gap: Duration) {
+        self.gap = gap;
+    }
+
+    /// Returns the channel messages are written to
+    pub fn channel(&self) -> &'a Channel<'a> {
+        self.channel
+    }
+
+    /// Waits until the gap since the last write has passed and writes a single message.
+    /// See [`Channel::write_msg`].
+    ///
+    /// The timeout includes the time spent waiting for the gap. Returns [`Error::Timeout`] without
+    /// writing if the gap doesn't pass before the timeout expires.
+    pub fn write_msg(&mut self, msg: &PassThruMsg, timeout: Duration) -> Result<(), Error> {
+        self.write_before(msg, deadline_after(timeout))
+    }
+
+    /// Writes each message in `msgs`, leaving the gap between them. Returns the amount of messages
+    /// written.
+    ///
+    /// The timeout applies to the whole batch. If it expires after some messages have been
+    /// written, the amount written is returned. [`Error::Timeout`] is only returned if no messages
+    /// were written.
+    pub fn write_msgs(&mut self, msgs: &[PassThruMsg], timeout: Duration) -> Result<usize, Error> {
+        let deadline = deadline_after(timeout);
+        for (i, msg) in msgs.iter().enumerate() {
+            match self.write_before(msg, deadline) {
+                Ok(()) => {}
+                Err(Error::Timeout) if i > 0 => return Ok(i),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(msgs.len())
+    }
+
+    /// Returns the average amount of messages written per second, or `None` if fewer than two
+    /// messages have been written.
+    pub fn transmit_rate(&self) -> Option<f64> {
+        let (first, last) = (self.first_write?, self.last_write?);
+        let elapsed = last.duration_since(first).as_secs_f64();
+        if self.written < 2 || elapsed == 0.0 {
+            return None;
+        }
+        Some((self.written - 1) as f64 / elapsed)
+    }
+
+    fn write_before(&mut self, msg: &PassThruMsg, deadline: Instant) -> Result<(), Error> {
+        if let Some(last_write) = self.last_write {
+            let next = last_write + self.gap;
+            if next > deadline {
+                return Err(Error::Timeout);
+            }
+            let wait = next.saturating_duration_since(Instant::now());
+            if !wait.is_zero() {
+                thread::sleep(wait);
+            }
+        }
+
+        let start = Instant::now();
+        self.channel
+            .write_msg(msg, deadline.saturating_duration_since(start))?;
+        self.last_write = Some(start);
+        self.first_write.get_or_insert(start);
+        self.written += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn driver_time_counts_towards_the_gap() {
+        let channel = mock::can_channel();
+        let gap = Duration::from_millis(30);
+        let delay = Duration::from_millis(20);
+        mock::with_channel(channel.id(), |channel| channel.write_delay = delay);
+
+        let mut writer = PacedWriter::new(&channel, gap);
+        assert!(writer.transmit_rate().is_none());
+        let msgs: Vec<_> = (0..5u8)
+            .map(|i| PassThruMsg::new_can(0x100, &[i]))
+            .collect();
+        let start = Instant::now();
+        assert_eq!(writer.write_msgs(&msgs, Duration::from_secs(5)).unwrap(), 5);
+        let elapsed = start.elapsed();
+
+        // Four gaps and the last write. Adding the delay to every gap would take 220ms.
+        assert!(elapsed >= gap * 4 + delay, "{:?}", elapsed);
+        assert!(elapsed < (gap + delay) * 4, "{:?}", elapsed);
+        assert_eq!(mock::channel(channel.id()).written.len(), 5);
+
+        let rate = writer.transmit_rate().unwrap();
+        assert!(rate <= 1.0 / gap.as_secs_f64(), "{}", rate);
+        assert!(rate > 1.0 / (gap + delay).as_secs_f64(), "{}", rate);
+    }
+
+    #[test]
+    fn gap_longer_than_timeout() {
+        let channel = mock::can_channel();
+        let mut writer = PacedWriter::new(&channel, Duration::from_secs(10));
+        let msg = PassThruMsg::new_can(0x100, &[0]);
+        writer.write_msg(&msg, Duration::from_secs(1)).unwrap();
+        assert!(matches!(
+            writer.write_msg(&msg, Duration::from_millis(10)),
+            Err(Error::Timeout)
+        ));
+        // A batch that times out after the first message returns the amount written
+        writer.set_gap(Duration::ZERO);
+        assert_eq!(writer.write_msgs(&[msg, msg], Duration::ZERO).unwrap(), 1);
+        assert_eq!(mock::channel(channel.id()).written.len(), 2);
+    }
+}
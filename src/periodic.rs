@@ -0,0 +1,366 @@
+//! Guards for periodic messages
+
+use std::time::{Duration, Instant};
+
+use crate::{Channel, Error, IsoTpChannel, MessageId, PassThruMsg};
+
+/// The service and sub-function of a tester present request that suppresses the positive response
+const TESTER_PRESENT: [u8; 2] = [0x3E, 0x80];
+
+/// A periodic message that is stopped when dropped. Created with [`Channel::periodic_msg`].
+pub struct PeriodicMsg<'a> {
+    channel: &'a Channel<'a>,
+    id: MessageId,
+}
+
+/// A periodic message started through a channel. Returned by [`Channel::active_periodic_msgs`].
+#[derive(Debug)]
+pub struct PeriodicInfo {
+    pub id: MessageId,
+    pub interval: Duration,
+    /// A copy of the message that is transmitted
+    pub msg: PassThruMsg,
+    /// When the message was started. Updating or restoring the message after reconnecting keeps
+    /// the original time.
+    pub created: Instant,
+}
+
+impl<'a> Channel<'a> {
+    /// Returns the periodic messages started through this channel that haven't been stopped, in
+    /// the order they were started.
+    ///
+    /// J2534 has no way to query periodic messages, so this only reflects the crate's own
+    /// bookkeeping. Messages the driver stopped by itself, or that were started on the
+    /// channel by other means, aren't known to it.
+    pub fn active_periodic_msgs(&self) -> Vec<PeriodicInfo> {
+        self.tracked
+            .lock()
+            .unwrap()
+            .periodic
+            .iter()
+            .map(|periodic| PeriodicInfo {
+                id: periodic.handle,
+                interval: Duration::from_millis(periodic.interval.into()),
+                msg: periodic.msg,
+                created: periodic.created,
+            })
+            .collect()
+    }
+
+    /// Starts transmitting `msg` every `interval` until the returned guard is dropped.
+    /// See [`Channel::start_periodic_message`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, PassThruMsg, Protocol, TxFlags};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::ISO15765, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// let msg = PassThruMsg::new_isotp(0x7DF, &[0x3E, 0x80]).tx_flags(TxFlags::ISO15765_FRAME_PAD);
+    /// let tester_present = channel.periodic_msg(&msg, Duration::from_secs(2)).unwrap();
+    /// // ...
+    /// tester_present.stop().unwrap();
+    /// ```
+    pub fn periodic_msg(
+        &'a self,
+        msg: &PassThruMsg,
+        interval: Duration,
+    ) -> Result<PeriodicMsg<'a>, Error> {
+        let id = self.start_periodic_message(msg, interval)?;
+        Ok(PeriodicMsg { channel: self, id })
+    }
+
+    /// Starts transmitting a prebuilt tester present message every `interval` until the returned
+    /// guard is dropped. See [`IsoTpChannel::tester_present`] to have the message built.
+    pub fn tester_present(
+        &'a self,
+        msg: &PassThruMsg,
+        interval: Duration,
+    ) -> Result<TesterPresent<'a>, Error> {
+        let periodic = self.periodic_msg(msg, interval)?;
+        Ok(TesterPresent {
+            channel: self,
+            msg: *msg,
+            interval,
+            periodic: Some(periodic),
+        })
+    }
+}
+
+impl<'a> IsoTpChannel<'a> {
+    /// Starts sending a padded tester present request (`3E 80`, without a positive response) on
+    /// `tx_id` every `interval` until the returned guard is dropped.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let mut isotp = device.connect_iso15765(500000, ConnectFlags::NONE).unwrap();
+    /// isotp.add_pair(0x7E0, 0x7E8).unwrap();
+    ///
+    /// let mut tester_present = isotp.tester_present(0x7DF, Duration::from_secs(2)).unwrap();
+    /// // Keep tester present out of a multi-frame transfer
+    /// tester_present.pause().unwrap();
+    /// let mut request = vec![0x2E, 0xF1, 0x90];
+    /// request.extend_from_slice(b"1G1YY22G965104587");
+    /// isotp.send(0x7E0, &request, Duration::from_secs(1)).unwrap();
+    /// tester_present.resume().unwrap();
+    /// ```
+    pub fn tester_present(
+        &self,
+        tx_id: u32,
+        interval: Duration,
+    ) -> Result<TesterPresent<'_>, Error> {
+        let msg = PassThruMsg::new_isotp(tx_id, &TESTER_PRESENT).tx_flags(self.tx_flags());
+        self.channel().tester_present(&msg, interval)
+    }
+}
+
+/// A tester present message sent periodically until the guard is dropped. Created with
+/// [`IsoTpChannel::tester_present`] or [`Channel::tester_present`].
+pub struct TesterPresent<'a> {
+    channel: &'a Channel<'a>,
+    msg: PassThruMsg,
+    interval: Duration,
+    periodic: Option<PeriodicMsg<'a>>,
+}
+
+impl<'a> TesterPresent<'a> {
+    /// Stops sending the message until [`TesterPresent::resume`] is called. Does nothing if it is
+    /// already paused.
+    pub fn pause(&mut self) -> Result<(), Error> {
+        match self.periodic.take() {
+            Some(periodic) => periodic.stop(),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts sending the message again after [`TesterPresent::pause`]. Does nothing if it isn't
+    /// paused.
+    pub fn resume(&mut self) -> Result<(), Error> {
+        if self.periodic.is_none() {
+            self.periodic = Some(self.channel.periodic_msg(&self.msg, self.interval)?);
+        }
+        Ok(())
+    }
+
+    /// Returns true if the message isn't being sent
+    pub fn is_paused(&self) -> bool {
+        self.periodic.is_none()
+    }
+
+    /// Returns the handle of the periodic message, or `None` while paused. The handle changes
+    /// every time the message is resumed.
+    pub fn id(&self) -> Option<MessageId> {
+        self.periodic.as_ref().map(PeriodicMsg::id)
+    }
+
+    /// Returns the message that is sent
+    pub fn msg(&self) -> &PassThruMsg {
+        &self.msg
+    }
+}
+
+impl<'a> PeriodicMsg<'a> {
+    /// Returns the handle of the periodic message
+    pub fn id(&self) -> MessageId {
+        self.id
+    }
+
+    /// Returns the channel the message is transmitted on
+    pub fn channel(&self) -> &'a Channel<'a> {
+        self.channel
+    }
+
+    /// Replaces the message and/or the interval of the periodic message. The handle stays the same.
+    /// The interval is rounded up to whole milliseconds.
+    ///
+    /// J2534 can't change a periodic message, so a replacement is started. To avoid a gap in the
+    /// transmissions, the replacement is started before the old message is stopped. If the old
+    /// message can't be stopped, the replacement is stopped again and the error is returned, leaving
+    /// the old message running. If the device
+    /// can't start another periodic message ([`Error::ExceededLimit`]), the old message is stopped
+    /// first instead. If starting the replacement fails in that case, the periodic message is gone
+    /// and the guard no longer refers to a running message.
+    pub fn update(
+        &mut self,
+        new_msg: Option<&PassThruMsg>,
+        new_interval: Option<Duration>,
+    ) -> Result<(), Error> {
+        self.channel
+            .update_periodic_message(self.id, new_msg, new_interval)
+    }
+
+    /// Stops the periodic message. Unlike dropping the guard, this reports errors returned by the driver.
+    pub fn stop(self) -> Result<(), Error> {
+        let result = self.channel.stop_periodic_message(self.id);
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl<'a> Drop for PeriodicMsg<'a> {
+    fn drop(&mut self) {
+        // Fails if the message was already cleared with `Channel::clear_periodic_messages`
+        let _ = self.channel.stop_periodic_message(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, ConnectFlags};
+
+    #[test]
+    fn guard_stops_message() {
+        let channel = mock::can_channel();
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x3E, 0x80]);
+
+        let periodic = channel
+            .periodic_msg(&msg, Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(mock::channel(channel.id()).periodic.len(), 1);
+        assert_eq!(channel.active_periodic_msgs()[0].id, periodic.id());
+        drop(periodic);
+        assert!(mock::channel(channel.id()).periodic.is_empty());
+        assert!(channel.active_periodic_msgs().is_empty());
+
+        let periodic = channel
+            .periodic_msg(&msg, Duration::from_millis(100))
+            .unwrap();
+        periodic.stop().unwrap();
+        assert!(mock::channel(channel.id()).periodic.is_empty());
+    }
+
+    #[test]
+    fn interval_range() {
+        let channel = mock::can_channel();
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x3E, 0x80]);
+
+        for interval in [0, 4, 65536] {
+            assert!(matches!(
+                channel.start_periodic_message_raw(&msg, interval),
+                Err(Error::InvalidTimeInterval)
+            ));
+        }
+        assert!(mock::channel(channel.id()).periodic.is_empty());
+        channel.start_periodic_message_raw(&msg, 5).unwrap();
+        channel.start_periodic_message_raw(&msg, 65535).unwrap();
+        assert_eq!(mock::channel(channel.id()).periodic.len(), 2);
+    }
+
+    #[test]
+    fn tester_present_pause() {
+        let device = mock::open();
+        let mut isotp = device
+            .connect_iso15765(500000, ConnectFlags::empty())
+            .unwrap();
+        isotp.add_pair(0x7E0, 0x7E8).unwrap();
+        let id = isotp.channel().id();
+
+        let mut tester_present = isotp.tester_present(0x7DF, Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            tester_present.msg().isotp_message(),
+            Some((0x7DF, &TESTER_PRESENT[..]))
+        );
+        tester_present.pause().unwrap();
+        assert!(tester_present.is_paused());
+        assert!(mock::channel(id).periodic.is_empty());
+        tester_present.resume().unwrap();
+        assert_eq!(mock::channel(id).periodic.len(), 1);
+        drop(tester_present);
+        assert!(mock::channel(id).periodic.is_empty());
+    }
+
+    #[test]
+    fn update_keeps_handle() {
+        let channel = mock::can_channel();
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x3E, 0x80]);
+        let mut periodic = channel
+            .periodic_msg(&msg, Duration::from_millis(100))
+            .unwrap();
+        let id = periodic.id();
+
+        periodic
+            .update(None, Some(Duration::from_micros(250500)))
+            .unwrap();
+        let running = mock::channel(channel.id()).periodic_msgs;
+        assert_eq!(running.len(), 1);
+        assert_eq!(running.values().next().unwrap().1, 251);
+
+        let new_msg = PassThruMsg::new_can(0x7E0, &[0x02, 0x3E, 0x00]);
+        periodic.update(Some(&new_msg), None).unwrap();
+        let running = mock::channel(channel.id()).periodic_msgs;
+        let (sent, interval) = running.values().next().unwrap();
+        assert_eq!(sent.can_message(), new_msg.can_message());
+        assert_eq!(*interval, 251);
+        assert_eq!(periodic.id(), id);
+        assert_eq!(channel.active_periodic_msgs()[0].interval.as_millis(), 251);
+
+        assert!(matches!(
+            periodic.update(None, Some(Duration::from_secs(100))),
+            Err(Error::InvalidTimeInterval)
+        ));
+    }
+
+    #[test]
+    fn update_keeps_old_message_if_stop_fails() {
+        let channel = mock::can_channel();
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x3E, 0x80]);
+        let mut periodic = channel
+            .periodic_msg(&msg, Duration::from_millis(100))
+            .unwrap();
+        let old = mock::channel(channel.id()).periodic[0];
+        mock::with_channel(channel.id(), |channel| channel.stuck_periodic.push(old));
+
+        let new_msg = PassThruMsg::new_can(0x7E0, &[0x02, 0x3E, 0x00]);
+        let err = periodic
+            .update(Some(&new_msg), Some(Duration::from_millis(50)))
+            .unwrap_err();
+        assert!(matches!(err.inner(), Error::Failed));
+        // Only the old message is running, and it's still tracked
+        let running = mock::channel(channel.id()).periodic_msgs;
+        assert_eq!(running.keys().copied().collect::<Vec<_>>(), [old]);
+        let active = channel.active_periodic_msgs();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].msg.can_message(), msg.can_message());
+        assert_eq!(active[0].interval, Duration::from_millis(100));
+
+        mock::with_channel(channel.id(), |channel| channel.stuck_periodic.clear());
+        periodic.update(Some(&new_msg), None).unwrap();
+        let running = mock::channel(channel.id()).periodic_msgs;
+        assert_eq!(running.len(), 1);
+        assert!(!running.contains_key(&old));
+    }
+
+    #[test]
+    fn update_at_limit() {
+        let channel = mock::can_channel();
+        let msg = PassThruMsg::new_can(0x7DF, &[0x02, 0x3E, 0x80]);
+        let mut periodic = channel
+            .periodic_msg(&msg, Duration::from_millis(100))
+            .unwrap();
+        mock::with_channel(channel.id(), |channel| channel.periodic_limit = Some(1));
+
+        // The old message is stopped before the replacement is started
+        periodic
+            .update(None, Some(Duration::from_millis(50)))
+            .unwrap();
+        let running = mock::channel(channel.id()).periodic_msgs;
+        assert_eq!(running.len(), 1);
+        assert_eq!(running.values().next().unwrap().1, 50);
+
+        mock::with_channel(channel.id(), |channel| channel.periodic_limit = Some(0));
+        assert!(matches!(
+            periodic.update(None, Some(Duration::from_millis(20))),
+            Err(Error::ExceededLimit)
+        ));
+        assert!(channel.active_periodic_msgs().is_empty());
+    }
+}
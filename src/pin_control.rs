@@ -0,0 +1,215 @@
+//! Programming voltage sequences with [`PinControl`]
+
+use crate::{Device, Error, Pin, ProgrammingVoltage};
+
+/// The pins the crate has applied a programming voltage to, so that only one is energized at a
+/// time as J2534-1 requires
+#[derive(Default)]
+pub(crate) struct EnergizedPins {
+    /// The pin with a voltage applied
+    voltage: Option<Pin>,
+    /// Whether pin 15 is shorted to ground, which is allowed alongside a voltage on another pin
+    grounded: bool,
+}
+
+impl EnergizedPins {
+    /// Returns [`Error::InvalidArgument`] if applying `voltage` to `pin` would energize a second pin
+    pub(crate) fn check(&self, pin: Pin, voltage: ProgrammingVoltage) -> Result<(), Error> {
+        match (voltage, self.voltage) {
+            (ProgrammingVoltage::Millivolts(_), Some(energized)) if energized != pin => {
+                Err(Error::InvalidArgument(format!(
+                    "{:?} still has a programming voltage applied, turn it off before energizing {:?}",
+                    energized, pin
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records that `voltage` was applied to `pin`
+    pub(crate) fn record(&mut self, pin: Pin, voltage: ProgrammingVoltage) {
+        match voltage {
+            ProgrammingVoltage::Millivolts(_) => self.voltage = Some(pin),
+            ProgrammingVoltage::ShortToGround => self.grounded = true,
+            ProgrammingVoltage::Off => {
+                if pin == Pin::Pin15 {
+                    self.grounded = false;
+                }
+                if self.voltage == Some(pin) {
+                    self.voltage = None;
+                }
+            }
+        }
+    }
+}
+
+/// Controls the programming voltage of one pin. Created with [`Device::pin`].
+///
+/// # Example
+/// ```no_run
+/// use j2534::{Interface, Pin};
+/// use std::time::Duration;
+///
+/// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+/// let device = interface.open_any().unwrap();
+///
+/// {
+///     // Both pins are turned off at the end of the block, even if the sequence fails
+///     let _ground = device.pin(Pin::Pin15).short_to_ground_guarded().unwrap();
+///     std::thread::sleep(Duration::from_millis(500));
+///     let _vpp = device.pin(Pin::Pin13).apply_mv_guarded(18000).unwrap();
+///     // Enter the bootloader
+/// }
+/// ```
+pub struct PinControl<'d, 'a> {
+    device: &'d Device<'a>,
+    pin: Pin,
+}
+
+impl<'a> Device<'a> {
+    /// Returns a handle to the programming voltage of `pin`
+    pub fn pin(&self, pin: Pin) -> PinControl<'_, 'a> {
+        PinControl { device: self, pin }
+    }
+}
+
+impl<'d, 'a> PinControl<'d, 'a> {
+    /// Returns the pin that is controlled
+    pub fn pin(&self) -> Pin {
+        self.pin
+    }
+
+    /// Applies `millivolts` to the pin. See [`Device::set_programming_voltage`].
+    pub fn apply_mv(&self, millivolts: u32) -> Result<(), Error> {
+        self.set(ProgrammingVoltage::Millivolts(millivolts))
+    }
+
+    /// Shorts the pin to ground. Only valid on [`Pin::Pin15`].
+    pub fn short_to_ground(&self) -> Result<(), Error> {
+        self.set(ProgrammingVoltage::ShortToGround)
+    }
+
+    /// Turns the output of the pin off
+    pub fn release(&self) -> Result<(), Error> {
+        self.set(ProgrammingVoltage::Off)
+    }
+
+    /// Like [`PinControl::apply_mv`], but returns a guard that turns the pin off when dropped
+    pub fn apply_mv_guarded(self, millivolts: u32) -> Result<PinGuard<'d, 'a>, Error> {
+        self.apply_mv(millivolts)?;
+        Ok(PinGuard { control: self })
+    }
+
+    /// Like [`PinControl::short_to_ground`], but returns a guard that turns the pin off when
+    /// dropped
+    pub fn short_to_ground_guarded(self) -> Result<PinGuard<'d, 'a>, Error> {
+        self.short_to_ground()?;
+        Ok(PinGuard { control: self })
+    }
+
+    fn set(&self, voltage: ProgrammingVoltage) -> Result<(), Error> {
+        self.device.set_programming_voltage(self.pin, voltage)
+    }
+}
+
+/// A pin with a programming voltage applied or shorted to ground. The pin is turned off when
+/// the guard is dropped, including while unwinding from a panic.
+#[must_use]
+pub struct PinGuard<'d, 'a> {
+    control: PinControl<'d, 'a>,
+}
+
+impl<'d, 'a> PinGuard<'d, 'a> {
+    /// Returns the pin that is energized
+    pub fn pin(&self) -> Pin {
+        self.control.pin
+    }
+
+    /// Turns the pin off. Unlike dropping the guard, this reports errors returned by the driver.
+    pub fn release(self) -> Result<(), Error> {
+        let result = self.control.release();
+        std::mem::forget(self);
+        result
+    }
+}
+
+impl<'d, 'a> Drop for PinGuard<'d, 'a> {
+    fn drop(&mut self) {
+        let _ = self.control.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock, SHORT_TO_GROUND, VOLTAGE_OFF};
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn voltages(device: &Device) -> Vec<(u32, u32)> {
+        mock::device(device.id()).voltages
+    }
+
+    #[test]
+    fn one_pin_at_a_time() {
+        let device = mock::open();
+        device.pin(Pin::Pin13).apply_mv(18000).unwrap();
+        // Pin 15 can be grounded alongside, and the energized pin can be changed
+        device.pin(Pin::Pin15).short_to_ground().unwrap();
+        device.pin(Pin::Pin13).apply_mv(12000).unwrap();
+
+        match device.pin(Pin::Pin12).apply_mv(18000) {
+            Err(Error::InvalidArgument(_)) => {}
+            other => panic!("expected InvalidArgument, got {:?}", other),
+        }
+        // The second pin never reached the driver
+        assert_eq!(
+            voltages(device),
+            [(13, 18000), (15, SHORT_TO_GROUND), (13, 12000)]
+        );
+
+        device.pin(Pin::Pin13).release().unwrap();
+        device.pin(Pin::Pin12).apply_mv(18000).unwrap();
+        assert_eq!(voltages(device).last(), Some(&(12, 18000)));
+    }
+
+    #[test]
+    fn guards_turn_pins_off() {
+        let device = mock::open();
+        {
+            let _ground = device.pin(Pin::Pin15).short_to_ground_guarded().unwrap();
+            let vpp = device.pin(Pin::Pin13).apply_mv_guarded(18000).unwrap();
+            assert_eq!(vpp.pin(), Pin::Pin13);
+        }
+        // Dropped in reverse order
+        assert_eq!(
+            voltages(device),
+            [
+                (15, SHORT_TO_GROUND),
+                (13, 18000),
+                (13, VOLTAGE_OFF),
+                (15, VOLTAGE_OFF)
+            ]
+        );
+
+        // Pin 13 was recorded as off, so another pin can be energized
+        device
+            .pin(Pin::Pin12)
+            .apply_mv_guarded(18000)
+            .unwrap()
+            .release()
+            .unwrap();
+        assert_eq!(voltages(device)[4..], [(12, 18000), (12, VOLTAGE_OFF)]);
+    }
+
+    #[test]
+    fn guards_turn_pins_off_on_panic() {
+        let device = mock::open();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _vpp = device.pin(Pin::Pin13).apply_mv_guarded(18000).unwrap();
+            panic!("sequence failed");
+        }));
+        assert!(result.is_err());
+        assert_eq!(voltages(device), [(13, 18000), (13, VOLTAGE_OFF)]);
+        device.pin(Pin::Pin12).apply_mv(18000).unwrap();
+    }
+}
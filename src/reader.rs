@@ -0,0 +1,241 @@
+//! Background receive threads created by [`Channel::spawn_reader`]
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, Scope, ScopedJoinHandle};
+use std::time::Duration;
+
+use crate::{Channel, Error, PassThruMsg};
+
+/// The amount of errors [`ReaderHandle::errors`] holds before further errors are dropped
+const ERROR_CAPACITY: usize = 16;
+
+/// What a background reader does when the message receiver is full
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait until the receiver has room. Messages may be lost in the device's receive queue instead.
+    Block,
+    /// Drop the message. Dropped messages are counted by [`ReaderHandle::dropped`].
+    Drop,
+}
+
+/// Configuration for [`Channel::spawn_reader`]
+#[derive(Copy, Clone, Debug)]
+pub struct ReaderConfig {
+    /// The maximum amount of messages to read per call to `PassThruReadMsgs`
+    pub batch_size: usize,
+    /// The amount of time to wait for each batch of messages
+    pub timeout: Duration,
+    /// The amount of messages the receiver can hold
+    pub capacity: usize,
+    /// What to do with messages when the receiver is full
+    pub overflow: Overflow,
+}
+
+impl Default for ReaderConfig {
+    fn default() -> ReaderConfig {
+        ReaderConfig {
+            batch_size: 16,
+            timeout: Duration::from_millis(100),
+            capacity: 1024,
+            overflow: Overflow::Block,
+        }
+    }
+}
+
+/// A handle to a background reader created by [`Channel::spawn_reader`]. The reader is stopped
+/// when the handle is dropped.
+pub struct ReaderHandle<'scope> {
+    stop: Arc<AtomicBool>,
+    dropped: Arc<AtomicUsize>,
+    errors: Receiver<Error>,
+    thread: Option<ScopedJoinHandle<'scope, ()>>,
+}
+
+impl<'scope> ReaderHandle<'scope> {
+    /// Returns errors reported by the driver while reading. The reader keeps running after
+    /// an error, unless the channel or device is no longer usable, but waits longer before
+    /// each retry while the errors continue. Errors are dropped while 16 are waiting to be
+    /// received.
+    pub fn errors(&self) -> &Receiver<Error> {
+        &self.errors
+    }
+
+    /// Returns the amount of messages dropped because the receiver was full
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the reader is still running
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stops the reader and waits for it to disconnect the channel
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<'scope> Drop for ReaderHandle<'scope> {
+    fn drop(&mut self) {
+        // The scope joins the thread
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<'a> Channel<'a> {
+    /// Starts a thread in `scope` that reads messages from the channel in a loop and sends them to
+    /// the returned receiver. The channel is disconnected when the reader stops.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol, ReaderConfig};
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// std::thread::scope(|s| {
+    ///     let (messages, reader) = channel.spawn_reader(s, ReaderConfig::default());
+    ///     for msg in messages.iter().take(100) {
+    ///         println!("{:?}", msg);
+    ///     }
+    ///     reader.stop();
+    /// });
+    /// ```
+    pub fn spawn_reader<'scope, 'env>(
+        self,
+        scope: &'scope Scope<'scope, 'env>,
+        config: ReaderConfig,
+    ) -> (Receiver<PassThruMsg>, ReaderHandle<'scope>)
+    where
+        'a: 'scope,
+    {
+        let (tx, rx) = mpsc::sync_channel(config.capacity);
+        let (errors_tx, errors) = mpsc::sync_channel(ERROR_CAPACITY);
+        let stop = Arc::new(AtomicBool::new(false));
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        let thread = {
+            let stop = stop.clone();
+            let dropped = dropped.clone();
+            scope.spawn(move || run_reader(&self, config, &tx, &errors_tx, &stop, &dropped))
+        };
+
+        (
+            rx,
+            ReaderHandle {
+                stop,
+                dropped,
+                errors,
+                thread: Some(thread),
+            },
+        )
+    }
+}
+
+fn run_reader(
+    channel: &Channel,
+    config: ReaderConfig,
+    tx: &SyncSender<PassThruMsg>,
+    errors: &SyncSender<Error>,
+    stop: &AtomicBool,
+    dropped: &AtomicUsize,
+) {
+    let mut buf = vec![channel.new_msg(); config.batch_size.max(1)];
+    let max_backoff = config.timeout.max(Duration::from_millis(1));
+    let mut backoff = Duration::ZERO;
+    while !stop.load(Ordering::Relaxed) {
+        let count = match channel.read(&mut buf, config.timeout) {
+            Ok(count) => count,
+            Err(Error::Timeout) | Err(Error::BufferEmpty) => continue,
+            Err(err) => {
+                let fatal = err.is_disconnect() || matches!(err.inner(), Error::InvalidChannelId);
+                // Errors nobody receives are dropped instead of piling up
+                let _ = errors.try_send(err);
+                if fatal {
+                    return;
+                }
+                // Don't call a failing driver in a tight loop
+                backoff = (backoff * 2).clamp(Duration::from_millis(1), max_backoff);
+                thread::sleep(backoff);
+                continue;
+            }
+        };
+        backoff = Duration::ZERO;
+
+        for msg in &buf[..count] {
+            let mut msg = *msg;
+            loop {
+                match tx.try_send(msg) {
+                    Ok(()) => break,
+                    Err(TrySendError::Full(_)) if config.overflow == Overflow::Drop => {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                        break;
+                    }
+                    Err(TrySendError::Full(m)) => {
+                        // Poll so that the reader can still be stopped while blocked
+                        if stop.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        msg = m;
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    // The receiver was dropped
+                    Err(TrySendError::Disconnected(_)) => return,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn errors_back_off() {
+        let channel = mock::can_channel();
+        let id = channel.id();
+        mock::with_channel(id, |channel| channel.read_error = Some(0x12));
+
+        let config = ReaderConfig {
+            timeout: Duration::from_millis(10),
+            ..ReaderConfig::default()
+        };
+        thread::scope(|s| {
+            let (_messages, reader) = channel.spawn_reader(s, config);
+            thread::sleep(Duration::from_millis(200));
+            assert!(reader.is_running());
+            let errors = reader.errors().try_iter().count();
+            assert!((1..=ERROR_CAPACITY).contains(&errors));
+            reader.stop();
+        });
+        // Without a delay the driver would be called thousands of times
+        assert!(mock::channel(id).reads < 50);
+    }
+
+    #[test]
+    fn stops_when_disconnected() {
+        let channel = mock::can_channel();
+        mock::with_channel(channel.id(), |channel| channel.read_error = Some(0x08));
+
+        thread::scope(|s| {
+            let (_messages, reader) = channel.spawn_reader(s, ReaderConfig::default());
+            let err = reader
+                .errors()
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap();
+            assert!(err.is_disconnect());
+            thread::sleep(Duration::from_millis(50));
+            assert!(!reader.is_running());
+        });
+    }
+}
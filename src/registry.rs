@@ -0,0 +1,178 @@
+//! Bookkeeping of the channels connected through a device
+
+use num_traits::FromPrimitive;
+
+use crate::{ConnectFlags, Device, Error, Protocol};
+
+/// A channel connected through a device. Returned by [`Device::open_channels`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OpenChannel {
+    /// The channel ID assigned by the driver
+    pub id: u32,
+    pub protocol_id: u32,
+    /// The protocol, or `None` if the protocol ID isn't a standard one
+    pub protocol: Option<Protocol>,
+    pub flags: ConnectFlags,
+    pub baudrate: u32,
+}
+
+impl OpenChannel {
+    pub(crate) fn new(
+        id: u32,
+        protocol_id: u32,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) -> OpenChannel {
+        OpenChannel {
+            id,
+            protocol_id,
+            protocol: Protocol::from_u32(protocol_id),
+            flags,
+            baudrate,
+        }
+    }
+}
+
+/// Returns the bus a protocol runs on, as the protocol ID of the J2534-1 protocol that stands
+/// for it. Protocols that aren't standard are only on the same bus as themselves.
+fn bus(protocol_id: u32) -> u32 {
+    match Protocol::from_u32(protocol_id).map(Protocol::base) {
+        Some(Protocol::ISO15765) => Protocol::CAN as u32,
+        Some(Protocol::ISO14230) => Protocol::ISO9141 as u32,
+        Some(protocol) => protocol as u32,
+        None => protocol_id,
+    }
+}
+
+/// Returns [`Error::AlreadyConnected`] if one of `channels` is on the bus of `protocol_id`
+pub(crate) fn check_bus_free(channels: &[OpenChannel], protocol_id: u32) -> Result<(), Error> {
+    match channels
+        .iter()
+        .find(|channel| bus(channel.protocol_id) == bus(protocol_id))
+    {
+        Some(channel) => Err(Error::AlreadyConnected {
+            protocol_id,
+            channel_id: channel.id,
+        }),
+        None => Ok(()),
+    }
+}
+
+impl<'a> Device<'a> {
+    /// Returns the channels connected through this device that haven't been disconnected, in the
+    /// order they were connected.
+    ///
+    /// Channels converted with [`Channel::into_raw`](crate::Channel::into_raw) or connected
+    /// outside of this crate aren't listed.
+    pub fn open_channels(&self) -> Vec<OpenChannel> {
+        self.channels.lock().unwrap().clone()
+    }
+
+    pub(crate) fn register_channel(
+        &self,
+        id: u32,
+        protocol_id: u32,
+        flags: ConnectFlags,
+        baudrate: u32,
+    ) {
+        self.channels
+            .lock()
+            .unwrap()
+            .push(OpenChannel::new(id, protocol_id, flags, baudrate));
+    }
+
+    pub(crate) fn unregister_channel(&self, id: u32) {
+        self.channels
+            .lock()
+            .unwrap()
+            .retain(|channel| channel.id != id);
+    }
+
+    /// Records a baud rate set with `DATA_RATE`
+    pub(crate) fn update_channel_baudrate(&self, id: u32, baudrate: u32) {
+        if let Some(channel) = self
+            .channels
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .find(|channel| channel.id == id)
+        {
+            channel.baudrate = baudrate;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    #[test]
+    fn same_bus_conflicts() {
+        let device = mock::open();
+        let can = device
+            .connect(Protocol::ISO15765, ConnectFlags::empty(), 500000)
+            .unwrap();
+        for protocol in [Protocol::ISO15765, Protocol::CAN, Protocol::CAN_PS] {
+            match device.connect(protocol, ConnectFlags::empty(), 500000) {
+                Err(Error::AlreadyConnected {
+                    protocol_id,
+                    channel_id,
+                }) => {
+                    assert_eq!(protocol_id, protocol as u32);
+                    assert_eq!(channel_id, can.id());
+                }
+                other => panic!("{:?} connected: {:?}", protocol, other.map(|c| c.id())),
+            }
+        }
+
+        let kline = device
+            .connect(Protocol::ISO14230, ConnectFlags::empty(), 10400)
+            .unwrap();
+        assert!(device
+            .connect(Protocol::ISO9141, ConnectFlags::empty(), 10400)
+            .is_err());
+        let sw_can = device
+            .connect(Protocol::SW_CAN_PS, ConnectFlags::empty(), 33333)
+            .unwrap();
+        assert_eq!(
+            device
+                .open_channels()
+                .iter()
+                .map(|channel| channel.id)
+                .collect::<Vec<_>>(),
+            [can.id(), kline.id(), sw_can.id()]
+        );
+
+        // connect_raw leaves it to the driver
+        let raw = device
+            .connect_raw(Protocol::CAN as u32, ConnectFlags::empty(), 500000)
+            .unwrap();
+        drop(raw);
+        drop(can);
+        let can = device
+            .connect(Protocol::CAN_PS, ConnectFlags::empty(), 500000)
+            .unwrap();
+        assert_eq!(device.open_channels().len(), 3);
+        assert!(device
+            .open_channels()
+            .iter()
+            .any(|channel| channel.id == can.id() && channel.protocol == Some(Protocol::CAN_PS)));
+    }
+
+    #[test]
+    fn concurrent_connects() {
+        let device = mock::open();
+        let results: Vec<_> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| s.spawn(|| device.connect(Protocol::CAN, ConnectFlags::empty(), 500000)))
+                .collect();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect()
+        });
+        let connected = results.iter().filter(|result| result.is_ok()).count();
+        assert_eq!(connected, 1);
+    }
+}
@@ -0,0 +1,210 @@
+//! Device discovery with the J2534 v05.00 scanning API
+
+use std::ffi::CString;
+
+use crate::{Error, Interface};
+
+/// The availability of a device is unknown
+pub const DEVICE_STATE_UNKNOWN: u32 = 0x00;
+/// The device can be opened
+pub const DEVICE_AVAILABLE: u32 = 0x01;
+/// The device is opened by another application
+pub const DEVICE_IN_USE: u32 = 0x02;
+
+/// The compatibility of the library and the firmware is unknown
+pub const DEVICE_DLL_FW_COMPATIBILITY_UNKNOWN: u32 = 0x00;
+/// The library and the firmware of the device are compatible
+pub const DEVICE_DLL_FW_COMPATIBLE: u32 = 0x01;
+/// The library or the firmware needs to be updated
+pub const DEVICE_DLL_OR_FW_NOT_COMPATIBLE: u32 = 0x02;
+/// The library needs to be updated
+pub const DEVICE_DLL_NOT_COMPATIBLE: u32 = 0x03;
+/// The firmware needs to be updated
+pub const DEVICE_FW_NOT_COMPATIBLE: u32 = 0x04;
+
+/// The `SDEVICE` structure filled by `PassThruGetNextDevice`
+#[repr(C)]
+pub struct SDeviceRaw {
+    pub device_name: [u8; 80],
+    pub device_available: u32,
+    pub device_dll_fw_status: u32,
+    pub device_connect_media: u32,
+    pub device_connect_speed: u32,
+    pub device_signal_quality: u32,
+    pub device_signal_strength: u32,
+}
+
+// Layout of the J2534-1 v05.00 header
+const _: () = assert!(std::mem::size_of::<SDeviceRaw>() == 80 + 6 * 4);
+const _: () = assert!(std::mem::align_of::<SDeviceRaw>() == 4);
+
+/// A device found by [`Interface::scan_devices`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SDevice {
+    /// The name as reported by the driver, usually in the ANSI code page. Pass it to
+    /// [`Interface::open`] to open the device.
+    pub raw_name: CString,
+    /// The name converted to UTF-8 for display, with invalid sequences replaced
+    pub name: String,
+    /// [`DEVICE_AVAILABLE`], [`DEVICE_IN_USE`] or [`DEVICE_STATE_UNKNOWN`]
+    pub available: u32,
+    /// Whether the library and the firmware are compatible, like [`DEVICE_DLL_FW_COMPATIBLE`]
+    pub dll_fw_status: u32,
+    /// How the device is connected, defined by the vendor
+    pub connect_media: u32,
+    /// The speed of the connection to the device in bits per second
+    pub connect_speed: u32,
+    /// The quality of a wireless connection in percent
+    pub signal_quality: u32,
+    /// The strength of a wireless connection in percent
+    pub signal_strength: u32,
+}
+
+impl SDevice {
+    /// Returns true if the device can be opened
+    pub fn is_available(&self) -> bool {
+        self.available == DEVICE_AVAILABLE
+    }
+
+    /// Returns true if the library and the firmware are known to be compatible
+    pub fn is_compatible(&self) -> bool {
+        self.dll_fw_status == DEVICE_DLL_FW_COMPATIBLE
+    }
+
+    fn from_raw(raw: &SDeviceRaw) -> SDevice {
+        // Some drivers fill the whole buffer without a terminator
+        let len = raw
+            .device_name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(raw.device_name.len());
+        let raw_name = &raw.device_name[..len];
+        SDevice {
+            // The name ends at the first nul
+            raw_name: CString::new(raw_name).unwrap(),
+            name: String::from_utf8_lossy(raw_name).into_owned(),
+            available: raw.device_available,
+            dll_fw_status: raw.device_dll_fw_status,
+            connect_media: raw.device_connect_media,
+            connect_speed: raw.device_connect_speed,
+            signal_quality: raw.device_signal_quality,
+            signal_strength: raw.device_signal_strength,
+        }
+    }
+}
+
+impl Interface {
+    /// Returns the devices the driver can connect to, using `PassThruScanForDevices` and
+    /// `PassThruGetNextDevice` from J2534-1 v05.00.
+    ///
+    /// Returns [`Error::NotSupported`] if the driver doesn't export the scanning functions, like
+    /// drivers that implement v04.04 only.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// for found in interface.scan_devices().unwrap() {
+    ///     if found.is_available() {
+    ///         let device = interface.open(found.raw_name.clone()).unwrap();
+    ///         println!("{}: {:?}", found.name, device.read_version());
+    ///     }
+    /// }
+    /// ```
+    pub fn scan_devices(&self) -> Result<Vec<SDevice>, Error> {
+        let (scan, next) = match (
+            self.c_pass_thru_scan_for_devices,
+            self.c_pass_thru_get_next_device,
+        ) {
+            (Some(scan), Some(next)) => (scan, next),
+            _ => return Err(Error::NotSupported),
+        };
+
+        let mut count: u32 = 0;
+        let res = self.call(|| unsafe { scan(&mut count as *mut u32) });
+        if res != 0 {
+            return Err(self.error(res));
+        }
+
+        let mut devices = Vec::new();
+        for _ in 0..count {
+            let mut raw = SDeviceRaw {
+                device_name: [0; 80],
+                device_available: 0,
+                device_dll_fw_status: 0,
+                device_connect_media: 0,
+                device_connect_speed: 0,
+                device_signal_quality: 0,
+                device_signal_strength: 0,
+            };
+            let res = self.call(|| unsafe { next(&mut raw as *mut SDeviceRaw) });
+            if res != 0 {
+                match self.error(res) {
+                    // Devices may disappear between the scan and reading them
+                    Error::BufferEmpty => break,
+                    err => return Err(err),
+                }
+            }
+            devices.push(SDevice::from_raw(&raw));
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(name: &[u8]) -> SDeviceRaw {
+        let mut device_name = [0; 80];
+        device_name[..name.len()].copy_from_slice(name);
+        SDeviceRaw {
+            device_name,
+            device_available: DEVICE_AVAILABLE,
+            device_dll_fw_status: DEVICE_DLL_FW_COMPATIBLE,
+            device_connect_media: 1,
+            device_connect_speed: 480_000_000,
+            device_signal_quality: 0,
+            device_signal_strength: 0,
+        }
+    }
+
+    #[test]
+    fn sdevice_layout() {
+        assert_eq!(std::mem::offset_of!(SDeviceRaw, device_available), 80);
+        assert_eq!(
+            std::mem::offset_of!(SDeviceRaw, device_signal_strength),
+            100
+        );
+    }
+
+    #[test]
+    fn sdevice_names() {
+        let device = SDevice::from_raw(&raw(b"CarDAQ-Plus 3 1234"));
+        assert_eq!(device.raw_name.as_bytes(), b"CarDAQ-Plus 3 1234");
+        assert_eq!(device.name, "CarDAQ-Plus 3 1234");
+        assert!(device.is_available());
+        assert!(device.is_compatible());
+
+        // "Gerät" in Windows-1252 keeps its bytes for opening the device
+        let device = SDevice::from_raw(&raw(b"Ger\xE4t"));
+        assert_eq!(device.raw_name.as_bytes(), b"Ger\xE4t");
+        assert_eq!(device.name, "Ger\u{FFFD}t");
+
+        // A name filling the whole buffer without a terminator
+        let device = SDevice::from_raw(&raw(&[b'A'; 80]));
+        assert_eq!(device.raw_name.as_bytes().len(), 80);
+    }
+
+    #[test]
+    fn open_raw_name() {
+        let interface = crate::mock::interface();
+        let found = SDevice::from_raw(&raw(b"Ger\xE4t"));
+        let device = interface.open(found.raw_name.clone()).unwrap();
+        assert_eq!(
+            crate::mock::device(device.id()).name.as_deref(),
+            Some(&b"Ger\xE4t"[..])
+        );
+    }
+}
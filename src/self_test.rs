@@ -0,0 +1,363 @@
+//! Loopback smoke test of a device with [`Device::self_test`]
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::{
+    is_can_protocol, ConnectFlags, Device, Error, PassThruMsg, Protocol, TxFlags, VersionInfo,
+};
+
+/// The number of frames transmitted by [`Device::self_test`]
+const SELF_TEST_FRAMES: usize = 5;
+
+/// How long [`Device::self_test`] waits for the echo of each frame
+const ECHO_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The functional CAN ID test frames are sent on
+const TEST_CAN_ID: u32 = 0x7DF;
+
+/// The CAN ID responses would be received on, used for the flow control filter on ISO 15765
+const TEST_RESPONSE_ID: u32 = 0x7E8;
+
+/// The step of [`Device::self_test`] that failed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SelfTestStage {
+    /// Connecting the channel
+    Connect,
+    /// Enabling loopback
+    Config,
+    /// Installing the filter
+    Filter,
+    /// Writing a frame
+    Write,
+    /// Receiving the echo of a frame
+    Echo,
+}
+
+/// Why [`Device::self_test`] failed
+#[derive(Debug)]
+pub struct SelfTestFailure {
+    pub stage: SelfTestStage,
+    /// The error returned by the driver, if the failure was caused by one
+    pub error: Option<Error>,
+    /// A description of what went wrong
+    pub detail: String,
+}
+
+/// The results of [`Device::self_test`]. Displayed as a summary that can be attached to a bug
+/// report.
+#[derive(Debug)]
+pub struct SelfTestReport {
+    pub protocol: Protocol,
+    pub baudrate: u32,
+    /// The versions of the firmware, library and API
+    pub version: Result<VersionInfo, Error>,
+    /// The number of frames written to the device
+    pub frames_sent: usize,
+    /// The number of frames whose echo was received
+    pub frames_echoed: usize,
+    /// The time from writing each echoed frame until its echo was received
+    pub latencies: Vec<Duration>,
+    /// The first failure, or `None` if every frame was echoed
+    pub failure: Option<SelfTestFailure>,
+}
+
+impl SelfTestReport {
+    /// Returns true if every frame was echoed
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+
+    /// Returns the shortest round-trip latency
+    pub fn min_latency(&self) -> Option<Duration> {
+        self.latencies.iter().min().copied()
+    }
+
+    /// Returns the longest round-trip latency
+    pub fn max_latency(&self) -> Option<Duration> {
+        self.latencies.iter().max().copied()
+    }
+
+    /// Returns the mean round-trip latency
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let total: Duration = self.latencies.iter().sum();
+        Some(total / self.latencies.len() as u32)
+    }
+
+    fn fail(&mut self, stage: SelfTestStage, error: Option<Error>, detail: String) {
+        self.failure = Some(SelfTestFailure {
+            stage,
+            error,
+            detail,
+        });
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "protocol: {:?} at {} baud", self.protocol, self.baudrate)?;
+        match &self.version {
+            Ok(version) => {
+                writeln!(f, "firmware version: {:?}", version.firmware_version)?;
+                writeln!(f, "library version: {:?}", version.dll_version)?;
+                writeln!(f, "api version: {:?}", version.api_version)?;
+            }
+            Err(err) => writeln!(f, "version: {}", err)?,
+        }
+        writeln!(
+            f,
+            "frames: {} sent, {} echoed",
+            self.frames_sent, self.frames_echoed
+        )?;
+        if let (Some(min), Some(mean), Some(max)) =
+            (self.min_latency(), self.mean_latency(), self.max_latency())
+        {
+            writeln!(f, "latency: min {:?}, mean {:?}, max {:?}", min, mean, max)?;
+        }
+        match &self.failure {
+            None => writeln!(f, "result: passed"),
+            Some(failure) => {
+                write!(
+                    f,
+                    "result: failed at {:?}: {}",
+                    failure.stage, failure.detail
+                )?;
+                match &failure.error {
+                    Some(err) => writeln!(f, " ({})", err),
+                    None => writeln!(f),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the frame transmitted by the self test. Every frame is a tester present request
+/// that suppresses the response, so ECUs that receive it don't change state.
+fn test_frame(protocol: Protocol) -> PassThruMsg {
+    let mut msg = match protocol.base() {
+        Protocol::ISO15765 => {
+            PassThruMsg::new_isotp(TEST_CAN_ID, &[0x3E, 0x80]).tx_flags(TxFlags::ISO15765_FRAME_PAD)
+        }
+        _ if is_can_protocol(protocol as u32) => {
+            PassThruMsg::new_can(TEST_CAN_ID, &[0x02, 0x3E, 0x80, 0, 0, 0, 0, 0])
+        }
+        // A functionally addressed ISO 14230 header followed by the request
+        _ => {
+            let mut msg = PassThruMsg::new(protocol);
+            let data = [0xC2, 0x33, 0xF1, 0x3E, 0x80];
+            msg.data[..data.len()].copy_from_slice(&data);
+            msg.data_size = data.len() as u32;
+            msg
+        }
+    };
+    msg.protocol_id = protocol as u32;
+    msg
+}
+
+impl<'a> Device<'a> {
+    /// Checks that the device can transmit on `protocol` by connecting a channel with loopback
+    /// enabled, writing a few frames and waiting for their echoes. The report tells which step
+    /// failed and contains the round-trip latencies and the versions of the device.
+    ///
+    /// The frames are tester present requests that suppress the response, sent functionally on
+    /// ID `0x7DF` on CAN. Most devices only echo CAN frames that another node acknowledged, so
+    /// connect the device to a vehicle or a bench setup for CAN protocols.
+    ///
+    /// Failures of the test are reported in the returned report. Returns an error only if the
+    /// device is no longer usable because it was unplugged.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{Interface, Protocol};
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let report = device.self_test(Protocol::ISO15765, 500000).unwrap();
+    /// print!("{}", report);
+    /// assert!(report.passed());
+    /// ```
+    pub fn self_test(&self, protocol: Protocol, baud: u32) -> Result<SelfTestReport, Error> {
+        let version = match self.read_version() {
+            Err(err) if err.is_disconnect() => return Err(err),
+            version => version,
+        };
+        let mut report = SelfTestReport {
+            protocol,
+            baudrate: baud,
+            version,
+            frames_sent: 0,
+            frames_echoed: 0,
+            latencies: Vec::new(),
+            failure: None,
+        };
+
+        let channel = match self.connect(protocol, ConnectFlags::NONE, baud) {
+            Ok(channel) => channel,
+            Err(err) => {
+                let detail = format!("could not connect a {:?} channel", protocol);
+                report.fail(SelfTestStage::Connect, Some(err), detail);
+                return Ok(report);
+            }
+        };
+        if let Err(err) = channel.set_loopback(true) {
+            let detail = "could not enable loopback".to_string();
+            report.fail(SelfTestStage::Config, Some(err), detail);
+            return Ok(report);
+        }
+        let filter = if protocol.base() == Protocol::ISO15765 {
+            channel.start_flow_filter(TEST_CAN_ID, TEST_RESPONSE_ID, false)
+        } else {
+            channel.start_pass_all_filter()
+        };
+        if let Err(err) = filter {
+            let detail = "could not install the filter".to_string();
+            report.fail(SelfTestStage::Filter, Some(err), detail);
+            return Ok(report);
+        }
+
+        let frame = test_frame(protocol);
+        let mut last_timestamp = None;
+        for n in 1..=SELF_TEST_FRAMES {
+            let started = Instant::now();
+            let confirmation = match channel.write_confirmed(&frame, ECHO_TIMEOUT) {
+                Ok(confirmation) => confirmation,
+                Err(Error::NotConfirmed { written: true }) => {
+                    report.frames_sent += 1;
+                    let detail = format!(
+                        "no matching echo of frame {} of {} within {:?}",
+                        n, SELF_TEST_FRAMES, ECHO_TIMEOUT
+                    );
+                    report.fail(SelfTestStage::Echo, None, detail);
+                    return Ok(report);
+                }
+                Err(err) => {
+                    let detail = format!("could not write frame {} of {}", n, SELF_TEST_FRAMES);
+                    report.fail(SelfTestStage::Write, Some(err), detail);
+                    return Ok(report);
+                }
+            };
+            report.frames_sent += 1;
+            report.frames_echoed += 1;
+            report.latencies.push(started.elapsed());
+
+            // The timestamp counter may wrap, but not within a few frames
+            if let Some(last) = last_timestamp.filter(|&last| confirmation.timestamp < last) {
+                let detail = format!(
+                    "frame {} was echoed at {}us, before the previous frame at {}us",
+                    n, confirmation.timestamp, last
+                );
+                report.fail(SelfTestStage::Echo, None, detail);
+                return Ok(report);
+            }
+            last_timestamp = Some(confirmation.timestamp);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockChannel, MockDevice};
+
+    fn run(device: MockDevice, protocol: Protocol) -> SelfTestReport {
+        mock::set_next_device(device);
+        mock::open().self_test(protocol, 500000).unwrap()
+    }
+
+    fn with_channel(new_channel: MockChannel) -> MockDevice {
+        MockDevice {
+            new_channel,
+            ..MockDevice::default()
+        }
+    }
+
+    fn stage(report: &SelfTestReport) -> Option<SelfTestStage> {
+        report.failure.as_ref().map(|failure| failure.stage)
+    }
+
+    #[test]
+    fn passes_with_echoes() {
+        for protocol in [Protocol::CAN, Protocol::ISO15765] {
+            let report = run(MockDevice::default(), protocol);
+            assert!(report.passed(), "{}", report);
+            assert_eq!(report.frames_sent, SELF_TEST_FRAMES);
+            assert_eq!(report.frames_echoed, SELF_TEST_FRAMES);
+            assert_eq!(report.latencies.len(), SELF_TEST_FRAMES);
+            assert!(report.version.is_ok());
+            assert!(report.to_string().contains("result: passed"));
+        }
+    }
+
+    #[test]
+    fn failure_stages() {
+        let refused = MockDevice {
+            refused_protocols: vec![Protocol::CAN as u32],
+            ..MockDevice::default()
+        };
+        let report = run(refused, Protocol::CAN);
+        assert_eq!(stage(&report), Some(SelfTestStage::Connect));
+        assert!(matches!(
+            report.failure.unwrap().error.unwrap().inner(),
+            Error::InvalidProtocolId
+        ));
+
+        let report = run(
+            with_channel(MockChannel {
+                unknown_configs: vec![0x03],
+                ..MockChannel::default()
+            }),
+            Protocol::CAN,
+        );
+        assert_eq!(stage(&report), Some(SelfTestStage::Config));
+
+        let report = run(
+            with_channel(MockChannel {
+                filter_limit: Some(0),
+                ..MockChannel::default()
+            }),
+            Protocol::ISO15765,
+        );
+        assert_eq!(stage(&report), Some(SelfTestStage::Filter));
+        assert_eq!(report.frames_sent, 0);
+
+        let report = run(
+            with_channel(MockChannel {
+                write_result: Some((0, 0x07)),
+                ..MockChannel::default()
+            }),
+            Protocol::CAN,
+        );
+        assert_eq!(stage(&report), Some(SelfTestStage::Write));
+        assert_eq!(report.frames_sent, 0);
+
+        let report = run(
+            with_channel(MockChannel {
+                drop_echoes: true,
+                ..MockChannel::default()
+            }),
+            Protocol::CAN,
+        );
+        let failure = report.failure.as_ref().unwrap();
+        assert_eq!(failure.stage, SelfTestStage::Echo);
+        assert!(failure.error.is_none());
+        assert_eq!((report.frames_sent, report.frames_echoed), (1, 0));
+        assert!(report.to_string().contains("result: failed at Echo"));
+    }
+
+    #[test]
+    fn lost_device_is_an_error() {
+        mock::set_next_device(MockDevice {
+            fail: Some(0x08),
+            ..MockDevice::default()
+        });
+        let device = mock::open();
+        assert!(matches!(
+            device.self_test(Protocol::CAN, 500000),
+            Err(Error::DeviceNotConnected)
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+//! Per-channel traffic statistics
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{BusErrorKind, Channel, Error, PassThruMsg};
+
+/// A snapshot of the traffic on a channel. Returned by [`Channel::stats`].
+#[derive(Clone, Debug, Default)]
+pub struct ChannelStats {
+    /// Messages read from the device
+    pub messages_read: u64,
+    /// Messages written to the device
+    pub messages_written: u64,
+    /// Bytes of message data read from the device
+    pub bytes_read: u64,
+    /// Bytes of message data written to the device
+    pub bytes_written: u64,
+    /// Reads and writes that timed out
+    pub timeouts: u64,
+    /// Reads and writes that failed with an error other than a timeout or an empty receive buffer
+    pub errors: u64,
+    /// Messages read with an error indication. See [`BusError`](crate::BusError).
+    pub bus_errors: u64,
+    /// The time messages were last read or written
+    pub last_activity: Option<Instant>,
+}
+
+/// Traffic counters updated by the read and write wrappers
+pub(crate) struct StatsCounters {
+    created: Instant,
+    messages_read: AtomicU64,
+    messages_written: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    timeouts: AtomicU64,
+    errors: AtomicU64,
+    bus_errors: AtomicU64,
+    /// Nanoseconds between `created` and the last activity plus one, or zero if there was no activity
+    last_activity: AtomicU64,
+}
+
+impl StatsCounters {
+    pub(crate) fn new() -> StatsCounters {
+        StatsCounters {
+            created: Instant::now(),
+            messages_read: AtomicU64::new(0),
+            messages_written: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            bus_errors: AtomicU64::new(0),
+            last_activity: AtomicU64::new(0),
+        }
+    }
+
+    /// Records the result of `PassThruReadMsgs`. `msgs` are the messages that were read.
+    pub(crate) fn record_read(&self, msgs: &[PassThruMsg], code: i32) {
+        self.record(&self.messages_read, &self.bytes_read, msgs, code);
+        let bus_errors = msgs
+            .iter()
+            .filter(|msg| BusErrorKind::of(msg).is_some())
+            .count();
+        if bus_errors > 0 {
+            self.bus_errors
+                .fetch_add(bus_errors as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Records the result of `PassThruWriteMsgs`. `msgs` are the messages that were written.
+    pub(crate) fn record_write(&self, msgs: &[PassThruMsg], code: i32) {
+        self.record(&self.messages_written, &self.bytes_written, msgs, code);
+    }
+
+    fn record(&self, messages: &AtomicU64, bytes: &AtomicU64, msgs: &[PassThruMsg], code: i32) {
+        match Error::from_code(code) {
+            Error::NoError | Error::BufferEmpty => {}
+            Error::Timeout => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if msgs.is_empty() {
+            return;
+        }
+        let size: u64 = msgs.iter().map(|msg| msg.data_size as u64).sum();
+        messages.fetch_add(msgs.len() as u64, Ordering::Relaxed);
+        bytes.fetch_add(size, Ordering::Relaxed);
+        let elapsed = self.created.elapsed().as_nanos() as u64;
+        self.last_activity.store(elapsed + 1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> ChannelStats {
+        let last_activity = match self.last_activity.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(self.created + Duration::from_nanos(nanos - 1)),
+        };
+        ChannelStats {
+            messages_read: self.messages_read.load(Ordering::Relaxed),
+            messages_written: self.messages_written.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bus_errors: self.bus_errors.load(Ordering::Relaxed),
+            last_activity,
+        }
+    }
+
+    fn reset(&self) {
+        for counter in &[
+            &self.messages_read,
+            &self.messages_written,
+            &self.bytes_read,
+            &self.bytes_written,
+            &self.timeouts,
+            &self.errors,
+            &self.bus_errors,
+            &self.last_activity,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<'a> Channel<'a> {
+    /// Returns the traffic statistics of the channel
+    pub fn stats(&self) -> ChannelStats {
+        self.stats.snapshot()
+    }
+
+    /// Resets all traffic statistics to zero
+    pub fn reset_stats(&self) {
+        self.stats.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mock, PassThruMsg, RxStatus};
+    use std::time::Duration;
+
+    #[test]
+    fn counts_traffic() {
+        let channel = mock::can_channel();
+        let id = channel.id();
+        let stats = channel.stats();
+        assert_eq!(stats.messages_read + stats.messages_written, 0);
+        assert!(stats.last_activity.is_none());
+
+        let mut msgs = [PassThruMsg::new_can(0x7DF, &[0x02, 0x01]); 2];
+        assert_eq!(channel.write(&mut msgs, Duration::ZERO).unwrap(), 2);
+
+        mock::push_rx(id, PassThruMsg::new_can(0x7E8, &[0x41]));
+        let mut brk = PassThruMsg::new_can(0x7E9, &[]);
+        brk.rx_status = RxStatus::RX_BREAK.bits();
+        mock::push_rx(id, brk);
+        // Two messages before the timeout count as a timeout too
+        let mut buf = [PassThruMsg::default(); 4];
+        assert_eq!(
+            channel.read(&mut buf, Duration::from_millis(10)).unwrap(),
+            2
+        );
+        // An empty receive queue isn't an error
+        assert!(channel.read(&mut buf, Duration::ZERO).is_err());
+        mock::with_channel(id, |channel| channel.read_error = Some(0x07));
+        assert!(channel.read(&mut buf, Duration::ZERO).is_err());
+
+        let stats = channel.stats();
+        assert_eq!(stats.messages_written, 2);
+        assert_eq!(stats.bytes_written, 12);
+        assert_eq!(stats.messages_read, 2);
+        assert_eq!(stats.bytes_read, 9);
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.bus_errors, 1);
+        assert!(stats.last_activity.is_some());
+
+        channel.reset_stats();
+        let stats = channel.stats();
+        assert_eq!(stats.messages_written + stats.errors + stats.bus_errors, 0);
+        assert!(stats.last_activity.is_none());
+    }
+}
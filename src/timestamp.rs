@@ -0,0 +1,152 @@
+//! Extending 32-bit message timestamps to 64 bits
+
+use std::time::Duration;
+
+use crate::{Channel, Error, PassThruMsg};
+
+/// Turns the 32-bit microsecond timestamps of messages, which wrap about every 71 minutes, into
+/// monotonically increasing 64-bit timestamps.
+///
+/// A timestamp that is behind the latest one by no more than the out-of-order window belongs to a
+/// message delivered out of order. Any other timestamp is taken as time moving forward, wrapping
+/// if it is smaller than the latest one, so gaps of up to a full wrap minus the window between
+/// messages are handled.
+#[derive(Copy, Clone, Debug)]
+pub struct TimestampTracker {
+    /// The latest raw timestamp and its extended value
+    last: Option<(u32, u64)>,
+    window: u32,
+}
+
+impl Default for TimestampTracker {
+    fn default() -> TimestampTracker {
+        // One second
+        TimestampTracker::with_window(1_000_000)
+    }
+}
+
+impl TimestampTracker {
+    /// Creates a tracker with an out-of-order window of one second
+    pub fn new() -> TimestampTracker {
+        TimestampTracker::default()
+    }
+
+    /// Creates a tracker with the given out-of-order window in microseconds
+    pub fn with_window(window: u32) -> TimestampTracker {
+        TimestampTracker { last: None, window }
+    }
+
+    /// Returns the 64-bit timestamp of a raw timestamp. Timestamps must be passed in the order the
+    /// messages were received.
+    pub fn extend(&mut self, raw: u32) -> u64 {
+        let (last, extended) = match self.last {
+            Some(last) => last,
+            None => {
+                self.last = Some((raw, raw as u64));
+                return raw as u64;
+            }
+        };
+        match last.wrapping_sub(raw) {
+            behind if behind != 0 && behind <= self.window => {
+                extended.saturating_sub(behind as u64)
+            }
+            _ => {
+                let extended = extended + raw.wrapping_sub(last) as u64;
+                self.last = Some((raw, extended));
+                extended
+            }
+        }
+    }
+
+    /// Forgets all timestamps seen so far
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// A message with a 64-bit timestamp. Returned by [`Channel::read_msgs_timed`].
+#[derive(Debug)]
+pub struct TimedMsg {
+    pub msg: PassThruMsg,
+    /// The timestamp of the message in microseconds
+    pub extended_timestamp: u64,
+}
+
+impl<'a> Channel<'a> {
+    /// Reads up to `max` messages like [`Channel::read_msgs`] and extends their timestamps with
+    /// `tracker`. Use the same tracker for every read from the channel.
+    pub fn read_msgs_timed(
+        &self,
+        max: usize,
+        timeout: Duration,
+        tracker: &mut TimestampTracker,
+    ) -> Result<Vec<TimedMsg>, Error> {
+        let msgs = self.read_msgs(max, timeout)?;
+        Ok(msgs
+            .into_iter()
+            .map(|msg| TimedMsg {
+                extended_timestamp: tracker.extend(msg.timestamp),
+                msg,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WRAP: u64 = 1 << 32;
+
+    #[test]
+    fn wraps() {
+        let mut tracker = TimestampTracker::new();
+        assert_eq!(tracker.extend(u32::MAX - 10), u32::MAX as u64 - 10);
+        assert_eq!(tracker.extend(5), WRAP + 5);
+        assert_eq!(tracker.extend(1 << 31), WRAP + (1 << 31));
+        assert_eq!(tracker.extend(u32::MAX - 10), 2 * WRAP - 11);
+        assert_eq!(tracker.extend(20), 2 * WRAP + 20);
+
+        tracker.reset();
+        assert_eq!(tracker.extend(20), 20);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut tracker = TimestampTracker::with_window(1000);
+        assert_eq!(tracker.extend(5000), 5000);
+        assert_eq!(tracker.extend(4500), 4500);
+        // The latest timestamp doesn't move back
+        assert_eq!(tracker.extend(4800), 4800);
+        assert_eq!(tracker.extend(5100), 5100);
+
+        // Just behind a wrap
+        let mut tracker = TimestampTracker::with_window(1000);
+        tracker.extend(u32::MAX - 100);
+        assert_eq!(tracker.extend(100), WRAP + 100);
+        assert_eq!(tracker.extend(u32::MAX - 50), WRAP - 51);
+        assert_eq!(tracker.extend(200), WRAP + 200);
+
+        // Before the first timestamp seen
+        let mut tracker = TimestampTracker::with_window(1000);
+        tracker.extend(100);
+        assert_eq!(tracker.extend(u32::MAX - 100), 0);
+    }
+
+    #[test]
+    fn long_gaps() {
+        let mut tracker = TimestampTracker::new();
+        // 40 minutes without messages
+        let gap = 40 * 60 * 1_000_000;
+        assert_eq!(tracker.extend(1000), 1000);
+        assert_eq!(tracker.extend(1000 + gap), 1000 + gap as u64);
+        // And another 40 minutes, wrapping in between
+        let raw = (1000 + gap).wrapping_add(gap);
+        assert_eq!(tracker.extend(raw), 1000 + 2 * gap as u64);
+
+        // Drops larger than the window are wraps
+        let mut tracker = TimestampTracker::with_window(1000);
+        tracker.extend(5000);
+        assert_eq!(tracker.extend(3000), WRAP + 3000);
+    }
+}
@@ -0,0 +1,266 @@
+//! Bookkeeping of the filters and periodic messages created through a channel, used to restore them
+//! with [`Channel::reconnect`] and to stop them when the channel is disconnected
+
+use std::time::Instant;
+
+use crate::{Channel, ChannelId, Error, FilterId, MessageId, PassThruMsg};
+
+/// A filter started with [`Channel::start_message_filter`]
+pub(crate) struct TrackedFilter {
+    pub(crate) handle: FilterId,
+    pub(crate) driver_id: u32,
+    pub(crate) filter_type: u32,
+    pub(crate) mask: Option<PassThruMsg>,
+    pub(crate) pattern: Option<PassThruMsg>,
+    pub(crate) flow_control: Option<PassThruMsg>,
+}
+
+/// A periodic message started with [`Channel::start_periodic_message`]
+pub(crate) struct TrackedPeriodic {
+    pub(crate) handle: MessageId,
+    pub(crate) driver_id: u32,
+    pub(crate) msg: PassThruMsg,
+    pub(crate) interval: u32,
+    pub(crate) created: Instant,
+}
+
+/// The filters and periodic messages of a channel. Handles given out by the channel stay the same
+/// when the driver assigns new IDs after reconnecting.
+#[derive(Default)]
+pub(crate) struct Tracked {
+    next_handle: u32,
+    pub(crate) filters: Vec<TrackedFilter>,
+    pub(crate) periodic: Vec<TrackedPeriodic>,
+}
+
+impl Tracked {
+    pub(crate) fn next_handle(&mut self) -> u32 {
+        let handle = self.next_handle;
+        self.next_handle = self.next_handle.wrapping_add(1);
+        handle
+    }
+
+    pub(crate) fn filter_id(&self, handle: FilterId) -> Option<u32> {
+        self.filters
+            .iter()
+            .find(|filter| filter.handle == handle)
+            .map(|filter| filter.driver_id)
+    }
+
+    pub(crate) fn periodic_id(&self, handle: MessageId) -> Option<u32> {
+        self.periodic
+            .iter()
+            .find(|periodic| periodic.handle == handle)
+            .map(|periodic| periodic.driver_id)
+    }
+}
+
+/// The filters and periodic messages that could not be restored by [`Channel::reconnect`].
+/// Their handles are no longer valid.
+#[derive(Debug, Default)]
+pub struct ReconnectReport {
+    pub filters: Vec<(FilterId, Error)>,
+    pub periodic_messages: Vec<(MessageId, Error)>,
+}
+
+impl ReconnectReport {
+    /// Returns true if everything was restored
+    pub fn is_complete(&self) -> bool {
+        self.filters.is_empty() && self.periodic_messages.is_empty()
+    }
+}
+
+impl<'a> Channel<'a> {
+    /// Forgets the filters and periodic messages started through the channel, so that they aren't
+    /// stopped when the channel is disconnected or restored by [`Channel::reconnect`]. The driver
+    /// still removes them when the channel is disconnected.
+    ///
+    /// The handles of the forgotten filters and periodic messages can no longer be stopped
+    /// individually; use [`Channel::clear_message_filters`] and [`Channel::clear_periodic_messages`].
+    pub fn clear_tracked(&self) {
+        let mut tracked = self.tracked.lock().unwrap();
+        tracked.filters.clear();
+        tracked.periodic.clear();
+    }
+
+    /// Stops the tracked periodic messages and filters before disconnecting. Errors are ignored,
+    /// since the driver removes them on disconnect anyway.
+    pub(crate) fn stop_tracked(&self) {
+        let mut tracked = self.tracked.lock().unwrap();
+        for periodic in tracked.periodic.drain(..) {
+            unsafe {
+                self.device.interface.call(|| {
+                    (self.device.interface.c_pass_thru_stop_periodic_msg)(
+                        self.id.0,
+                        periodic.driver_id,
+                    )
+                })
+            };
+        }
+        for filter in tracked.filters.drain(..) {
+            unsafe {
+                self.device.interface.call(|| {
+                    (self.device.interface.c_pass_thru_stop_msg_filter)(self.id.0, filter.driver_id)
+                })
+            };
+        }
+    }
+
+    /// Disconnects the channel and connects it again with the protocol, flags and baud rate it was
+    /// created with. Use this to recover after the device was briefly disconnected.
+    ///
+    /// Errors from disconnecting are ignored. Filters and periodic messages started through the
+    /// channel are started again and keep their handles. The ones that could not be restored are
+    /// returned in the report.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{ConnectFlags, Interface, Protocol};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let mut channel = device.connect(Protocol::CAN, ConnectFlags::NONE, 500000).unwrap();
+    ///
+    /// if let Err(j2534::Error::DeviceNotConnected) = channel.read_msgs(16, Duration::from_secs(1)) {
+    ///     let report = channel.reconnect().unwrap();
+    ///     if !report.is_complete() {
+    ///         println!("could not restore {:?}", report);
+    ///     }
+    /// }
+    /// ```
+    pub fn reconnect(&mut self) -> Result<ReconnectReport, Error> {
+        if !self.disconnected {
+            unsafe {
+                self.device
+                    .interface
+                    .call(|| (self.device.interface.c_pass_thru_disconnect)(self.id.0))
+            };
+            self.device.unregister_channel(self.id.0);
+            // Don't disconnect the old ID again if connecting fails
+            self.disconnected = true;
+        }
+
+        let mut id: u32 = 0;
+        let res = unsafe {
+            self.device.interface.call(|| {
+                (self.device.interface.c_pass_thru_connect)(
+                    self.device.id.0,
+                    self.protocol_id,
+                    self.flags.bits,
+                    self.baudrate(),
+                    &mut id as *mut u32,
+                )
+            })
+        };
+        if res != 0 {
+            return Err(self.device.interface.error(res));
+        }
+        self.id = ChannelId(id);
+        self.disconnected = false;
+        self.device
+            .register_channel(id, self.protocol_id, self.flags, self.baudrate());
+        // Anything queued on the old channel is gone
+        *self.pending_writes.get_mut() = 0;
+
+        let mut report = ReconnectReport::default();
+        let mut tracked = self.tracked.lock().unwrap();
+        let mut filters = std::mem::take(&mut tracked.filters);
+        filters.retain_mut(|filter| {
+            match self.driver_start_filter(
+                filter.filter_type,
+                filter.mask.as_ref(),
+                filter.pattern.as_ref(),
+                filter.flow_control.as_ref(),
+            ) {
+                Ok(driver_id) => {
+                    filter.driver_id = driver_id;
+                    true
+                }
+                Err(err) => {
+                    report.filters.push((filter.handle, err));
+                    false
+                }
+            }
+        });
+        tracked.filters = filters;
+
+        let mut periodic = std::mem::take(&mut tracked.periodic);
+        periodic.retain_mut(|periodic| {
+            match self.driver_start_periodic(&periodic.msg, periodic.interval) {
+                Ok(driver_id) => {
+                    periodic.driver_id = driver_id;
+                    true
+                }
+                Err(err) => {
+                    report.periodic_messages.push((periodic.handle, err));
+                    false
+                }
+            }
+        });
+        tracked.periodic = periodic;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{mock, FilterType, PassThruMsg};
+
+    #[test]
+    fn reconnect_keeps_handles() {
+        let mut channel = mock::can_channel();
+        let mask = PassThruMsg::new_can(0, &[]);
+        let filter = channel
+            .start_message_filter(FilterType::Pass, Some(&mask), Some(&mask), None)
+            .unwrap();
+        let msg = PassThruMsg::new_can(0x7DF, &[0x01, 0x3E]);
+        let periodic = channel
+            .start_periodic_message(&msg, Duration::from_millis(100))
+            .unwrap();
+
+        let old_id = channel.id();
+        assert!(channel.reconnect().unwrap().is_complete());
+        assert_ne!(channel.id(), old_id);
+        assert_eq!(mock::channel(channel.id()).filters.len(), 1);
+        assert_eq!(mock::channel(channel.id()).periodic.len(), 1);
+
+        channel.stop_message_filter(filter).unwrap();
+        channel.stop_periodic_message(periodic).unwrap();
+        assert!(mock::channel(channel.id()).filters.is_empty());
+        assert!(mock::channel(channel.id()).periodic.is_empty());
+        assert!(channel.stop_message_filter(filter).is_err());
+    }
+
+    #[test]
+    fn stop_by_driver_id() {
+        let mut channel = mock::can_channel();
+        let mask = PassThruMsg::new_can(0, &[]);
+        channel
+            .start_message_filter(FilterType::Pass, Some(&mask), Some(&mask), None)
+            .unwrap();
+        // Started without the channel knowing
+        let untracked = channel
+            .driver_start_filter(FilterType::Pass as u32, Some(&mask), Some(&mask), None)
+            .unwrap();
+
+        channel.stop_message_filter_raw(untracked).unwrap();
+        let tracked = mock::channel(channel.id()).filters[0];
+        channel.stop_message_filter_raw(tracked).unwrap();
+        assert!(mock::channel(channel.id()).filters.is_empty());
+
+        // The stopped filter isn't started again
+        assert!(channel.reconnect().unwrap().is_complete());
+        assert!(mock::channel(channel.id()).filters.is_empty());
+
+        let msg = PassThruMsg::new_can(0x7DF, &[0x01, 0x3E]);
+        channel
+            .start_periodic_message(&msg, Duration::from_millis(100))
+            .unwrap();
+        let driver_id = mock::channel(channel.id()).periodic[0];
+        channel.stop_periodic_message_raw(driver_id).unwrap();
+        assert!(channel.stop_periodic_message_raw(driver_id).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! Background battery voltage supervision created by [`Device::monitor_vbatt`]
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{Scope, ScopedJoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::Device;
+
+/// The most readings kept for [`VbattMonitor::history`]. The oldest readings are dropped first.
+const MAX_VBATT_HISTORY: usize = 600;
+
+/// When a [`VbattMonitor`] trips
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VbattThresholds {
+    /// The monitor trips when a reading is below this voltage in millivolts
+    pub min_millivolts: u32,
+}
+
+impl Default for VbattThresholds {
+    /// Trips below 11.5 V, a common lower limit for flashing ECUs
+    fn default() -> VbattThresholds {
+        VbattThresholds {
+            min_millivolts: 11500,
+        }
+    }
+}
+
+/// A battery voltage read by a [`VbattMonitor`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VbattReading {
+    pub millivolts: u32,
+    /// When the voltage was read
+    pub time: Instant,
+}
+
+#[derive(Default)]
+struct History {
+    readings: VecDeque<VbattReading>,
+}
+
+/// A handle to a battery voltage monitor created by [`Device::monitor_vbatt`]. The monitor is
+/// stopped when the handle is dropped.
+pub struct VbattMonitor<'scope> {
+    history: Arc<Mutex<History>>,
+    tripped: Arc<AtomicBool>,
+    errors: Arc<AtomicUsize>,
+    /// Dropped to wake the monitor thread and stop it
+    stop: Option<Sender<()>>,
+    thread: Option<ScopedJoinHandle<'scope, ()>>,
+}
+
+impl<'scope> VbattMonitor<'scope> {
+    /// Returns the most recent reading, or `None` if the voltage hasn't been read successfully yet
+    pub fn latest(&self) -> Option<VbattReading> {
+        self.history.lock().unwrap().readings.back().copied()
+    }
+
+    /// Returns the readings kept so far, oldest first
+    pub fn history(&self) -> Vec<VbattReading> {
+        self.history
+            .lock()
+            .unwrap()
+            .readings
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Returns true if a reading was below the minimum since the monitor was started or
+    /// [`VbattMonitor::reset`] was called. Stays true after the voltage recovers.
+    pub fn tripped(&self) -> bool {
+        self.tripped.load(Ordering::Relaxed)
+    }
+
+    /// Clears the tripped flag
+    pub fn reset(&self) {
+        self.tripped.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns the amount of reads that failed. The monitor keeps running after an error.
+    pub fn errors(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Stops the monitor and waits for its thread to exit
+    pub fn stop(mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<'scope> Drop for VbattMonitor<'scope> {
+    fn drop(&mut self) {
+        // Wakes the thread, which exits. The scope joins it.
+        self.stop.take();
+    }
+}
+
+impl<'a> Device<'a> {
+    /// Starts a thread in `scope` that reads the battery voltage every `interval`, keeps a
+    /// history of the readings and trips when a reading is below the minimum of `thresholds`.
+    ///
+    /// The reads are serialized with the other device-level calls made while the monitor runs,
+    /// like [`Device::read_programming_voltage`] or [`Device::connect`], but not with calls on
+    /// channels, like reads, writes or channel ioctls. Drivers that can't handle those at the
+    /// same time as a device-level ioctl need
+    /// [`Interface::serialize_calls`](crate::Interface::serialize_calls). Failed reads are
+    /// counted and retried at the next interval.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::{Interface, VbattThresholds};
+    /// use std::time::Duration;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    ///
+    /// std::thread::scope(|s| {
+    ///     let monitor = device.monitor_vbatt(s, Duration::from_millis(500), VbattThresholds::default());
+    ///     // Flash the ECU, checking the monitor between blocks
+    ///     if monitor.tripped() {
+    ///         println!("battery voltage dropped to {:?}", monitor.latest());
+    ///     }
+    ///     monitor.stop();
+    /// });
+    /// ```
+    pub fn monitor_vbatt<'scope, 'env>(
+        &'env self,
+        scope: &'scope Scope<'scope, 'env>,
+        interval: Duration,
+        thresholds: VbattThresholds,
+    ) -> VbattMonitor<'scope> {
+        let history = Arc::new(Mutex::new(History::default()));
+        let tripped = Arc::new(AtomicBool::new(false));
+        let errors = Arc::new(AtomicUsize::new(0));
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread = {
+            let history = history.clone();
+            let tripped = tripped.clone();
+            let errors = errors.clone();
+            scope.spawn(move || {
+                loop {
+                    match self.read_battery_voltage() {
+                        Ok(millivolts) => {
+                            if millivolts < thresholds.min_millivolts {
+                                tripped.store(true, Ordering::Relaxed);
+                            }
+                            let mut history = history.lock().unwrap();
+                            if history.readings.len() == MAX_VBATT_HISTORY {
+                                history.readings.pop_front();
+                            }
+                            history.readings.push_back(VbattReading {
+                                millivolts,
+                                time: Instant::now(),
+                            });
+                        }
+                        // The device is gone, so every following read would fail too
+                        Err(err) if err.is_disconnect() => {
+                            errors.fetch_add(1, Ordering::Relaxed);
+                            return;
+                        }
+                        Err(_) => {
+                            errors.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    match stopped.recv_timeout(interval) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        // The handle was dropped or stopped
+                        _ => return,
+                    }
+                }
+            })
+        };
+
+        VbattMonitor {
+            history,
+            tripped,
+            errors,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+    use std::thread;
+
+    /// Waits up to two seconds for `condition` to become true
+    fn wait_until(mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !condition() {
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for the monitor"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn calls(device: &Device) -> usize {
+        mock::device(device.id()).calls
+    }
+
+    #[test]
+    fn trips_below_minimum() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.vbatt = 12600);
+        thread::scope(|s| {
+            let thresholds = VbattThresholds {
+                min_millivolts: 12000,
+            };
+            let monitor = device.monitor_vbatt(s, Duration::from_millis(1), thresholds);
+            wait_until(|| monitor.history().len() >= 2);
+            assert!(!monitor.tripped());
+            assert_eq!(monitor.latest().unwrap().millivolts, 12600);
+
+            mock::with_device(device.id(), |device| device.vbatt = 11900);
+            wait_until(|| monitor.tripped());
+            assert_eq!(monitor.latest().unwrap().millivolts, 11900);
+
+            // The flag stays set until it's reset
+            mock::with_device(device.id(), |device| device.vbatt = 12000);
+            wait_until(|| monitor.latest().unwrap().millivolts == 12000);
+            assert!(monitor.tripped());
+            monitor.reset();
+            let len = monitor.history().len();
+            wait_until(|| monitor.history().len() >= len + 2);
+            assert!(!monitor.tripped());
+            monitor.stop();
+        });
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.vbatt = 12600);
+        thread::scope(|s| {
+            let monitor = device.monitor_vbatt(s, Duration::ZERO, VbattThresholds::default());
+            wait_until(|| calls(device) > MAX_VBATT_HISTORY + 50);
+            let history = monitor.history();
+            assert_eq!(history.len(), MAX_VBATT_HISTORY);
+            // Oldest first
+            assert!(history.windows(2).all(|pair| pair[0].time <= pair[1].time));
+            assert_eq!(monitor.latest(), history.last().copied());
+        });
+    }
+
+    #[test]
+    fn survives_transient_errors() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.vbatt_error = Some(0x07));
+        thread::scope(|s| {
+            let monitor =
+                device.monitor_vbatt(s, Duration::from_millis(1), VbattThresholds::default());
+            wait_until(|| monitor.errors() >= 3);
+            assert!(monitor.latest().is_none());
+
+            mock::with_device(device.id(), |device| {
+                device.vbatt_error = None;
+                device.vbatt = 12600;
+            });
+            wait_until(|| monitor.latest().is_some());
+            assert_eq!(monitor.latest().unwrap().millivolts, 12600);
+
+            // A lost device stops the monitor
+            let before = monitor.errors();
+            mock::with_device(device.id(), |device| device.vbatt_error = Some(0x08));
+            wait_until(|| monitor.errors() > before);
+            thread::sleep(Duration::from_millis(20));
+            let (errors, reads) = (monitor.errors(), calls(device));
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!((monitor.errors(), calls(device)), (errors, reads));
+        });
+    }
+
+    #[test]
+    fn stops_on_drop() {
+        let device = mock::open();
+        mock::with_device(device.id(), |device| device.vbatt = 12600);
+        thread::scope(|s| {
+            let monitor =
+                device.monitor_vbatt(s, Duration::from_millis(1), VbattThresholds::default());
+            wait_until(|| monitor.history().len() >= 2);
+            drop(monitor);
+        });
+        // The scope joined the thread, so there are no more reads
+        let stopped = calls(device);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(calls(device), stopped);
+
+        // Stopping joins the thread right away
+        thread::scope(|s| {
+            let monitor =
+                device.monitor_vbatt(s, Duration::from_secs(60), VbattThresholds::default());
+            wait_until(|| monitor.latest().is_some());
+            let started = Instant::now();
+            monitor.stop();
+            assert!(started.elapsed() < Duration::from_secs(1));
+            let stopped = calls(device);
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(calls(device), stopped);
+        });
+    }
+}
@@ -0,0 +1,200 @@
+//! Parsing of the version strings returned by `PassThruReadVersion`
+
+use std::cmp::Ordering;
+use std::ffi::CStr;
+use std::fmt;
+
+use crate::VersionInfo;
+
+/// A version number parsed from a vendor-defined version string, like `04.04` or
+/// `1.17.4877 (beta)`. Returned by [`VersionInfo::firmware`], [`VersionInfo::dll`] and
+/// [`VersionInfo::api`].
+///
+/// Versions are compared by their numbers only, so `"1.2"` and `"v1.2.0"` are equal.
+#[derive(Clone, Debug)]
+pub struct ParsedVersion {
+    pub major: u32,
+    pub minor: u32,
+    /// The third number, or 0 if the version has only two
+    pub patch: u32,
+    /// The string the version was parsed from
+    pub raw: String,
+}
+
+impl ParsedVersion {
+    /// Parses the first version number in `s`. Numbers with dots are preferred over plain ones,
+    /// so `"CarDAQ 3 FW 2.1.7"` is parsed as 2.1.7. Returns `None` if `s` has no digits.
+    pub fn parse(s: &str) -> Option<ParsedVersion> {
+        let mut first = None;
+        let mut rest = s;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+            let candidate = &rest[start..];
+            let len = candidate
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(candidate.len());
+            let numbers: Vec<&str> = candidate[..len]
+                .split('.')
+                .take_while(|part| !part.is_empty())
+                .collect();
+            if numbers.len() > 1 {
+                return ParsedVersion::from_numbers(&numbers, s);
+            }
+            if first.is_none() {
+                first = Some(numbers);
+            }
+            rest = &candidate[len..];
+        }
+        ParsedVersion::from_numbers(&first?, s)
+    }
+
+    fn from_numbers(numbers: &[&str], raw: &str) -> Option<ParsedVersion> {
+        // Numbers too large for a u32 aren't versions
+        let number = |i: usize| match numbers.get(i) {
+            Some(n) => n.parse().ok(),
+            None => Some(0),
+        };
+        Some(ParsedVersion {
+            major: number(0)?,
+            minor: number(1)?,
+            patch: number(2)?,
+            raw: raw.to_string(),
+        })
+    }
+
+    fn numbers(&self) -> (u32, u32, u32) {
+        (self.major, self.minor, self.patch)
+    }
+}
+
+impl PartialEq for ParsedVersion {
+    fn eq(&self, other: &ParsedVersion) -> bool {
+        self.numbers() == other.numbers()
+    }
+}
+
+impl Eq for ParsedVersion {}
+
+impl PartialOrd for ParsedVersion {
+    fn partial_cmp(&self, other: &ParsedVersion) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ParsedVersion {
+    fn cmp(&self, other: &ParsedVersion) -> Ordering {
+        self.numbers().cmp(&other.numbers())
+    }
+}
+
+impl fmt::Display for ParsedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl VersionInfo {
+    /// Parses the firmware version. Returns `None` if it has no version number.
+    pub fn firmware(&self) -> Option<ParsedVersion> {
+        parse_c_str(&self.firmware_version)
+    }
+
+    /// Parses the version of the driver library
+    pub fn dll(&self) -> Option<ParsedVersion> {
+        parse_c_str(&self.dll_version)
+    }
+
+    /// Parses the version of the J2534 API the driver implements, like `04.04`
+    pub fn api(&self) -> Option<ParsedVersion> {
+        parse_c_str(&self.api_version)
+    }
+
+    /// Returns true if the driver implements J2534 API version `major.minor` or newer. Returns
+    /// false if the API version can't be parsed.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use j2534::Interface;
+    ///
+    /// let interface = Interface::new("C:\\j2534_driver.dll").unwrap();
+    /// let device = interface.open_any().unwrap();
+    /// let version = device.read_version().unwrap();
+    /// println!("{}", version);
+    /// if !version.api_is_at_least(4, 4) {
+    ///     println!("the driver predates J2534-1 v04.04");
+    /// }
+    /// ```
+    pub fn api_is_at_least(&self, major: u32, minor: u32) -> bool {
+        match self.api() {
+            Some(api) => (api.major, api.minor) >= (major, minor),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "firmware {}, library {}, API {}",
+            self.firmware_version.to_string_lossy(),
+            self.dll_version.to_string_lossy(),
+            self.api_version.to_string_lossy()
+        )
+    }
+}
+
+fn parse_c_str(s: &CStr) -> Option<ParsedVersion> {
+    ParsedVersion::parse(&s.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    fn numbers(s: &str) -> Option<(u32, u32, u32)> {
+        ParsedVersion::parse(s).map(|version| version.numbers())
+    }
+
+    #[test]
+    fn parse_corpus() {
+        for (s, expected) in [
+            ("04.04", Some((4, 4, 0))),
+            ("05.00", Some((5, 0, 0))),
+            ("v1.2.3", Some((1, 2, 3))),
+            ("1.17.4877 (beta)", Some((1, 17, 4877))),
+            ("CarDAQ 3 FW 2.1.7", Some((2, 1, 7))),
+            ("1.2.3.4", Some((1, 2, 3))),
+            ("Build 12", Some((12, 0, 0))),
+            ("3.", Some((3, 0, 0))),
+            ("", None),
+            ("unknown", None),
+            ("99999999999.1", None),
+        ] {
+            assert_eq!(numbers(s), expected, "{:?}", s);
+        }
+        assert_eq!(ParsedVersion::parse("v1.2").unwrap().raw, "v1.2");
+    }
+
+    #[test]
+    fn compare_numbers_only() {
+        let parse = |s| ParsedVersion::parse(s).unwrap();
+        assert_eq!(parse("1.2"), parse("v1.2.0"));
+        assert!(parse("04.04") < parse("05.00"));
+        assert!(parse("1.10") > parse("1.9"));
+        assert_eq!(parse("v1.2").to_string(), "1.2.0");
+    }
+
+    #[test]
+    fn api_version() {
+        let info = |api: &str| VersionInfo {
+            firmware_version: CString::new("1.0").unwrap(),
+            dll_version: CString::new("1.0").unwrap(),
+            api_version: CString::new(api).unwrap(),
+        };
+        assert!(info("04.04").api_is_at_least(4, 4));
+        assert!(info("05.00").api_is_at_least(4, 4));
+        assert!(!info("02.02").api_is_at_least(4, 4));
+        assert!(!info("none").api_is_at_least(0, 0));
+    }
+}